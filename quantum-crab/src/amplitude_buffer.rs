@@ -0,0 +1,790 @@
+//! Implements structure-of-arrays buffer of amplitudes, used by the state vector
+//! backend.
+//!
+//! Real and imaginary parts of amplitudes are stored in separate arrays, so that gate
+//! kernels can process several amplitudes at once with SIMD instructions. On `x86_64`,
+//! AVX kernels are selected at runtime if the processor supports them, otherwise
+//! portable scalar kernels are used.
+//!
+//! Amplitudes are stored either in double ([`f64`], the default) or in single
+//! ([`f32`]) precision, which halves the memory used by the buffer.
+//!
+//! ```
+//! use quantum_crab::{amplitude_buffer::AmplitudeBuffer, complex::Complex};
+//!
+//! let buffer = AmplitudeBuffer::<f32>::new(2);
+//! assert_eq!(buffer.len(), 4);
+//! assert_eq!(buffer.get(0), Complex::from(1));
+//! assert_eq!(buffer.to_amplitudes()[3], Complex::from(0));
+//! ```
+use crate::{complex::Complex, matrix::Matrix};
+use kernels::{GateCoefficients, Kernels, Lanes};
+use num::Float;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::fmt::Debug;
+
+/// Amount of amplitude pairs processed by a single task in multi-threaded kernels.
+#[cfg(feature = "parallel")]
+const CHUNK_SIZE: usize = 1 << 10;
+
+/// Floating point type of amplitudes in [`AmplitudeBuffer`], implemented for [`f32`]
+/// and [`f64`].
+pub trait Real: Float + Send + Sync + Debug + Kernels {}
+
+impl Real for f32 {}
+
+impl Real for f64 {}
+
+/// Amplitudes of the state vector, stored as separate arrays of real and imaginary
+/// parts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmplitudeBuffer<F: Real = f64> {
+    real: Vec<F>,
+    imag: Vec<F>,
+}
+
+impl<F: Real> AmplitudeBuffer<F> {
+    /// Constructs buffer of the state, where all qubits are `|0>`.
+    pub fn new(qubits: usize) -> AmplitudeBuffer<F> {
+        let mut real = vec![F::zero(); 1 << qubits];
+        real[0] = F::one();
+
+        AmplitudeBuffer {
+            imag: vec![F::zero(); real.len()],
+            real,
+        }
+    }
+
+    /// Constructs buffer from amplitudes, the amount of which must be a power of two.
+    pub fn from_amplitudes(amplitudes: &[Complex]) -> AmplitudeBuffer<F> {
+        assert!(
+            amplitudes.len().is_power_of_two(),
+            "Amount of amplitudes must be a power of two"
+        );
+
+        AmplitudeBuffer {
+            real: amplitudes
+                .iter()
+                .map(|amplitude| cast(amplitude.real))
+                .collect(),
+            imag: amplitudes
+                .iter()
+                .map(|amplitude| cast(amplitude.imag))
+                .collect(),
+        }
+    }
+
+    /// Resets the buffer to the basis state of `qubits` qubits with the index,
+    /// reusing the allocated memory.
+    pub(crate) fn reset(&mut self, qubits: usize, index: usize) {
+        for part in [&mut self.real, &mut self.imag] {
+            part.clear();
+            part.resize(1 << qubits, F::zero());
+        }
+        self.real[index] = F::one();
+    }
+
+    /// Amount of amplitudes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.real.len()
+    }
+
+    /// Checks if the buffer has no amplitudes, which is never the case.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.real.is_empty()
+    }
+
+    /// Returns amplitude of the basis state with the index.
+    #[inline]
+    pub fn get(&self, index: usize) -> Complex {
+        Complex::new(to_f64(self.real[index]), to_f64(self.imag[index]))
+    }
+
+    /// Returns all amplitudes.
+    pub fn to_amplitudes(&self) -> Vec<Complex> {
+        self.real
+            .iter()
+            .zip(&self.imag)
+            .map(|(real, imag)| Complex::new(to_f64(*real), to_f64(*imag)))
+            .collect()
+    }
+
+    /// Returns probabilities of all basis states.
+    #[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+    pub(crate) fn probabilities(&self, parallel: bool) -> Vec<f64> {
+        let probability = |(real, imag): (&F, &F)| to_f64(*real * *real + *imag * *imag);
+
+        #[cfg(feature = "parallel")]
+        if parallel {
+            return self
+                .real
+                .par_iter()
+                .zip(&self.imag)
+                .map(probability)
+                .collect();
+        }
+
+        self.real.iter().zip(&self.imag).map(probability).collect()
+    }
+
+    /// Measures the qubit, collapsing the state into the outcome and renormalizing it.
+    /// `random` is a uniformly distributed number in `[0, 1)`, the outcome is `1` if
+    /// it is below the probability of `|1>`.
+    pub(crate) fn measure(&mut self, qubit: usize, random: f64) -> u8 {
+        let mask = 1 << qubit;
+        assert!(mask < self.len(), "Invalid qubit {}", qubit);
+
+        let mut probabilities = [0f64; 2];
+        for (index, (real, imag)) in self.real.iter().zip(&self.imag).enumerate() {
+            probabilities[(index & mask != 0) as usize] += to_f64(*real * *real + *imag * *imag);
+        }
+
+        let outcome = random * (probabilities[0] + probabilities[1]) < probabilities[1];
+        let scale: F = cast(probabilities[outcome as usize].sqrt().recip());
+        for (index, (real, imag)) in self.real.iter_mut().zip(&mut self.imag).enumerate() {
+            let factor = if (index & mask != 0) == outcome {
+                scale
+            } else {
+                F::zero()
+            };
+            *real = *real * factor;
+            *imag = *imag * factor;
+        }
+
+        outcome as u8
+    }
+
+    #[inline]
+    fn lanes(&mut self) -> Lanes<'_, F> {
+        Lanes {
+            real: &mut self.real,
+            imag: &mut self.imag,
+        }
+    }
+
+    /// Applies single qubit gate with the 2x2 matrix to the `target` qubit, for basis
+    /// states where all qubits in the `controls` bit mask are `|1>`. If `parallel` is
+    /// set, amplitudes are updated in the current thread pool.
+    #[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+    pub(crate) fn apply_single_qubit_gate(
+        &mut self,
+        gate_matrix: &Matrix<Complex>,
+        controls: usize,
+        target: usize,
+        parallel: bool,
+    ) {
+        let gate = GateCoefficients::new(gate_matrix, 2);
+        let half = 1 << target;
+        assert!(2 * half <= self.len(), "Invalid target qubit {}", target);
+
+        // Every block of `2 * half` amplitudes consists of pairs of amplitudes, that
+        // differ only in the target qubit, so blocks are updated independently.
+        #[cfg(feature = "parallel")]
+        if parallel {
+            let size = half.min(CHUNK_SIZE);
+
+            self.lanes()
+                .par_chunks_mut(2 * half)
+                .enumerate()
+                .for_each(|(block, lanes)| {
+                    let (zeros, ones) = lanes.split_at_mut(half);
+
+                    zeros
+                        .par_chunks_mut(size)
+                        .zip(ones.par_chunks_mut(size))
+                        .enumerate()
+                        .for_each(|(chunk, (zeros, ones))| {
+                            F::pair_kernel(
+                                &gate,
+                                zeros,
+                                ones,
+                                block * 2 * half + chunk * size,
+                                controls,
+                            )
+                        });
+                });
+            return;
+        }
+
+        for (block, lanes) in self.lanes().chunks_mut(2 * half).enumerate() {
+            let (zeros, ones) = lanes.split_at_mut(half);
+            F::pair_kernel(&gate, zeros, ones, block * 2 * half, controls);
+        }
+    }
+
+    /// Applies two qubit gate with the 4x4 matrix, indexed by
+    /// `bit(qubit0) + 2 * bit(qubit1)`, to the qubits. If `parallel` is set,
+    /// amplitudes are updated in the current thread pool.
+    #[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+    pub(crate) fn apply_two_qubit_gate(
+        &mut self,
+        gate_matrix: &Matrix<Complex>,
+        qubit0: usize,
+        qubit1: usize,
+        parallel: bool,
+    ) {
+        assert_ne!(qubit0, qubit1, "Gate qubits must be different");
+        let (low, high) = (1 << qubit0.min(qubit1), 1 << qubit0.max(qubit1));
+        assert!(
+            2 * high <= self.len(),
+            "Invalid qubits {} and {}",
+            qubit0,
+            qubit1
+        );
+
+        // Kernels index amplitudes by `bit(low) + 2 * bit(high)`.
+        let mut gate = GateCoefficients::new(gate_matrix, 4);
+        if qubit0 > qubit1 {
+            gate = gate.permuted([0, 2, 1, 3]);
+        }
+
+        #[cfg(feature = "parallel")]
+        if parallel {
+            self.lanes().par_chunks_mut(2 * high).for_each(|lanes| {
+                let (zeros, ones) = lanes.split_at_mut(high);
+
+                zeros
+                    .par_chunks_mut(2 * low)
+                    .zip(ones.par_chunks_mut(2 * low))
+                    .for_each(|(zeros, ones)| {
+                        let (zero_zero, zero_one) = zeros.split_at_mut(low);
+                        let (one_zero, one_one) = ones.split_at_mut(low);
+                        F::quad_kernel(&gate, [zero_zero, zero_one, one_zero, one_one]);
+                    });
+            });
+            return;
+        }
+
+        for lanes in self.lanes().chunks_mut(2 * high) {
+            let (zeros, ones) = lanes.split_at_mut(high);
+
+            for (zeros, ones) in zeros.chunks_mut(2 * low).zip(ones.chunks_mut(2 * low)) {
+                let (zero_zero, zero_one) = zeros.split_at_mut(low);
+                let (one_zero, one_one) = ones.split_at_mut(low);
+                F::quad_kernel(&gate, [zero_zero, zero_one, one_zero, one_one]);
+            }
+        }
+    }
+}
+
+#[inline]
+fn cast<F: Real>(value: f64) -> F {
+    F::from(value).expect("Failed to convert amplitude")
+}
+
+#[inline]
+fn to_f64<F: Real>(value: F) -> f64 {
+    value.to_f64().expect("Failed to convert amplitude")
+}
+
+/// Gate kernels and views they operate on.
+mod kernels {
+    use super::{cast, Real};
+    use crate::{complex::Complex, matrix::Matrix};
+    #[cfg(feature = "parallel")]
+    use rayon::prelude::*;
+
+    /// Mutable view into real and imaginary parts of consecutive amplitudes.
+    pub struct Lanes<'a, F> {
+        pub real: &'a mut [F],
+        pub imag: &'a mut [F],
+    }
+
+    impl<'a, F: Real> Lanes<'a, F> {
+        #[inline]
+        pub fn len(&self) -> usize {
+            self.real.len()
+        }
+
+        #[inline]
+        pub fn split_at_mut(self, mid: usize) -> (Lanes<'a, F>, Lanes<'a, F>) {
+            let (real0, real1) = self.real.split_at_mut(mid);
+            let (imag0, imag1) = self.imag.split_at_mut(mid);
+
+            (
+                Lanes {
+                    real: real0,
+                    imag: imag0,
+                },
+                Lanes {
+                    real: real1,
+                    imag: imag1,
+                },
+            )
+        }
+
+        pub fn chunks_mut(self, size: usize) -> impl Iterator<Item = Lanes<'a, F>> {
+            self.real
+                .chunks_mut(size)
+                .zip(self.imag.chunks_mut(size))
+                .map(|(real, imag)| Lanes { real, imag })
+        }
+
+        #[cfg(feature = "parallel")]
+        pub fn par_chunks_mut(
+            self,
+            size: usize,
+        ) -> impl IndexedParallelIterator<Item = Lanes<'a, F>> {
+            self.real
+                .par_chunks_mut(size)
+                .zip(self.imag.par_chunks_mut(size))
+                .map(|(real, imag)| Lanes { real, imag })
+        }
+    }
+
+    /// Real and imaginary parts of the gate matrix elements in row-major order.
+    pub struct GateCoefficients<F> {
+        size: usize,
+        pub real: Vec<F>,
+        pub imag: Vec<F>,
+    }
+
+    impl<F: Real> GateCoefficients<F> {
+        pub fn new(matrix: &Matrix<Complex>, size: usize) -> GateCoefficients<F> {
+            assert!(
+                matrix.rows() == size && matrix.cols() == size,
+                "Gate matrix must be {}x{}",
+                size,
+                size
+            );
+
+            let elements: Vec<_> = (0..size * size)
+                .map(|index| matrix.get(index / size, index % size))
+                .collect();
+
+            GateCoefficients {
+                size,
+                real: elements.iter().map(|element| cast(element.real)).collect(),
+                imag: elements.iter().map(|element| cast(element.imag)).collect(),
+            }
+        }
+
+        /// Returns the gate acting on basis states, where state `i` is `order[i]` of
+        /// the original gate.
+        pub fn permuted(&self, order: [usize; 4]) -> GateCoefficients<F> {
+            let index = |i: usize| order[i / self.size] * self.size + order[i % self.size];
+
+            GateCoefficients {
+                size: self.size,
+                real: (0..self.size * self.size)
+                    .map(|i| self.real[index(i)])
+                    .collect(),
+                imag: (0..self.size * self.size)
+                    .map(|i| self.imag[index(i)])
+                    .collect(),
+            }
+        }
+    }
+
+    /// Kernels specialized for the floating point type.
+    pub trait Kernels: Sized {
+        /// Updates pairs of amplitudes `zeros[k]` and `ones[k]`, where the first one
+        /// has index `base + k`, if the index has all `controls` bits set.
+        fn pair_kernel(
+            gate: &GateCoefficients<Self>,
+            zeros: Lanes<Self>,
+            ones: Lanes<Self>,
+            base: usize,
+            controls: usize,
+        );
+
+        /// Updates quadruples of amplitudes `lanes[0][k]`, ..., `lanes[3][k]`.
+        fn quad_kernel(gate: &GateCoefficients<Self>, lanes: [Lanes<Self>; 4]);
+    }
+
+    /// Implements kernels, that use AVX instructions processing `$width` amplitudes
+    /// at once if they are supported, and scalar ones otherwise.
+    macro_rules! impl_kernels {
+        (
+            $float:ty,
+            $width:expr,
+            $module:ident,
+            $register:ty,
+            $set1:ident,
+            $setzero:ident,
+            $load:ident,
+            $store:ident,
+            $add:ident,
+            $sub:ident,
+            $mul:ident
+        ) => {
+            impl Kernels for $float {
+                fn pair_kernel(
+                    gate: &GateCoefficients<$float>,
+                    zeros: Lanes<$float>,
+                    ones: Lanes<$float>,
+                    base: usize,
+                    controls: usize,
+                ) {
+                    // Vector kernels check controls once per `$width` amplitudes.
+                    #[cfg(target_arch = "x86_64")]
+                    if controls & ($width - 1) == 0
+                        && base.is_multiple_of($width)
+                        && zeros.len().is_multiple_of($width)
+                        && is_x86_feature_detected!("avx")
+                    {
+                        // SAFETY: AVX support and alignment of lanes are checked above.
+                        unsafe { $module::pair_kernel(gate, zeros, ones, base, controls) };
+                        return;
+                    }
+
+                    scalar::pair_kernel(gate, zeros, ones, base, controls);
+                }
+
+                fn quad_kernel(gate: &GateCoefficients<$float>, lanes: [Lanes<$float>; 4]) {
+                    #[cfg(target_arch = "x86_64")]
+                    if lanes[0].len().is_multiple_of($width) && is_x86_feature_detected!("avx") {
+                        // SAFETY: AVX support and alignment of lanes are checked above.
+                        unsafe { $module::quad_kernel(gate, lanes) };
+                        return;
+                    }
+
+                    scalar::quad_kernel(gate, lanes);
+                }
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            mod $module {
+                use super::{GateCoefficients, Lanes};
+                use std::arch::x86_64::*;
+
+                /// Multiplies broadcasted gate element by the vector of amplitudes.
+                #[inline]
+                #[target_feature(enable = "avx")]
+                unsafe fn mul(
+                    gate_real: $register,
+                    gate_imag: $register,
+                    real: $register,
+                    imag: $register,
+                ) -> ($register, $register) {
+                    (
+                        $sub($mul(gate_real, real), $mul(gate_imag, imag)),
+                        $add($mul(gate_real, imag), $mul(gate_imag, real)),
+                    )
+                }
+
+                /// # Safety
+                ///
+                /// The processor must support AVX, `controls` must not contain lowest
+                /// bits of the vector index, `base` and length of lanes must be
+                /// multiples of the vector width.
+                #[target_feature(enable = "avx")]
+                pub(super) unsafe fn pair_kernel(
+                    gate: &GateCoefficients<$float>,
+                    zeros: Lanes<$float>,
+                    ones: Lanes<$float>,
+                    base: usize,
+                    controls: usize,
+                ) {
+                    let real: [$register; 4] = std::array::from_fn(|i| $set1(gate.real[i]));
+                    let imag: [$register; 4] = std::array::from_fn(|i| $set1(gate.imag[i]));
+
+                    for k in (0..zeros.len()).step_by($width) {
+                        if (base + k) & controls != controls {
+                            continue;
+                        }
+
+                        let real0 = $load(zeros.real.as_ptr().add(k));
+                        let imag0 = $load(zeros.imag.as_ptr().add(k));
+                        let real1 = $load(ones.real.as_ptr().add(k));
+                        let imag1 = $load(ones.imag.as_ptr().add(k));
+
+                        let (a_real, a_imag) = mul(real[0], imag[0], real0, imag0);
+                        let (b_real, b_imag) = mul(real[1], imag[1], real1, imag1);
+                        let (c_real, c_imag) = mul(real[2], imag[2], real0, imag0);
+                        let (d_real, d_imag) = mul(real[3], imag[3], real1, imag1);
+
+                        $store(zeros.real.as_mut_ptr().add(k), $add(a_real, b_real));
+                        $store(zeros.imag.as_mut_ptr().add(k), $add(a_imag, b_imag));
+                        $store(ones.real.as_mut_ptr().add(k), $add(c_real, d_real));
+                        $store(ones.imag.as_mut_ptr().add(k), $add(c_imag, d_imag));
+                    }
+                }
+
+                /// # Safety
+                ///
+                /// The processor must support AVX and length of lanes must be a
+                /// multiple of the vector width.
+                #[target_feature(enable = "avx")]
+                pub(super) unsafe fn quad_kernel(
+                    gate: &GateCoefficients<$float>,
+                    mut lanes: [Lanes<$float>; 4],
+                ) {
+                    let real: [$register; 16] = std::array::from_fn(|i| $set1(gate.real[i]));
+                    let imag: [$register; 16] = std::array::from_fn(|i| $set1(gate.imag[i]));
+
+                    for k in (0..lanes[0].len()).step_by($width) {
+                        let input: [($register, $register); 4] = std::array::from_fn(|i| {
+                            (
+                                $load(lanes[i].real.as_ptr().add(k)),
+                                $load(lanes[i].imag.as_ptr().add(k)),
+                            )
+                        });
+
+                        for (row, output) in lanes.iter_mut().enumerate() {
+                            let (mut sum_real, mut sum_imag) = ($setzero(), $setzero());
+
+                            for (col, (input_real, input_imag)) in input.iter().enumerate() {
+                                let (product_real, product_imag) = mul(
+                                    real[row * 4 + col],
+                                    imag[row * 4 + col],
+                                    *input_real,
+                                    *input_imag,
+                                );
+                                sum_real = $add(sum_real, product_real);
+                                sum_imag = $add(sum_imag, product_imag);
+                            }
+
+                            $store(output.real.as_mut_ptr().add(k), sum_real);
+                            $store(output.imag.as_mut_ptr().add(k), sum_imag);
+                        }
+                    }
+                }
+            }
+        };
+    }
+
+    impl_kernels!(
+        f64,
+        4,
+        avx_f64,
+        __m256d,
+        _mm256_set1_pd,
+        _mm256_setzero_pd,
+        _mm256_loadu_pd,
+        _mm256_storeu_pd,
+        _mm256_add_pd,
+        _mm256_sub_pd,
+        _mm256_mul_pd
+    );
+
+    impl_kernels!(
+        f32,
+        8,
+        avx_f32,
+        __m256,
+        _mm256_set1_ps,
+        _mm256_setzero_ps,
+        _mm256_loadu_ps,
+        _mm256_storeu_ps,
+        _mm256_add_ps,
+        _mm256_sub_ps,
+        _mm256_mul_ps
+    );
+
+    /// Portable kernels.
+    pub mod scalar {
+        use super::{GateCoefficients, Lanes};
+        use crate::amplitude_buffer::Real;
+
+        pub fn pair_kernel<F: Real>(
+            gate: &GateCoefficients<F>,
+            zeros: Lanes<F>,
+            ones: Lanes<F>,
+            base: usize,
+            controls: usize,
+        ) {
+            let (real, imag) = (&gate.real, &gate.imag);
+
+            for k in 0..zeros.len() {
+                if (base + k) & controls != controls {
+                    continue;
+                }
+
+                let (real0, imag0) = (zeros.real[k], zeros.imag[k]);
+                let (real1, imag1) = (ones.real[k], ones.imag[k]);
+
+                zeros.real[k] =
+                    real[0] * real0 - imag[0] * imag0 + real[1] * real1 - imag[1] * imag1;
+                zeros.imag[k] =
+                    real[0] * imag0 + imag[0] * real0 + real[1] * imag1 + imag[1] * real1;
+                ones.real[k] =
+                    real[2] * real0 - imag[2] * imag0 + real[3] * real1 - imag[3] * imag1;
+                ones.imag[k] =
+                    real[2] * imag0 + imag[2] * real0 + real[3] * imag1 + imag[3] * real1;
+            }
+        }
+
+        pub fn quad_kernel<F: Real>(gate: &GateCoefficients<F>, mut lanes: [Lanes<F>; 4]) {
+            for k in 0..lanes[0].len() {
+                let input: [(F, F); 4] =
+                    std::array::from_fn(|i| (lanes[i].real[k], lanes[i].imag[k]));
+
+                for (row, output) in lanes.iter_mut().enumerate() {
+                    let (mut real, mut imag) = (F::zero(), F::zero());
+
+                    for (col, (input_real, input_imag)) in input.iter().enumerate() {
+                        let (gate_real, gate_imag) =
+                            (gate.real[row * 4 + col], gate.imag[row * 4 + col]);
+                        real = real + gate_real * *input_real - gate_imag * *input_imag;
+                        imag = imag + gate_real * *input_imag + gate_imag * *input_real;
+                    }
+
+                    output.real[k] = real;
+                    output.imag[k] = imag;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        amplitude_buffer::{
+            kernels::{scalar, GateCoefficients, Kernels, Lanes},
+            AmplitudeBuffer, Real,
+        },
+        complex::Complex,
+        matrix::Matrix,
+    };
+
+    /// Deterministic pseudo-random amplitudes.
+    fn amplitudes(qubits: usize) -> Vec<Complex> {
+        let mut state = 0x2545f4914f6cdd1du64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 2000) as f64 / 1000f64 - 1f64
+        };
+
+        (0..1 << qubits)
+            .map(|_| Complex::new(next(), next()))
+            .collect()
+    }
+
+    fn gate(size: usize) -> Matrix<Complex> {
+        let elements = amplitudes(size.trailing_zeros() as usize * 2);
+        Matrix::new(size, size, elements)
+    }
+
+    fn assert_close<F: Real>(actual: &AmplitudeBuffer<F>, expected: &[Complex], epsilon: f64) {
+        for (index, expected) in expected.iter().enumerate() {
+            let actual = actual.get(index);
+            assert!(
+                (actual.real - expected.real).abs() < epsilon
+                    && (actual.imag - expected.imag).abs() < epsilon,
+                "amplitude {}: {} != {}",
+                index,
+                actual,
+                expected
+            );
+        }
+    }
+
+    fn check_single_qubit_gates<F: Real>(epsilon: f64) {
+        let (gate, amplitudes) = (gate(2), amplitudes(7));
+
+        for target in 0..7 {
+            for controls in [0, 1, 0b1100, 0b1000001] {
+                let controls = controls & !(1 << target);
+
+                let mut expected = amplitudes.clone();
+                for i in 0..expected.len() {
+                    if i & 1 << target == 0 && i & controls == controls {
+                        let j = i | 1 << target;
+                        let (zero, one) = (expected[i], expected[j]);
+                        expected[i] = gate.get(0, 0) * zero + gate.get(0, 1) * one;
+                        expected[j] = gate.get(1, 0) * zero + gate.get(1, 1) * one;
+                    }
+                }
+
+                let mut buffer = AmplitudeBuffer::<F>::from_amplitudes(&amplitudes);
+                buffer.apply_single_qubit_gate(&gate, controls, target, false);
+                assert_close(&buffer, &expected, epsilon);
+
+                #[cfg(feature = "parallel")]
+                {
+                    let mut buffer = AmplitudeBuffer::<F>::from_amplitudes(&amplitudes);
+                    buffer.apply_single_qubit_gate(&gate, controls, target, true);
+                    assert_close(&buffer, &expected, epsilon);
+                }
+            }
+        }
+    }
+
+    fn check_two_qubit_gates<F: Real>(epsilon: f64) {
+        let (gate, amplitudes) = (gate(4), amplitudes(6));
+
+        for qubit0 in 0..6 {
+            for qubit1 in (0..6).filter(|qubit1| *qubit1 != qubit0) {
+                let mut expected = amplitudes.clone();
+                for i in 0..expected.len() {
+                    if i & (1 << qubit0 | 1 << qubit1) != 0 {
+                        continue;
+                    }
+
+                    let indices = [
+                        i,
+                        i | 1 << qubit0,
+                        i | 1 << qubit1,
+                        i | 1 << qubit0 | 1 << qubit1,
+                    ];
+                    let input = indices.map(|index| expected[index]);
+                    for (row, index) in indices.iter().enumerate() {
+                        expected[*index] = (0..4).map(|col| gate.get(row, col) * input[col]).sum();
+                    }
+                }
+
+                let mut buffer = AmplitudeBuffer::<F>::from_amplitudes(&amplitudes);
+                buffer.apply_two_qubit_gate(&gate, qubit0, qubit1, false);
+                assert_close(&buffer, &expected, epsilon);
+
+                #[cfg(feature = "parallel")]
+                {
+                    let mut buffer = AmplitudeBuffer::<F>::from_amplitudes(&amplitudes);
+                    buffer.apply_two_qubit_gate(&gate, qubit0, qubit1, true);
+                    assert_close(&buffer, &expected, epsilon);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn applies_single_qubit_gates() {
+        check_single_qubit_gates::<f64>(1e-12);
+        check_single_qubit_gates::<f32>(1e-5);
+    }
+
+    #[test]
+    fn applies_two_qubit_gates() {
+        check_two_qubit_gates::<f64>(1e-12);
+        check_two_qubit_gates::<f32>(1e-5);
+    }
+
+    #[test]
+    fn vector_kernels_match_scalar_ones() {
+        let (gate, amplitudes) = (GateCoefficients::<f64>::new(&gate(2), 2), amplitudes(5));
+        let (mut real, mut imag): (Vec<_>, Vec<_>) = amplitudes
+            .iter()
+            .map(|amplitude| (amplitude.real, amplitude.imag))
+            .unzip();
+        let (mut expected_real, mut expected_imag) = (real.clone(), imag.clone());
+
+        let lanes = Lanes {
+            real: &mut real,
+            imag: &mut imag,
+        };
+        let (zeros, ones) = lanes.split_at_mut(16);
+        f64::pair_kernel(&gate, zeros, ones, 0, 0);
+
+        let lanes = Lanes {
+            real: &mut expected_real,
+            imag: &mut expected_imag,
+        };
+        let (zeros, ones) = lanes.split_at_mut(16);
+        scalar::pair_kernel(&gate, zeros, ones, 0, 0);
+
+        for (actual, expected) in real
+            .iter()
+            .chain(&imag)
+            .zip(expected_real.iter().chain(&expected_imag))
+        {
+            assert!((actual - expected).abs() < 1e-12);
+        }
+    }
+}
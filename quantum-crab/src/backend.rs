@@ -1,12 +1,336 @@
-use crate::quantum_circuit::QuantumCircuit;
+use crate::{
+    classical_register::ClassicalRegister,
+    job::{CancellationToken, Job},
+    quantum_circuit::{Instruction, QuantumCircuit},
+    statevector::StateVector,
+};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::fmt::{self, Display};
 
 /// Represents backend, that executes quantum circuit and returns
 /// any kind of representation of result.
+///
+/// Backend settings (like precision of amplitudes) are stored in the backend itself,
+/// while [`RunOptions`] apply to a single execution.
+///
+/// ```
+/// use quantum_crab::{
+///     backend::{Backend, RunOptions},
+///     quantum_circuit::QuantumCircuit,
+///     stabilizer_backend::StabilizerBackend,
+/// };
+///
+/// let mut circuit = QuantumCircuit::with_clbits(2, 2);
+/// circuit.h(0).cx(0, 1).measure(0..2, 0..2);
+///
+/// let options = RunOptions {
+///     seed: Some(42),
+///     ..RunOptions::default()
+/// };
+/// let register = StabilizerBackend.run(&circuit, &options).unwrap();
+/// assert_eq!(register, StabilizerBackend.run(&circuit, &options).unwrap());
+///
+/// circuit.t(0);
+/// assert!(StabilizerBackend.run(&circuit, &options).is_err());
+/// ```
 pub trait Backend {
     /// Type of output data, that is returned after the circuit is executed.
     type Output;
 
-    /// Executes given quantum circuit and returns the output data
+    /// Executes given quantum circuit with the options and returns the output data
     /// corresponding to different types of backends.
-    fn execute(circuit: QuantumCircuit) -> Self::Output;
+    fn run(
+        &self,
+        circuit: &QuantumCircuit,
+        options: &RunOptions,
+    ) -> Result<Self::Output, BackendError>;
+
+    /// Executes given quantum circuit on the backend with default settings and
+    /// options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend can't execute the circuit.
+    fn execute(circuit: QuantumCircuit) -> Self::Output
+    where
+        Self: Default,
+    {
+        Self::default()
+            .run(&circuit, &RunOptions::default())
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Executes the circuits, returning results in the same order. With the
+    /// `parallel` feature, circuits are executed on multiple threads.
+    ///
+    /// Cancellation token of the options is shared by all circuits, while the seed
+    /// `s` is replaced by `s + i` for the circuit `i`, so that results don't depend
+    /// on the order of execution.
+    fn run_batch(
+        &self,
+        circuits: &[QuantumCircuit],
+        options: &RunOptions,
+    ) -> Vec<Result<Self::Output, BackendError>>
+    where
+        Self: Sync,
+        Self::Output: Send,
+    {
+        let run = |(index, circuit)| self.run(circuit, &batch_options(options, index));
+
+        #[cfg(feature = "parallel")]
+        return circuits.par_iter().enumerate().map(run).collect();
+
+        #[cfg(not(feature = "parallel"))]
+        circuits.iter().enumerate().map(run).collect()
+    }
+
+    /// Executes variants of the circuit, built by the closure for every parameter,
+    /// as a batch (see [`Backend::run_batch`]).
+    ///
+    /// ```
+    /// use quantum_crab::{
+    ///     backend::{Backend, RunOptions},
+    ///     quantum_circuit::QuantumCircuit,
+    ///     statevector_backend::StateVectorBackend,
+    /// };
+    /// use std::f64::consts::PI;
+    ///
+    /// let angles: Vec<f64> = (0..=4).map(|i| PI * i as f64 / 4f64).collect();
+    /// let states = StateVectorBackend::default().run_sweep(
+    ///     &angles,
+    ///     |angle| {
+    ///         let mut circuit = QuantumCircuit::new(1);
+    ///         circuit.ry(0, *angle);
+    ///         circuit
+    ///     },
+    ///     &RunOptions::default(),
+    /// );
+    ///
+    /// for (angle, state) in angles.iter().zip(states) {
+    ///     let amplitude = state.unwrap().amplitude("1");
+    ///     assert!((amplitude.real - (angle / 2f64).sin()).abs() < 1e-12);
+    /// }
+    /// ```
+    fn run_sweep<P, F>(
+        &self,
+        parameters: &[P],
+        circuit: F,
+        options: &RunOptions,
+    ) -> Vec<Result<Self::Output, BackendError>>
+    where
+        Self: Sync,
+        Self::Output: Send,
+        F: Fn(&P) -> QuantumCircuit,
+    {
+        let circuits: Vec<_> = parameters.iter().map(circuit).collect();
+        self.run_batch(&circuits, options)
+    }
+
+    /// Starts execution of the circuit in a background thread, returning the job,
+    /// that can be polled, cancelled and waited for (see [`Job`]).
+    fn submit(&self, circuit: &QuantumCircuit, options: &RunOptions) -> Job<Self::Output>
+    where
+        Self: Clone + Send + Sized + 'static,
+        Self::Output: Send + 'static,
+    {
+        Job::spawn(self.clone(), circuit.clone(), options.clone())
+    }
+}
+
+/// Options of a single execution of the circuit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunOptions {
+    /// Seed of the random number generator, used for measurement outcomes, so that
+    /// results are reproducible. By default, the generator is seeded from entropy.
+    pub seed: Option<u64>,
+    /// Token, that stops the execution when cancelled. Backends check it before
+    /// every instruction.
+    pub cancellation: Option<CancellationToken>,
+    /// State of qubits before the execution, `|0...0>` by default.
+    pub initial_state: Option<InitialState>,
+}
+
+impl RunOptions {
+    /// Returns [`BackendError::Cancelled`] if the execution was cancelled.
+    pub fn check_cancelled(&self) -> Result<(), BackendError> {
+        match &self.cancellation {
+            Some(token) if token.is_cancelled() => Err(BackendError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the initial state is valid for the amount of qubits.
+    pub(crate) fn initial_state(
+        &self,
+        qubits: usize,
+    ) -> Result<CheckedInitialState<'_>, BackendError> {
+        let invalid = |message: String| Err(BackendError::InvalidInitialState(message));
+
+        match &self.initial_state {
+            None => Ok(CheckedInitialState::Basis(vec![0; qubits])),
+            Some(InitialState::StateVector(state)) => {
+                if state.qubits() != qubits {
+                    return invalid(format!(
+                        "State has {} qubits, but circuit has {}",
+                        state.qubits(),
+                        qubits
+                    ));
+                }
+
+                if !state.is_normalized() {
+                    return invalid("State is not normalized".to_owned());
+                }
+
+                Ok(CheckedInitialState::StateVector(state))
+            }
+            Some(InitialState::Bitstring(bitstring)) => {
+                if bitstring.len() != qubits
+                    || bitstring.chars().any(|bit| bit != '0' && bit != '1')
+                {
+                    return invalid(format!(
+                        "Bitstring {:?} is not a basis state of {} qubits",
+                        bitstring, qubits
+                    ));
+                }
+
+                Ok(CheckedInitialState::Basis(
+                    bitstring.bytes().rev().map(|bit| bit - b'0').collect(),
+                ))
+            }
+            Some(InitialState::Register(register)) => {
+                if register.width() != qubits {
+                    return invalid(format!(
+                        "Register has {} bits, but circuit has {} qubits",
+                        register.width(),
+                        qubits
+                    ));
+                }
+
+                Ok(CheckedInitialState::Basis(register.bits().to_vec()))
+            }
+        }
+    }
+}
+
+/// State of qubits before the execution of the circuit.
+///
+/// ```
+/// use quantum_crab::{
+///     backend::{Backend, InitialState, RunOptions},
+///     quantum_circuit::QuantumCircuit,
+///     statevector_backend::StateVectorBackend,
+/// };
+///
+/// let mut circuit = QuantumCircuit::new(2);
+/// circuit.cx(0, 1);
+///
+/// let options = RunOptions {
+///     initial_state: Some(InitialState::Bitstring("01".to_owned())),
+///     ..RunOptions::default()
+/// };
+/// let state = StateVectorBackend::default().run(&circuit, &options).unwrap();
+/// assert_eq!(state.to_string(), "1.000|11>");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitialState {
+    /// Arbitrary state of all qubits.
+    StateVector(StateVector),
+    /// Basis state, written as a bitstring like `"01"`, where qubit 0 is the last bit.
+    Bitstring(String),
+    /// Basis state, where qubit `i` is in the state of bit `i` of the register.
+    Register(ClassicalRegister),
+}
+
+/// Initial state, that was checked against the circuit.
+#[derive(Debug)]
+pub(crate) enum CheckedInitialState<'a> {
+    /// Basis state with the state of every qubit.
+    Basis(Vec<u8>),
+    /// Arbitrary normalized state of all qubits.
+    StateVector(&'a StateVector),
+}
+
+/// Error returned when the backend can't execute the circuit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendError {
+    /// Circuit contains instructions, that are not supported by the backend (custom
+    /// gates are flattened).
+    UnsupportedInstructions(Vec<Instruction>),
+    /// Circuit has more qubits, than the backend supports.
+    TooManyQubits { qubits: usize, max_qubits: usize },
+    /// Execution was cancelled.
+    Cancelled,
+    /// Initial state doesn't match the circuit.
+    InvalidInitialState(String),
+    /// Initial state can't be prepared by the backend.
+    UnsupportedInitialState,
+    /// Backend panicked while executing the circuit in a job (see
+    /// [`Backend::submit`]), with the panic message.
+    Panicked(String),
+    /// Settings of the backend are invalid.
+    InvalidSettings(String),
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::UnsupportedInstructions(instructions) => {
+                write!(f, "Circuit contains unsupported instructions:")?;
+
+                for instruction in instructions {
+                    write!(f, " {:?}", instruction)?;
+                }
+
+                Ok(())
+            }
+            BackendError::TooManyQubits { qubits, max_qubits } => write!(
+                f,
+                "Circuit has {} qubits, but backend supports at most {}",
+                qubits, max_qubits
+            ),
+            BackendError::Cancelled => write!(f, "Execution was cancelled"),
+            BackendError::InvalidInitialState(message) => {
+                write!(f, "Invalid initial state: {}", message)
+            }
+            BackendError::UnsupportedInitialState => {
+                write!(f, "Initial state is not supported by backend")
+            }
+            BackendError::Panicked(message) => write!(f, "Backend panicked: {}", message),
+            BackendError::InvalidSettings(message) => {
+                write!(f, "Invalid backend settings: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+/// Returns options for the circuit with the index in a batch.
+pub(crate) fn batch_options(options: &RunOptions, index: usize) -> RunOptions {
+    RunOptions {
+        seed: options.seed.map(|seed| seed.wrapping_add(index as u64)),
+        ..options.clone()
+    }
+}
+
+/// Returns error if the flattened circuit contains instructions, that don't satisfy
+/// the predicate.
+pub(crate) fn check_instructions(
+    circuit: &QuantumCircuit,
+    supported: impl Fn(&Instruction) -> bool,
+) -> Result<(), BackendError> {
+    let unsupported: Vec<_> = circuit
+        .flatten()
+        .instructions()
+        .iter()
+        .filter(|instruction| !supported(instruction))
+        .cloned()
+        .collect();
+
+    if unsupported.is_empty() {
+        Ok(())
+    } else {
+        Err(BackendError::UnsupportedInstructions(unsupported))
+    }
 }
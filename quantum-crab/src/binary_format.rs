@@ -0,0 +1,876 @@
+//! Implements compact binary encoding of quantum circuits.
+//!
+//! Encoded circuit starts with magic bytes `QCRB` and the version of the format,
+//! followed by the table of custom gate definitions and the circuit itself:
+//!
+//! ```txt
+//! circuit     := "QCRB" version:u8 definitions body
+//! definitions := count:varint (name body)*
+//! name        := length:varint utf8-bytes
+//! body        := qubits:varint clbits:varint count:varint instruction* layout registers
+//! layout      := 0 | 1 (physical_to_logical:varint)*
+//! registers   := count:varint register* count:varint register*
+//! register    := name offset:varint size:varint
+//! instruction := opcode:u8 operands
+//! ```
+//!
+//! Qubit and classical bit indices, as well as lengths, are stored as LEB128 varints
+//! and angles are stored as little-endian `f64`. Every distinct custom gate is stored
+//! only once in the table of definitions: instructions refer to it by its index, and
+//! definitions can only refer to definitions coming before them. In the layout,
+//! physical qubit `i` stores `0` if it is not used, or `1 + logical` otherwise.
+//! Quantum registers are followed by classical registers (see [`crate::register`]);
+//! circuits encoded with version 1 of the format have no registers.
+//!
+//! ```
+//! use quantum_crab::quantum_circuit::QuantumCircuit;
+//!
+//! let mut circuit = QuantumCircuit::with_clbits(2, 2);
+//! circuit.h(0).cx(0, 1).measure(0..2, 0..2);
+//!
+//! let bytes = circuit.to_bytes();
+//! assert_eq!(bytes.len(), 23);
+//! assert_eq!(QuantumCircuit::from_bytes(&bytes), Ok(circuit));
+//! ```
+use crate::{
+    complex::Complex,
+    layout::Layout,
+    quantum_circuit::{Instruction, QuantumCircuit},
+    register::Register,
+};
+use std::fmt::{self, Display};
+
+/// Magic bytes every encoded circuit starts with.
+const MAGIC: &[u8; 4] = b"QCRB";
+
+/// Current version of the binary format.
+pub const FORMAT_VERSION: u8 = 2;
+
+/// Maximum amount of instructions in the decoded circuit, including instructions of
+/// custom gates. As custom gate definitions are shared, small inputs could otherwise
+/// describe circuits that don't fit in memory.
+pub const MAX_DECODED_INSTRUCTIONS: usize = 1 << 20;
+
+/// Maximum nesting of [`Instruction::ControlledU`] and [`Instruction::If`]
+/// instructions in the decoded circuit.
+const MAX_NESTING: usize = 64;
+
+const IDENTITY: u8 = 0;
+const PAULI_X: u8 = 1;
+const PAULI_Y: u8 = 2;
+const PAULI_Z: u8 = 3;
+const HADAMARD: u8 = 4;
+const PHASE: u8 = 5;
+const PHASE_DAGGER: u8 = 6;
+const T: u8 = 7;
+const T_DAGGER: u8 = 8;
+const S: u8 = 9;
+const S_DAGGER: u8 = 10;
+const CONTROLLED_NOT: u8 = 11;
+const CONTROLLED_U: u8 = 12;
+const TOFFOLI: u8 = 13;
+const SWAP: u8 = 14;
+const ROTATION_X: u8 = 15;
+const ROTATION_Y: u8 = 16;
+const ROTATION_Z: u8 = 17;
+const MEASURE: u8 = 18;
+const BARRIER: u8 = 19;
+const CUSTOM: u8 = 20;
+const INITIALIZE: u8 = 21;
+const RESET: u8 = 22;
+const IF: u8 = 23;
+
+/// Error returned when bytes don't contain a valid encoded circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Input doesn't start with the magic bytes.
+    InvalidMagic,
+    /// Input was encoded with unsupported version of the format.
+    UnsupportedVersion(u8),
+    /// Input ended in the middle of the circuit.
+    UnexpectedEnd,
+    /// Varint doesn't fit into `usize`.
+    InvalidVarint,
+    /// Unknown instruction opcode.
+    InvalidOpcode(u8),
+    /// Custom gate or register name is not valid UTF-8.
+    InvalidName,
+    /// Instruction refers to custom gate definition, that doesn't exist (yet).
+    InvalidDefinition(usize),
+    /// Instruction is not valid for the circuit.
+    InvalidInstruction(String),
+    /// Layout is not a valid mapping of circuit qubits.
+    InvalidLayout,
+    /// Registers are not valid for the circuit.
+    InvalidRegisters(String),
+    /// Decoded circuit exceeds [`MAX_DECODED_INSTRUCTIONS`] or nests controlled gates
+    /// and conditioned instructions too deeply.
+    TooLarge,
+    /// There are bytes left after the circuit.
+    TrailingBytes,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidMagic => write!(f, "input is not an encoded circuit"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::InvalidVarint => write!(f, "invalid varint"),
+            DecodeError::InvalidOpcode(opcode) => write!(f, "invalid opcode {}", opcode),
+            DecodeError::InvalidName => write!(f, "name is not valid UTF-8"),
+            DecodeError::InvalidDefinition(index) => {
+                write!(f, "invalid custom gate definition {}", index)
+            }
+            DecodeError::InvalidInstruction(message) => write!(f, "{}", message),
+            DecodeError::InvalidLayout => write!(f, "invalid layout"),
+            DecodeError::InvalidRegisters(message) => write!(f, "{}", message),
+            DecodeError::TooLarge => write!(f, "decoded circuit is too large"),
+            DecodeError::TrailingBytes => write!(f, "unexpected bytes after the circuit"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl QuantumCircuit {
+    /// Encodes the circuit into the binary format (see [`crate::binary_format`]).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder {
+            definitions: Vec::new(),
+            bytes: Vec::new(),
+        };
+        encoder.collect_definitions(self);
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        write_varint(&mut bytes, encoder.definitions.len());
+
+        for i in 0..encoder.definitions.len() {
+            let (name, circuit) = encoder.definitions[i];
+            write_name(&mut bytes, name);
+
+            encoder.write_body(circuit);
+            bytes.append(&mut encoder.bytes);
+        }
+
+        encoder.write_body(self);
+        bytes.append(&mut encoder.bytes);
+
+        bytes
+    }
+
+    /// Decodes the circuit encoded with [`QuantumCircuit::to_bytes`], including
+    /// circuits encoded with earlier versions of the format. Never panics, returning
+    /// an error if bytes don't contain a valid circuit instead.
+    pub fn from_bytes(bytes: &[u8]) -> Result<QuantumCircuit, DecodeError> {
+        let mut decoder = Decoder {
+            bytes,
+            position: 0,
+            version: FORMAT_VERSION,
+            definitions: Vec::new(),
+            instructions: 0,
+        };
+
+        if decoder.read_bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(DecodeError::InvalidMagic);
+        }
+
+        decoder.version = decoder.read_byte()?;
+        if !(1..=FORMAT_VERSION).contains(&decoder.version) {
+            return Err(DecodeError::UnsupportedVersion(decoder.version));
+        }
+
+        let definitions = decoder.read_varint()?;
+        for _ in 0..definitions {
+            let name = decoder.read_name()?;
+            let instructions = decoder.instructions;
+            let circuit = decoder.read_body()?;
+
+            decoder
+                .definitions
+                .push((name, circuit, decoder.instructions - instructions));
+        }
+
+        let circuit = decoder.read_body()?;
+        if decoder.position != bytes.len() {
+            return Err(DecodeError::TrailingBytes);
+        }
+
+        Ok(circuit)
+    }
+}
+
+struct Encoder<'a> {
+    /// Distinct custom gates, every gate comes after gates used inside of it.
+    definitions: Vec<(&'a str, &'a QuantumCircuit)>,
+    bytes: Vec<u8>,
+}
+
+impl<'a> Encoder<'a> {
+    fn collect_definitions(&mut self, circuit: &'a QuantumCircuit) {
+        for instruction in circuit.instructions() {
+            self.collect_instruction_definitions(instruction);
+        }
+    }
+
+    fn collect_instruction_definitions(&mut self, instruction: &'a Instruction) {
+        match instruction {
+            Instruction::Custom { name, circuit, .. }
+                if self.definition(name, circuit).is_none() =>
+            {
+                self.collect_definitions(circuit);
+                self.definitions.push((name, circuit));
+            }
+            Instruction::ControlledU { gate, .. } => self.collect_instruction_definitions(gate),
+            Instruction::If { instruction, .. } => {
+                self.collect_instruction_definitions(instruction)
+            }
+            _ => {}
+        }
+    }
+
+    fn definition(&self, name: &str, circuit: &QuantumCircuit) -> Option<usize> {
+        self.definitions
+            .iter()
+            .position(|definition| *definition == (name, circuit))
+    }
+
+    fn write_body(&mut self, circuit: &QuantumCircuit) {
+        write_varint(&mut self.bytes, circuit.qubits());
+        write_varint(&mut self.bytes, circuit.clbits());
+        write_varint(&mut self.bytes, circuit.instructions().len());
+
+        for instruction in circuit.instructions() {
+            self.write_instruction(instruction);
+        }
+
+        match circuit.layout() {
+            Some(layout) => {
+                self.bytes.push(1);
+                write_varint(&mut self.bytes, layout.physical_qubits());
+
+                for physical in 0..layout.physical_qubits() {
+                    write_varint(
+                        &mut self.bytes,
+                        layout.logical(physical).map_or(0, |logical| logical + 1),
+                    );
+                }
+            }
+            None => self.bytes.push(0),
+        }
+
+        for registers in [circuit.qregs(), circuit.cregs()] {
+            write_varint(&mut self.bytes, registers.len());
+
+            for register in registers {
+                write_name(&mut self.bytes, register.name());
+                write_varint(&mut self.bytes, register.offset());
+                write_varint(&mut self.bytes, register.size());
+            }
+        }
+    }
+
+    fn write_instruction(&mut self, instruction: &Instruction) {
+        self.bytes.push(opcode(instruction));
+
+        match instruction {
+            Instruction::Phase { qubit, phase }
+            | Instruction::PhaseDagger { qubit, phase }
+            | Instruction::RotationX { qubit, phase }
+            | Instruction::RotationY { qubit, phase }
+            | Instruction::RotationZ { qubit, phase } => {
+                write_varint(&mut self.bytes, *qubit);
+                self.bytes.extend_from_slice(&phase.to_le_bytes());
+            }
+            Instruction::ControlledU {
+                gate,
+                control,
+                target,
+            } => {
+                write_varint(&mut self.bytes, *control);
+                write_varint(&mut self.bytes, *target);
+                self.write_instruction(gate);
+            }
+            Instruction::Measure { qubit, clbit } => {
+                write_varint(&mut self.bytes, *qubit);
+                write_varint(&mut self.bytes, *clbit);
+            }
+            Instruction::If {
+                register,
+                value,
+                instruction,
+            } => {
+                self.write_qubits(register);
+                write_wide_varint(&mut self.bytes, *value);
+                self.write_instruction(instruction);
+            }
+            Instruction::Barrier(qubits) => self.write_qubits(qubits),
+            Instruction::Initialize { qubits, amplitudes } => {
+                self.write_qubits(qubits);
+
+                for amplitude in amplitudes {
+                    self.bytes.extend_from_slice(&amplitude.real.to_le_bytes());
+                    self.bytes.extend_from_slice(&amplitude.imag.to_le_bytes());
+                }
+            }
+            Instruction::Custom {
+                name,
+                circuit,
+                input_qubits,
+            } => {
+                let index = self.definition(name, circuit).unwrap();
+                write_varint(&mut self.bytes, index);
+                self.write_qubits(input_qubits);
+            }
+            _ => {
+                for qubit in instruction.qubits() {
+                    write_varint(&mut self.bytes, qubit);
+                }
+            }
+        }
+    }
+
+    fn write_qubits(&mut self, qubits: &[usize]) {
+        write_varint(&mut self.bytes, qubits.len());
+
+        for qubit in qubits {
+            write_varint(&mut self.bytes, *qubit);
+        }
+    }
+}
+
+fn opcode(instruction: &Instruction) -> u8 {
+    match instruction {
+        Instruction::Identity(..) => IDENTITY,
+        Instruction::PauliX(..) => PAULI_X,
+        Instruction::PauliY(..) => PAULI_Y,
+        Instruction::PauliZ(..) => PAULI_Z,
+        Instruction::Hadamard(..) => HADAMARD,
+        Instruction::Phase { .. } => PHASE,
+        Instruction::PhaseDagger { .. } => PHASE_DAGGER,
+        Instruction::T(..) => T,
+        Instruction::TDagger(..) => T_DAGGER,
+        Instruction::S(..) => S,
+        Instruction::SDagger(..) => S_DAGGER,
+        Instruction::ControlledNot { .. } => CONTROLLED_NOT,
+        Instruction::ControlledU { .. } => CONTROLLED_U,
+        Instruction::Toffoli { .. } => TOFFOLI,
+        Instruction::Swap(..) => SWAP,
+        Instruction::RotationX { .. } => ROTATION_X,
+        Instruction::RotationY { .. } => ROTATION_Y,
+        Instruction::RotationZ { .. } => ROTATION_Z,
+        Instruction::Measure { .. } => MEASURE,
+        Instruction::Reset(..) => RESET,
+        Instruction::If { .. } => IF,
+        Instruction::Barrier(..) => BARRIER,
+        Instruction::Custom { .. } => CUSTOM,
+        Instruction::Initialize { .. } => INITIALIZE,
+    }
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    write_varint(bytes, name.len());
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+fn write_varint(bytes: &mut Vec<u8>, value: usize) {
+    write_wide_varint(bytes, value as u128);
+}
+
+/// Writes varint, that may not fit into `usize`. Values fitting into it are encoded
+/// just like by [`write_varint`].
+fn write_wide_varint(bytes: &mut Vec<u8>, mut value: u128) {
+    while value >= 0x80 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+
+    bytes.push(value as u8);
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// Version of the format the input was encoded with.
+    version: u8,
+    /// Decoded custom gates with the amount of instructions in them, including
+    /// instructions of nested custom gates.
+    definitions: Vec<(String, QuantumCircuit, usize)>,
+    /// Amount of instructions decoded so far (see [`MAX_DECODED_INSTRUCTIONS`]).
+    instructions: usize,
+}
+
+impl Decoder<'_> {
+    fn read_byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        self.position += 1;
+
+        Ok(byte)
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&[u8], DecodeError> {
+        if self.bytes.len() - self.position < length {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let bytes = &self.bytes[self.position..self.position + length];
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    fn read_varint(&mut self) -> Result<usize, DecodeError> {
+        let mut value = 0usize;
+
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.read_byte()?;
+            let bits = (byte & 0x7f) as usize;
+
+            if bits.checked_shl(shift).map(|shifted| shifted >> shift) != Some(bits) {
+                return Err(DecodeError::InvalidVarint);
+            }
+
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError::InvalidVarint)
+    }
+
+    /// Reads varint written by [`write_wide_varint`].
+    fn read_wide_varint(&mut self) -> Result<u128, DecodeError> {
+        let mut value = 0u128;
+
+        for shift in (0..u128::BITS).step_by(7) {
+            let byte = self.read_byte()?;
+            let bits = (byte & 0x7f) as u128;
+
+            if bits.checked_shl(shift).map(|shifted| shifted >> shift) != Some(bits) {
+                return Err(DecodeError::InvalidVarint);
+            }
+
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError::InvalidVarint)
+    }
+
+    fn read_phase(&mut self) -> Result<f64, DecodeError> {
+        let bytes = self.read_bytes(8)?;
+        Ok(f64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_body(&mut self) -> Result<QuantumCircuit, DecodeError> {
+        let qubits = self.read_varint()?;
+        let clbits = self.read_varint()?;
+        let mut circuit = QuantumCircuit::with_clbits(qubits, clbits);
+
+        for _ in 0..self.read_varint()? {
+            let instruction = self.read_instruction(0)?;
+            circuit
+                .check_instruction(&instruction, None)
+                .map_err(DecodeError::InvalidInstruction)?;
+            circuit.add(instruction);
+        }
+
+        match self.read_byte()? {
+            0 => {}
+            1 => {
+                let layout = self.read_layout(qubits)?;
+                circuit.set_layout(layout);
+            }
+            _ => return Err(DecodeError::InvalidLayout),
+        }
+
+        if self.version >= 2 {
+            let qregs = self.read_registers()?;
+            let cregs = self.read_registers()?;
+            circuit
+                .set_registers(qregs, cregs)
+                .map_err(DecodeError::InvalidRegisters)?;
+        }
+
+        Ok(circuit)
+    }
+
+    fn read_registers(&mut self) -> Result<Vec<Register>, DecodeError> {
+        let mut registers = Vec::new();
+
+        for _ in 0..self.read_varint()? {
+            let name = self.read_name()?;
+            let offset = self.read_varint()?;
+            let size = self.read_varint()?;
+            registers.push(Register::new(&name, offset, size));
+        }
+
+        Ok(registers)
+    }
+
+    fn read_name(&mut self) -> Result<String, DecodeError> {
+        let length = self.read_varint()?;
+        std::str::from_utf8(self.read_bytes(length)?)
+            .map(str::to_owned)
+            .map_err(|_| DecodeError::InvalidName)
+    }
+
+    fn read_layout(&mut self, logical_qubits: usize) -> Result<Layout, DecodeError> {
+        let physical_qubits = self.read_varint()?;
+        let mut physical_to_logical = Vec::new();
+
+        for _ in 0..physical_qubits {
+            physical_to_logical.push(self.read_varint()?.checked_sub(1));
+        }
+
+        // Every logical qubit has to be mapped into exactly one physical qubit.
+        let mapped = physical_to_logical.iter().flatten().count();
+        if mapped != logical_qubits {
+            return Err(DecodeError::InvalidLayout);
+        }
+
+        let mut logical_to_physical = vec![None; logical_qubits];
+        for (physical, logical) in physical_to_logical.iter().enumerate() {
+            if let Some(logical) = logical {
+                match logical_to_physical.get_mut(*logical) {
+                    Some(slot @ None) => *slot = Some(physical),
+                    _ => return Err(DecodeError::InvalidLayout),
+                }
+            }
+        }
+
+        Ok(Layout::new(
+            logical_to_physical.into_iter().flatten().collect(),
+            physical_qubits,
+        ))
+    }
+
+    fn read_qubits(&mut self) -> Result<Vec<usize>, DecodeError> {
+        let mut qubits = Vec::new();
+
+        for _ in 0..self.read_varint()? {
+            qubits.push(self.read_varint()?);
+        }
+
+        Ok(qubits)
+    }
+
+    fn read_instruction(&mut self, nesting: usize) -> Result<Instruction, DecodeError> {
+        self.instructions += 1;
+        if self.instructions > MAX_DECODED_INSTRUCTIONS || nesting > MAX_NESTING {
+            return Err(DecodeError::TooLarge);
+        }
+
+        let opcode = self.read_byte()?;
+        let instruction = match opcode {
+            IDENTITY => Instruction::Identity(self.read_varint()?),
+            PAULI_X => Instruction::PauliX(self.read_varint()?),
+            PAULI_Y => Instruction::PauliY(self.read_varint()?),
+            PAULI_Z => Instruction::PauliZ(self.read_varint()?),
+            HADAMARD => Instruction::Hadamard(self.read_varint()?),
+            PHASE => Instruction::Phase {
+                qubit: self.read_varint()?,
+                phase: self.read_phase()?,
+            },
+            PHASE_DAGGER => Instruction::PhaseDagger {
+                qubit: self.read_varint()?,
+                phase: self.read_phase()?,
+            },
+            T => Instruction::T(self.read_varint()?),
+            T_DAGGER => Instruction::TDagger(self.read_varint()?),
+            S => Instruction::S(self.read_varint()?),
+            S_DAGGER => Instruction::SDagger(self.read_varint()?),
+            CONTROLLED_NOT => Instruction::ControlledNot {
+                control: self.read_varint()?,
+                target: self.read_varint()?,
+            },
+            CONTROLLED_U => Instruction::ControlledU {
+                control: self.read_varint()?,
+                target: self.read_varint()?,
+                gate: Box::new(self.read_instruction(nesting + 1)?),
+            },
+            TOFFOLI => Instruction::Toffoli {
+                control1: self.read_varint()?,
+                control2: self.read_varint()?,
+                target: self.read_varint()?,
+            },
+            SWAP => Instruction::Swap(self.read_varint()?, self.read_varint()?),
+            ROTATION_X => Instruction::RotationX {
+                qubit: self.read_varint()?,
+                phase: self.read_phase()?,
+            },
+            ROTATION_Y => Instruction::RotationY {
+                qubit: self.read_varint()?,
+                phase: self.read_phase()?,
+            },
+            ROTATION_Z => Instruction::RotationZ {
+                qubit: self.read_varint()?,
+                phase: self.read_phase()?,
+            },
+            MEASURE => Instruction::Measure {
+                qubit: self.read_varint()?,
+                clbit: self.read_varint()?,
+            },
+            RESET => Instruction::Reset(self.read_varint()?),
+            IF => Instruction::If {
+                register: self.read_qubits()?,
+                value: self.read_wide_varint()?,
+                instruction: Box::new(self.read_instruction(nesting + 1)?),
+            },
+            BARRIER => Instruction::Barrier(self.read_qubits()?),
+            INITIALIZE => {
+                let qubits = self.read_qubits()?;
+                // Every amplitude takes 16 bytes, so there can't be more of them than
+                // bytes left.
+                let count = 1usize
+                    .checked_shl(qubits.len() as u32)
+                    .filter(|count| *count <= self.bytes.len() - self.position)
+                    .ok_or(DecodeError::UnexpectedEnd)?;
+
+                let mut amplitudes = Vec::with_capacity(count);
+                for _ in 0..count {
+                    amplitudes.push(Complex::new(self.read_phase()?, self.read_phase()?));
+                }
+
+                Instruction::Initialize { qubits, amplitudes }
+            }
+            CUSTOM => {
+                let index = self.read_varint()?;
+                let input_qubits = self.read_qubits()?;
+                let (name, circuit, size) = self
+                    .definitions
+                    .get(index)
+                    .ok_or(DecodeError::InvalidDefinition(index))?;
+
+                if input_qubits.len() != circuit.qubits() {
+                    return Err(DecodeError::InvalidInstruction(format!(
+                        "Custom gate {:?} acts on {} qubits, but {} are given",
+                        name,
+                        circuit.qubits(),
+                        input_qubits.len()
+                    )));
+                }
+
+                self.instructions += size;
+                if self.instructions > MAX_DECODED_INSTRUCTIONS {
+                    return Err(DecodeError::TooLarge);
+                }
+
+                Instruction::Custom {
+                    name: name.clone(),
+                    circuit: circuit.clone(),
+                    input_qubits,
+                }
+            }
+            _ => return Err(DecodeError::InvalidOpcode(opcode)),
+        };
+
+        Ok(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        binary_format::DecodeError,
+        complex::Complex,
+        layout::Layout,
+        quantum_circuit::{Instruction, QuantumCircuit},
+    };
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    fn sample_circuit() -> QuantumCircuit {
+        let mut bell = QuantumCircuit::new(2);
+        bell.h(0).cx(0, 1);
+
+        let mut nested = QuantumCircuit::new(3);
+        nested
+            .custom("bell", bell.clone(), [0, 1])
+            .ccx(0, 1, 2)
+            .custom("bell", bell.clone(), [2, 0]);
+
+        let mut t = QuantumCircuit::new(1);
+        t.t(0);
+
+        let mut circuit = QuantumCircuit::with_clbits(200, 3);
+        circuit
+            .i(0)
+            .x(1)
+            .y(2)
+            .z(3)
+            .s(4)
+            .sdg(5)
+            .t(6)
+            .tdg(7)
+            .p(8, 0.25)
+            .pdg(9, -1.5)
+            .rx(10, 1.0)
+            .ry(11, 2.0)
+            .rz(199, f64::MIN_POSITIVE)
+            .swap(0, 150)
+            .cu(
+                Instruction::RotationY {
+                    qubit: 1,
+                    phase: 0.5,
+                },
+                0,
+                1,
+            )
+            .custom("bell", bell.clone(), [3, 4])
+            .custom("nested", nested, [5, 6, 7])
+            .custom("bell", bell, [8, 9])
+            .barrier_on([0, 5, 130])
+            .measure([1, 2, 199], 0..3)
+            .reset(14)
+            .c_if([2, 0], 2, Instruction::Swap(15, 16))
+            .c_if(
+                1,
+                1,
+                Instruction::Custom {
+                    name: "t".to_owned(),
+                    circuit: t,
+                    input_qubits: vec![17],
+                },
+            );
+        circuit.add(Instruction::Initialize {
+            qubits: vec![12, 13],
+            amplitudes: vec![
+                Complex::new(0.5, 0.5),
+                Complex::from(0),
+                Complex::from(0),
+                Complex::new(0f64, -FRAC_1_SQRT_2),
+            ],
+        });
+        let flags = circuit.add_creg("flags", 2);
+        circuit.measure([3, 4], &flags);
+        circuit.set_layout(Layout::new((0..200).rev().collect(), 210));
+
+        circuit
+    }
+
+    #[test]
+    fn round_trips_circuits() {
+        let circuit = sample_circuit();
+        let bytes = circuit.to_bytes();
+
+        assert_eq!(QuantumCircuit::from_bytes(&bytes), Ok(circuit));
+        // Definition of the Bell gate is stored once.
+        let bell = [4, 0, 11, 0, 1];
+        assert_eq!(bytes.windows(bell.len()).filter(|w| *w == bell).count(), 1);
+
+        let empty = QuantumCircuit::new(0);
+        assert_eq!(QuantumCircuit::from_bytes(&empty.to_bytes()), Ok(empty));
+
+        let mut wide = QuantumCircuit::with_clbits(1, 130);
+        wide.c_if(0..130, u128::MAX, Instruction::PauliX(0));
+        assert_eq!(QuantumCircuit::from_bytes(&wide.to_bytes()), Ok(wide));
+
+        // Circuits encoded with version 1 don't have registers.
+        assert_eq!(
+            QuantumCircuit::from_bytes(b"QCRB\x01\x00\x02\x01\x00\x00"),
+            Ok(QuantumCircuit::with_clbits(2, 1))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let mut bytes = sample_circuit().to_bytes();
+
+        assert_eq!(
+            QuantumCircuit::from_bytes(b""),
+            Err(DecodeError::InvalidMagic)
+        );
+        assert_eq!(
+            QuantumCircuit::from_bytes(b"QCRB\x03"),
+            Err(DecodeError::UnsupportedVersion(3))
+        );
+        assert_eq!(
+            QuantumCircuit::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+
+        bytes.push(0);
+        assert_eq!(
+            QuantumCircuit::from_bytes(&bytes),
+            Err(DecodeError::TrailingBytes)
+        );
+
+        // Register of two qubits in a single-qubit circuit.
+        assert!(matches!(
+            QuantumCircuit::from_bytes(b"QCRB\x02\x00\x01\x00\x00\x00\x01\x01q\x00\x02\x00"),
+            Err(DecodeError::InvalidRegisters(..))
+        ));
+
+        // Hadamard gate on qubit 1 of a single-qubit circuit.
+        assert!(matches!(
+            QuantumCircuit::from_bytes(b"QCRB\x01\x00\x01\x00\x01\x04\x01\x00"),
+            Err(DecodeError::InvalidInstruction(..))
+        ));
+        assert_eq!(
+            QuantumCircuit::from_bytes(b"QCRB\x01\x00\x01\x00\x01\xff"),
+            Err(DecodeError::InvalidOpcode(0xff))
+        );
+        assert_eq!(
+            QuantumCircuit::from_bytes(b"QCRB\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01"),
+            Err(DecodeError::InvalidVarint)
+        );
+    }
+
+    #[test]
+    fn rejects_exponentially_large_circuits() {
+        // Every definition applies the previous one twice.
+        let mut bytes = b"QCRB\x01\x40".to_vec();
+        bytes.extend_from_slice(&[1, b'g', 1, 0, 1, 1, 0, 0]);
+        for i in 0..63 {
+            bytes.extend_from_slice(&[1, b'g', 1, 0, 2, 20, i, 1, 0, 20, i, 1, 0, 0]);
+        }
+        bytes.extend_from_slice(&[1, 0, 1, 20, 63, 1, 0, 0]);
+
+        assert_eq!(
+            QuantumCircuit::from_bytes(&bytes),
+            Err(DecodeError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn never_panics_on_corrupt_input() {
+        let bytes = sample_circuit().to_bytes();
+        let mut state = 0x2545f4914f6cdd1du64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for length in 0..bytes.len() {
+            assert!(QuantumCircuit::from_bytes(&bytes[..length]).is_err());
+        }
+
+        for _ in 0..20000 {
+            let mut corrupted = bytes.clone();
+            for _ in 0..1 + random() % 4 {
+                let position = random() as usize % corrupted.len();
+                corrupted[position] = random() as u8;
+            }
+
+            let _ = QuantumCircuit::from_bytes(&corrupted);
+        }
+
+        for _ in 0..20000 {
+            let mut random_bytes = b"QCRB\x01".to_vec();
+            random_bytes.extend((0..random() % 64).map(|_| random() as u8));
+
+            let _ = QuantumCircuit::from_bytes(&random_bytes);
+        }
+    }
+}
@@ -0,0 +1,654 @@
+//! Implements chainable methods for building quantum circuits.
+//!
+//! Every method adds corresponding [`Instruction`] into the circuit and returns the
+//! circuit back, so that calls can be chained. Single-qubit gates and measurements
+//! accept anything implementing [`QubitSelection`], so that the gate can be applied
+//! to many qubits at once:
+//!
+//! ```
+//! use quantum_crab::quantum_circuit::{QuantumCircuit, Instruction};
+//!
+//! let mut circuit = QuantumCircuit::with_clbits(3, 3);
+//! circuit.h(0..3).cx(0, 1).rz(2, 0.5).measure(0..3, 0..3);
+//!
+//! assert_eq!(circuit.instructions()[1], Instruction::Hadamard(1));
+//! assert_eq!(circuit.instructions().len(), 8);
+//! ```
+//!
+//! Just like [`QuantumCircuit::add`], methods panic if the instruction is not valid
+//! for the circuit.
+//!
+//! For circuits known upfront, [`circuit!`](crate::circuit!) macro provides even more
+//! compact syntax.
+use crate::{
+    complex::Complex,
+    quantum_circuit::{Instruction, QuantumCircuit},
+    register::Register,
+};
+use std::ops::{Range, RangeInclusive};
+
+/// Represents a set of qubits (or classical bits) an instruction is applied to.
+pub trait QubitSelection {
+    /// Returns indices of selected qubits in order.
+    fn into_qubits(self) -> Vec<usize>;
+}
+
+impl QubitSelection for usize {
+    fn into_qubits(self) -> Vec<usize> {
+        vec![self]
+    }
+}
+
+impl QubitSelection for Range<usize> {
+    fn into_qubits(self) -> Vec<usize> {
+        self.collect()
+    }
+}
+
+impl QubitSelection for RangeInclusive<usize> {
+    fn into_qubits(self) -> Vec<usize> {
+        self.collect()
+    }
+}
+
+impl QubitSelection for Vec<usize> {
+    fn into_qubits(self) -> Vec<usize> {
+        self
+    }
+}
+
+impl QubitSelection for &[usize] {
+    fn into_qubits(self) -> Vec<usize> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> QubitSelection for [usize; N] {
+    fn into_qubits(self) -> Vec<usize> {
+        self.to_vec()
+    }
+}
+
+impl QubitSelection for &Register {
+    fn into_qubits(self) -> Vec<usize> {
+        self.indices().collect()
+    }
+}
+
+impl QuantumCircuit {
+    /// Applies the gate constructed by `gate` to every selected qubit.
+    fn broadcast<Q, F>(&mut self, qubits: Q, gate: F) -> &mut Self
+    where
+        Q: QubitSelection,
+        F: Fn(usize) -> Instruction,
+    {
+        for qubit in qubits.into_qubits() {
+            self.add(gate(qubit));
+        }
+
+        self
+    }
+
+    /// Adds [`Instruction::Identity`] gates.
+    pub fn i<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::Identity)
+    }
+
+    /// Adds [`Instruction::PauliX`] gates.
+    pub fn x<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::PauliX)
+    }
+
+    /// Adds [`Instruction::PauliY`] gates.
+    pub fn y<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::PauliY)
+    }
+
+    /// Adds [`Instruction::PauliZ`] gates.
+    pub fn z<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::PauliZ)
+    }
+
+    /// Adds [`Instruction::Hadamard`] gates.
+    pub fn h<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::Hadamard)
+    }
+
+    /// Adds [`Instruction::Phase`] gates.
+    pub fn p<Q: QubitSelection>(&mut self, qubits: Q, phase: f64) -> &mut Self {
+        self.broadcast(qubits, |qubit| Instruction::Phase { qubit, phase })
+    }
+
+    /// Adds [`Instruction::PhaseDagger`] gates.
+    pub fn pdg<Q: QubitSelection>(&mut self, qubits: Q, phase: f64) -> &mut Self {
+        self.broadcast(qubits, |qubit| Instruction::PhaseDagger { qubit, phase })
+    }
+
+    /// Adds [`Instruction::T`] gates.
+    pub fn t<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::T)
+    }
+
+    /// Adds [`Instruction::TDagger`] gates.
+    pub fn tdg<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::TDagger)
+    }
+
+    /// Adds [`Instruction::S`] gates.
+    pub fn s<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::S)
+    }
+
+    /// Adds [`Instruction::SDagger`] gates.
+    pub fn sdg<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::SDagger)
+    }
+
+    /// Adds [`Instruction::RotationX`] gates.
+    pub fn rx<Q: QubitSelection>(&mut self, qubits: Q, phase: f64) -> &mut Self {
+        self.broadcast(qubits, |qubit| Instruction::RotationX { qubit, phase })
+    }
+
+    /// Adds [`Instruction::RotationY`] gates.
+    pub fn ry<Q: QubitSelection>(&mut self, qubits: Q, phase: f64) -> &mut Self {
+        self.broadcast(qubits, |qubit| Instruction::RotationY { qubit, phase })
+    }
+
+    /// Adds [`Instruction::RotationZ`] gates.
+    pub fn rz<Q: QubitSelection>(&mut self, qubits: Q, phase: f64) -> &mut Self {
+        self.broadcast(qubits, |qubit| Instruction::RotationZ { qubit, phase })
+    }
+
+    /// Adds [`Instruction::ControlledNot`] gate.
+    pub fn cx(&mut self, control: usize, target: usize) -> &mut Self {
+        self.add(Instruction::ControlledNot { control, target });
+        self
+    }
+
+    /// Adds [`Instruction::ControlledU`] gate.
+    pub fn cu(&mut self, gate: Instruction, control: usize, target: usize) -> &mut Self {
+        self.add(Instruction::ControlledU {
+            gate: Box::new(gate),
+            control,
+            target,
+        });
+        self
+    }
+
+    /// Adds [`Instruction::Toffoli`] gate.
+    pub fn ccx(&mut self, control1: usize, control2: usize, target: usize) -> &mut Self {
+        self.add(Instruction::Toffoli {
+            control1,
+            control2,
+            target,
+        });
+        self
+    }
+
+    /// Adds [`Instruction::Swap`] gate.
+    pub fn swap(&mut self, qubit1: usize, qubit2: usize) -> &mut Self {
+        self.add(Instruction::Swap(qubit1, qubit2));
+        self
+    }
+
+    /// Adds [`Instruction::Measure`] instructions, measuring `i`-th selected qubit into
+    /// `i`-th selected classical bit.
+    pub fn measure<Q, C>(&mut self, qubits: Q, clbits: C) -> &mut Self
+    where
+        Q: QubitSelection,
+        C: QubitSelection,
+    {
+        let (qubits, clbits) = (qubits.into_qubits(), clbits.into_qubits());
+        assert_eq!(
+            qubits.len(),
+            clbits.len(),
+            "Amount of measured qubits doesn't match the amount of classical bits"
+        );
+
+        for (qubit, clbit) in qubits.into_iter().zip(clbits) {
+            self.add(Instruction::Measure { qubit, clbit });
+        }
+
+        self
+    }
+
+    /// Measures qubit `i` into classical bit `i` for every qubit in the circuit.
+    pub fn measure_all(&mut self) -> &mut Self {
+        self.measure(0..self.qubits(), 0..self.qubits())
+    }
+
+    /// Adds [`Instruction::Reset`] instructions.
+    pub fn reset<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.broadcast(qubits, Instruction::Reset)
+    }
+
+    /// Adds [`Instruction::Initialize`], preparing the selected qubits in the state
+    /// with the normalized amplitudes, where the first selected qubit is the least
+    /// significant bit of the basis state index.
+    pub fn initialize<Q: QubitSelection>(
+        &mut self,
+        qubits: Q,
+        amplitudes: Vec<Complex>,
+    ) -> &mut Self {
+        self.add(Instruction::Initialize {
+            qubits: qubits.into_qubits(),
+            amplitudes,
+        });
+        self
+    }
+
+    /// Adds [`Instruction::If`], executing the instruction only if the selected
+    /// classical bits (least significant first) hold the value.
+    ///
+    /// ```
+    /// use quantum_crab::quantum_circuit::{QuantumCircuit, Instruction};
+    ///
+    /// let mut circuit = QuantumCircuit::with_clbits(2, 2);
+    /// circuit.measure(0..2, 0..2).c_if(0..2, 3, Instruction::PauliX(1));
+    ///
+    /// assert_eq!(circuit.instructions()[2].clbits(), vec![0, 1]);
+    /// ```
+    pub fn c_if<C: QubitSelection>(
+        &mut self,
+        register: C,
+        value: u128,
+        instruction: Instruction,
+    ) -> &mut Self {
+        self.add(Instruction::If {
+            register: register.into_qubits(),
+            value,
+            instruction: Box::new(instruction),
+        });
+        self
+    }
+
+    /// Adds [`Instruction::Barrier`] across all qubits of the circuit.
+    pub fn barrier(&mut self) -> &mut Self {
+        self.barrier_on(0..self.qubits())
+    }
+
+    /// Adds [`Instruction::Barrier`] across the selected qubits.
+    pub fn barrier_on<Q: QubitSelection>(&mut self, qubits: Q) -> &mut Self {
+        self.add(Instruction::Barrier(qubits.into_qubits()));
+        self
+    }
+
+    /// Adds [`Instruction::Custom`] gate.
+    pub fn custom<N, Q>(&mut self, name: N, circuit: QuantumCircuit, input_qubits: Q) -> &mut Self
+    where
+        N: Into<String>,
+        Q: QubitSelection,
+    {
+        self.add(Instruction::Custom {
+            name: name.into(),
+            circuit,
+            input_qubits: input_qubits.into_qubits(),
+        });
+        self
+    }
+}
+
+/// Macro used to construct a quantum circuit out of compact description.
+///
+/// The macro takes the amount of qubits (optionally followed by the amount of
+/// classical bits) and a list of instructions separated by `;`:
+///
+/// ```
+/// use quantum_crab::{circuit, quantum_circuit::{QuantumCircuit, Instruction}};
+/// use std::f64::consts::PI;
+///
+/// let circuit = circuit!(3; h 0; cx 0 1; rz(pi/4) 2; measure 0 -> 0);
+///
+/// let mut expected = QuantumCircuit::with_clbits(3, 1);
+/// expected.h(0).cx(0, 1).rz(2, PI / 4f64).measure(0, 0);
+/// assert_eq!(circuit, expected);
+/// ```
+///
+/// Supported instructions are `id q`, `x q`, `y q`, `z q`, `h q`, `s q`, `sdg q`,
+/// `t q`, `tdg q`, `p(angle) q`, `pdg(angle) q`, `rx(angle) q`, `ry(angle) q`,
+/// `rz(angle) q`, `cx control target`, `ccx control1 control2 target`, `swap q1 q2`,
+/// `measure q -> c`, `reset q` and `barrier` (optionally followed by qubits). Qubits are single
+/// tokens: literals, variables or expressions in parentheses.
+///
+/// Angles are regular expressions, where `pi` stands for [`std::f64::consts::PI`] and
+/// integer literals are treated as floating point numbers.
+///
+/// If the amount of classical bits is omitted, the circuit gets as many classical bits
+/// as measurements need. Instructions are validated just like in
+/// [`QuantumCircuit::add`], malformed instructions are reported at compile time:
+///
+/// ```compile_fail
+/// use quantum_crab::circuit;
+///
+/// let circuit = circuit!(2; h 0; cx 0; measure 0 -> 0);
+/// ```
+#[macro_export]
+macro_rules! circuit {
+    (@gates $circuit:ident;) => {};
+    (@gates $circuit:ident; ; $($rest:tt)*) => {
+        $crate::circuit!(@gates $circuit; $($rest)*)
+    };
+    (@gates $circuit:ident; id $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; Identity($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; x $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; PauliX($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; y $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; PauliY($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; z $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; PauliZ($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; h $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; Hadamard($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; s $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; S($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; sdg $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; SDagger($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; t $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; T($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; tdg $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; TDagger($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; p($($angle:tt)+) $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@rotation $circuit; Phase [$($angle)+] $qubit; $($rest)*)
+    };
+    (@gates $circuit:ident; pdg($($angle:tt)+) $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@rotation $circuit; PhaseDagger [$($angle)+] $qubit; $($rest)*)
+    };
+    (@gates $circuit:ident; rx($($angle:tt)+) $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@rotation $circuit; RotationX [$($angle)+] $qubit; $($rest)*)
+    };
+    (@gates $circuit:ident; ry($($angle:tt)+) $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@rotation $circuit; RotationY [$($angle)+] $qubit; $($rest)*)
+    };
+    (@gates $circuit:ident; rz($($angle:tt)+) $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@rotation $circuit; RotationZ [$($angle)+] $qubit; $($rest)*)
+    };
+    (@gates $circuit:ident; cx $control:tt $target:tt; $($rest:tt)*) => {
+        $crate::circuit!(
+            @push $circuit; ControlledNot { control: $control, target: $target }; $($rest)*
+        )
+    };
+    (@gates $circuit:ident; ccx $control1:tt $control2:tt $target:tt; $($rest:tt)*) => {
+        $crate::circuit!(
+            @push $circuit;
+            Toffoli { control1: $control1, control2: $control2, target: $target };
+            $($rest)*
+        )
+    };
+    (@gates $circuit:ident; swap $qubit1:tt $qubit2:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; Swap($qubit1, $qubit2); $($rest)*)
+    };
+    (@gates $circuit:ident; measure $qubit:tt -> $clbit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; Measure { qubit: $qubit, clbit: $clbit }; $($rest)*)
+    };
+    (@gates $circuit:ident; reset $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; Reset($qubit); $($rest)*)
+    };
+    (@gates $circuit:ident; barrier; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; Barrier((0..$circuit.qubits).collect()); $($rest)*)
+    };
+    (@gates $circuit:ident; barrier $($rest:tt)*) => {
+        $crate::circuit!(@barrier $circuit; [] $($rest)*)
+    };
+    (@gates $circuit:ident; $($rest:tt)*) => {
+        $crate::circuit!(@error [] $($rest)*)
+    };
+    (@barrier $circuit:ident; [$($qubit:tt)*] ; $($rest:tt)*) => {
+        $crate::circuit!(@push $circuit; Barrier(vec![$($qubit),*]); $($rest)*)
+    };
+    (@barrier $circuit:ident; [$($qubit:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::circuit!(@barrier $circuit; [$($qubit)* $next] $($rest)*)
+    };
+    (@rotation $circuit:ident; $gate:ident [$($angle:tt)+] $qubit:tt; $($rest:tt)*) => {
+        $crate::circuit!(
+            @push $circuit;
+            $gate { qubit: $qubit, phase: $crate::circuit!(@angle [] $($angle)+) };
+            $($rest)*
+        )
+    };
+    (@push $circuit:ident; $($instruction:tt)::+ $(($($arguments:tt)*))? $({$($fields:tt)*})?; $($rest:tt)*) => {
+        $circuit.instructions.push(
+            $crate::quantum_circuit::Instruction::$($instruction)::+ $(($($arguments)*))? $({$($fields)*})?
+        );
+        $crate::circuit!(@gates $circuit; $($rest)*)
+    };
+    (@angle [$($out:tt)*]) => {
+        ($($out)*)
+    };
+    (@angle [$($out:tt)*] pi $($rest:tt)*) => {
+        $crate::circuit!(@angle [$($out)* ::std::f64::consts::PI] $($rest)*)
+    };
+    (@angle [$($out:tt)*] ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::circuit!(@angle [$($out)* ($crate::circuit!(@angle [] $($inner)*))] $($rest)*)
+    };
+    (@angle [$($out:tt)*] - $($rest:tt)*) => {
+        $crate::circuit!(@angle [$($out)* -] $($rest)*)
+    };
+    (@angle [$($out:tt)*] $literal:literal $($rest:tt)*) => {
+        $crate::circuit!(@angle [$($out)* ($literal as f64)] $($rest)*)
+    };
+    (@angle [$($out:tt)*] $token:tt $($rest:tt)*) => {
+        $crate::circuit!(@angle [$($out)* $token] $($rest)*)
+    };
+    (@error [$($statement:tt)*] ; $($rest:tt)*) => {
+        compile_error!(concat!(
+            "malformed instruction in circuit!: `",
+            stringify!($($statement)*),
+            "`"
+        ))
+    };
+    (@error [$($statement:tt)*] $token:tt $($rest:tt)*) => {
+        $crate::circuit!(@error [$($statement)* $token] $($rest)*)
+    };
+    ($qubits:expr, $clbits:expr; $($body:tt)*) => {{
+        let mut circuit = $crate::circuit_builder::CircuitDescription {
+            qubits: $qubits,
+            clbits: Some($clbits),
+            instructions: Vec::new(),
+        };
+        $crate::circuit!(@gates circuit; $($body)*;);
+        circuit.build()
+    }};
+    ($qubits:expr; $($body:tt)*) => {{
+        let mut circuit = $crate::circuit_builder::CircuitDescription {
+            qubits: $qubits,
+            clbits: None,
+            instructions: Vec::new(),
+        };
+        $crate::circuit!(@gates circuit; $($body)*;);
+        circuit.build()
+    }};
+    ($qubits:expr) => {
+        $crate::quantum_circuit::QuantumCircuit::new($qubits)
+    };
+}
+
+/// Instructions collected by the [`circuit!`] macro before the circuit is built.
+#[doc(hidden)]
+pub struct CircuitDescription {
+    pub qubits: usize,
+    pub clbits: Option<usize>,
+    pub instructions: Vec<Instruction>,
+}
+
+impl CircuitDescription {
+    /// Builds the circuit, validating every instruction.
+    pub fn build(self) -> QuantumCircuit {
+        let clbits = self.clbits.unwrap_or_else(|| {
+            self.instructions
+                .iter()
+                .flat_map(Instruction::clbits)
+                .max()
+                .map_or(0, |clbit| clbit + 1)
+        });
+
+        let mut circuit = QuantumCircuit::with_clbits(self.qubits, clbits);
+        for instruction in self.instructions {
+            circuit.add(instruction);
+        }
+
+        circuit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        complex::Complex,
+        quantum_circuit::{Instruction, QuantumCircuit},
+    };
+    use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+    #[test]
+    fn builds_same_circuit_as_add() {
+        let mut expected = QuantumCircuit::with_clbits(3, 2);
+        expected.add(Instruction::Hadamard(0));
+        expected.add(Instruction::ControlledNot {
+            control: 0,
+            target: 1,
+        });
+        expected.add(Instruction::RotationX {
+            qubit: 2,
+            phase: 0.3,
+        });
+        expected.add(Instruction::Toffoli {
+            control1: 0,
+            control2: 1,
+            target: 2,
+        });
+        expected.add(Instruction::Swap(1, 2));
+        expected.add(Instruction::Barrier(vec![0, 1, 2]));
+        expected.add(Instruction::Measure { qubit: 0, clbit: 1 });
+
+        let mut circuit = QuantumCircuit::with_clbits(3, 2);
+        circuit
+            .h(0)
+            .cx(0, 1)
+            .rx(2, 0.3)
+            .ccx(0, 1, 2)
+            .swap(1, 2)
+            .barrier()
+            .measure(0, 1);
+
+        assert_eq!(circuit, expected);
+        assert_eq!(circuit.depth(), 4);
+        assert_eq!(circuit.two_qubit_gate_count(), 2);
+    }
+
+    #[test]
+    fn broadcasts_over_qubit_selections() {
+        let mut circuit = QuantumCircuit::with_clbits(4, 4);
+        circuit.x(1..=2).t([0, 3]).sdg(vec![2]).measure_all();
+
+        assert_eq!(
+            &circuit.instructions()[..5],
+            &[
+                Instruction::PauliX(1),
+                Instruction::PauliX(2),
+                Instruction::T(0),
+                Instruction::T(3),
+                Instruction::SDagger(2),
+            ]
+        );
+        assert_eq!(circuit.count_ops()["Measure"], 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't match the amount of classical bits")]
+    fn measure_requires_matching_selections() {
+        QuantumCircuit::with_clbits(2, 2).measure(0..2, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid input qubit")]
+    fn broadcast_validates_qubits() {
+        QuantumCircuit::new(2).h(0..3);
+    }
+
+    #[test]
+    fn initializes_selected_qubits() {
+        let amplitudes = vec![
+            Complex::from(FRAC_1_SQRT_2),
+            Complex::from(0),
+            Complex::from(0),
+            Complex::new(0f64, FRAC_1_SQRT_2),
+        ];
+
+        let mut circuit = QuantumCircuit::new(3);
+        circuit.initialize([2, 0], amplitudes.clone()).h(1);
+
+        assert_eq!(
+            circuit.instructions()[0],
+            Instruction::Initialize {
+                qubits: vec![2, 0],
+                amplitudes
+            }
+        );
+        assert_eq!(circuit.instructions().len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "not normalized")]
+    fn initialize_validates_amplitudes() {
+        QuantumCircuit::new(1).initialize(0, vec![Complex::from(1), Complex::from(1)]);
+    }
+
+    #[test]
+    fn circuit_macro_expands_every_instruction() {
+        let theta = 0.25;
+        let last = 3;
+        let circuit = circuit!(4, 2;
+            id 0; x 1; y 2; z 3; h 0; s 1; sdg 2; t 3; tdg 0;
+            p(pi) 1; pdg(2 * theta) 2; rx(-pi/2) 3; ry(theta) 0; rz(pi * (1 + 1) / 4) (last - 1);
+            cx 0 1; ccx 0 1 2; swap 2 last;
+            barrier; barrier 0 1;
+            measure 0 -> 1; reset 0;
+        );
+
+        let mut expected = QuantumCircuit::with_clbits(4, 2);
+        expected
+            .i(0)
+            .x(1)
+            .y(2)
+            .z(3)
+            .h(0)
+            .s(1)
+            .sdg(2)
+            .t(3)
+            .tdg(0)
+            .p(1, PI)
+            .pdg(2, 0.5)
+            .rx(3, -PI / 2f64)
+            .ry(0, 0.25)
+            .rz(2, PI * 2f64 / 4f64)
+            .cx(0, 1)
+            .ccx(0, 1, 2)
+            .swap(2, 3)
+            .barrier()
+            .barrier_on(0..2)
+            .measure(0, 1)
+            .reset(0);
+
+        assert_eq!(circuit, expected);
+    }
+
+    #[test]
+    fn circuit_macro_infers_classical_bits() {
+        assert_eq!(circuit!(2).clbits(), 0);
+        assert_eq!(circuit!(2; h 0; cx 0 1).clbits(), 0);
+        assert_eq!(circuit!(2; measure 0 -> 0; measure 1 -> 3).clbits(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid input qubit")]
+    fn circuit_macro_validates_instructions() {
+        circuit!(2; cx 0 2);
+    }
+}
@@ -0,0 +1,479 @@
+//! Implements directed acyclic graph representation of quantum circuits.
+//!
+//! Every instruction of the circuit becomes a node of the graph. Nodes are connected
+//! by edges along qubit and classical bit wires: there is an edge from node `a` to
+//! node `b` labeled with wire `w`, if `b` is the first instruction that uses the wire
+//! `w` after `a`.
+use crate::quantum_circuit::{Instruction, QuantumCircuit};
+use std::fmt::{self, Display};
+
+/// Index of the node in the [`CircuitDag`].
+pub type NodeIndex = usize;
+
+/// Represents wire of the circuit, that connects nodes of the [`CircuitDag`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Wire {
+    /// The qubit wire.
+    Qubit(usize),
+
+    /// The classical bit wire.
+    Clbit(usize),
+}
+
+/// Represents node of the [`CircuitDag`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DagNode {
+    instruction: Instruction,
+    in_edges: Vec<(Wire, NodeIndex)>,
+    out_edges: Vec<(Wire, NodeIndex)>,
+}
+
+impl DagNode {
+    /// The instruction in the node.
+    #[inline]
+    pub const fn instruction(&self) -> &Instruction {
+        &self.instruction
+    }
+
+    /// Incoming edges of the node, as pairs of wire and the source node.
+    #[inline]
+    pub fn in_edges(&self) -> &[(Wire, NodeIndex)] {
+        &self.in_edges
+    }
+
+    /// Outgoing edges of the node, as pairs of wire and the target node.
+    #[inline]
+    pub fn out_edges(&self) -> &[(Wire, NodeIndex)] {
+        &self.out_edges
+    }
+
+    /// Nodes the node directly depends on.
+    pub fn predecessors(&self) -> Vec<NodeIndex> {
+        unique_nodes(&self.in_edges)
+    }
+
+    /// Nodes directly depending on the node.
+    pub fn successors(&self) -> Vec<NodeIndex> {
+        unique_nodes(&self.out_edges)
+    }
+}
+
+/// Returns sorted list of nodes in the edges without duplicates.
+fn unique_nodes(edges: &[(Wire, NodeIndex)]) -> Vec<NodeIndex> {
+    let mut nodes: Vec<NodeIndex> = edges.iter().map(|(_, node)| *node).collect();
+    nodes.sort_unstable();
+    nodes.dedup();
+    nodes
+}
+
+/// Represents quantum circuit as a directed acyclic graph of instructions.
+///
+/// ```
+/// use quantum_crab::{
+///   circuit_dag::CircuitDag,
+///   quantum_circuit::{QuantumCircuit, Instruction}
+/// };
+///
+/// let mut circuit = QuantumCircuit::new(2);
+/// circuit.add(Instruction::Hadamard(0));
+/// circuit.add(Instruction::PauliX(1));
+/// circuit.add(Instruction::ControlledNot { control: 0, target: 1 });
+///
+/// let dag = CircuitDag::from_circuit(&circuit);
+/// assert_eq!(dag.front_layer(), vec![0, 1]);
+/// assert_eq!(dag.node(2).unwrap().predecessors(), vec![0, 1]);
+/// assert_eq!(dag.to_circuit(), circuit);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitDag {
+    /// Circuit without instructions, that keeps layout and registers of the source
+    /// circuit.
+    circuit: QuantumCircuit,
+    nodes: Vec<Option<DagNode>>,
+    last_nodes: Vec<Option<NodeIndex>>,
+}
+
+impl CircuitDag {
+    /// Constructs empty graph with a concrete amount of qubits and classical bits.
+    pub fn new(qubits: usize, clbits: usize) -> CircuitDag {
+        CircuitDag {
+            circuit: QuantumCircuit::with_clbits(qubits, clbits),
+            nodes: Vec::new(),
+            last_nodes: vec![None; qubits + clbits],
+        }
+    }
+
+    /// Constructs graph out of instructions of the circuit.
+    pub fn from_circuit(circuit: &QuantumCircuit) -> CircuitDag {
+        let mut dag = CircuitDag {
+            circuit: circuit.empty_copy(),
+            nodes: Vec::new(),
+            last_nodes: vec![None; circuit.width()],
+        };
+
+        // Instructions of the circuit are already validated.
+        for instruction in circuit.instructions() {
+            dag.insert(instruction.clone());
+        }
+
+        dag
+    }
+
+    /// Converts the graph back into the circuit, with instructions placed in
+    /// topological order. Layout and registers of the circuit, that the graph was
+    /// constructed from, are kept.
+    pub fn to_circuit(&self) -> QuantumCircuit {
+        let mut circuit = self.circuit.clone();
+
+        for index in self.topological_order() {
+            circuit.add(self.nodes[index].as_ref().unwrap().instruction.clone());
+        }
+
+        circuit
+    }
+
+    /// Amount of qubits in the graph.
+    #[inline]
+    pub const fn qubits(&self) -> usize {
+        self.circuit.qubits()
+    }
+
+    /// Amount of classical bits in the graph.
+    #[inline]
+    pub const fn clbits(&self) -> usize {
+        self.circuit.clbits()
+    }
+
+    /// Appends instruction at the end of the graph and returns index of the new node.
+    /// Instructions are checked like in [`QuantumCircuit::add`], so those acting on
+    /// invalid or repeated qubits and classical bits are rejected.
+    pub fn push(&mut self, instruction: Instruction) -> Result<NodeIndex, InvalidInstructionError> {
+        self.circuit
+            .check_instruction(&instruction, None)
+            .map_err(|message| InvalidInstructionError { message })?;
+
+        Ok(self.insert(instruction))
+    }
+
+    /// Appends already validated instruction at the end of the graph.
+    fn insert(&mut self, instruction: Instruction) -> NodeIndex {
+        let index = self.nodes.len();
+        let wires = self.wires(&instruction);
+
+        let mut in_edges = Vec::new();
+        for wire in wires {
+            let slot = self.wire_slot(wire);
+
+            if let Some(previous) = self.last_nodes[slot] {
+                in_edges.push((wire, previous));
+                self.nodes[previous]
+                    .as_mut()
+                    .unwrap()
+                    .out_edges
+                    .push((wire, index));
+            }
+
+            self.last_nodes[slot] = Some(index);
+        }
+
+        self.nodes.push(Some(DagNode {
+            instruction,
+            in_edges,
+            out_edges: Vec::new(),
+        }));
+
+        index
+    }
+
+    /// Removes the node from the graph, connecting its predecessors directly to its
+    /// successors along the same wires. Returns the instruction of the removed node.
+    pub fn remove_node(&mut self, index: NodeIndex) -> Option<Instruction> {
+        let node = self.nodes.get_mut(index)?.take()?;
+
+        for (wire, source) in &node.in_edges {
+            let source = self.nodes[*source].as_mut().unwrap();
+            source
+                .out_edges
+                .retain(|(w, target)| !(w == wire && *target == index));
+        }
+
+        for (wire, target) in &node.out_edges {
+            let target = self.nodes[*target].as_mut().unwrap();
+            target
+                .in_edges
+                .retain(|(w, source)| !(w == wire && *source == index));
+        }
+
+        for (wire, source) in &node.in_edges {
+            match node.out_edges.iter().find(|(w, _)| w == wire) {
+                Some((_, target)) => {
+                    self.nodes[*source]
+                        .as_mut()
+                        .unwrap()
+                        .out_edges
+                        .push((*wire, *target));
+                    self.nodes[*target]
+                        .as_mut()
+                        .unwrap()
+                        .in_edges
+                        .push((*wire, *source));
+                }
+                None => {
+                    let slot = self.wire_slot(*wire);
+                    self.last_nodes[slot] = Some(*source);
+                }
+            }
+        }
+
+        for wire in self.wires(&node.instruction) {
+            let slot = self.wire_slot(wire);
+            if self.last_nodes[slot] == Some(index) {
+                self.last_nodes[slot] = None;
+            }
+        }
+
+        Some(node.instruction)
+    }
+
+    /// Node with a given index, if it wasn't removed.
+    #[inline]
+    pub fn node(&self, index: NodeIndex) -> Option<&DagNode> {
+        self.nodes.get(index)?.as_ref()
+    }
+
+    /// Indices of all nodes in the graph, in order of insertion.
+    pub fn node_indices(&self) -> Vec<NodeIndex> {
+        (0..self.nodes.len())
+            .filter(|index| self.nodes[*index].is_some())
+            .collect()
+    }
+
+    /// Amount of nodes in the graph.
+    pub fn node_count(&self) -> usize {
+        self.nodes.iter().filter(|node| node.is_some()).count()
+    }
+
+    /// All edges in the graph, as triples of source node, target node and wire.
+    pub fn edges(&self) -> Vec<(NodeIndex, NodeIndex, Wire)> {
+        self.node_indices()
+            .into_iter()
+            .flat_map(|source| {
+                self.nodes[source]
+                    .as_ref()
+                    .unwrap()
+                    .out_edges
+                    .iter()
+                    .map(move |(wire, target)| (source, *target, *wire))
+            })
+            .collect()
+    }
+
+    /// Nodes without predecessors, i.e. instructions that can be executed first.
+    pub fn front_layer(&self) -> Vec<NodeIndex> {
+        self.node_indices()
+            .into_iter()
+            .filter(|index| self.nodes[*index].as_ref().unwrap().in_edges.is_empty())
+            .collect()
+    }
+
+    /// Nodes ordered so that every node comes after all of its predecessors. Nodes
+    /// go layer by layer (see [`CircuitDag::layers`]).
+    pub fn topological_order(&self) -> Vec<NodeIndex> {
+        self.layers().into_iter().flatten().collect()
+    }
+
+    /// Splits nodes into layers: every layer consists of nodes, whose predecessors
+    /// are all in the previous layers.
+    pub fn layers(&self) -> Vec<Vec<NodeIndex>> {
+        let mut remaining_in_degrees: Vec<usize> = self
+            .nodes
+            .iter()
+            .map(|node| node.as_ref().map_or(0, |node| node.predecessors().len()))
+            .collect();
+
+        let mut layers = Vec::new();
+        let mut layer = self.front_layer();
+
+        while !layer.is_empty() {
+            let mut next_layer = Vec::new();
+
+            for index in &layer {
+                for successor in self.nodes[*index].as_ref().unwrap().successors() {
+                    remaining_in_degrees[successor] -= 1;
+
+                    if remaining_in_degrees[successor] == 0 {
+                        next_layer.push(successor);
+                    }
+                }
+            }
+
+            next_layer.sort_unstable();
+            layers.push(layer);
+            layer = next_layer;
+        }
+
+        layers
+    }
+
+    /// Wires used by the instruction.
+    fn wires(&self, instruction: &Instruction) -> Vec<Wire> {
+        instruction
+            .qubits()
+            .into_iter()
+            .map(Wire::Qubit)
+            .chain(instruction.clbits().into_iter().map(Wire::Clbit))
+            .collect()
+    }
+
+    /// Index of the wire in [`CircuitDag::last_nodes`].
+    fn wire_slot(&self, wire: Wire) -> usize {
+        match wire {
+            Wire::Qubit(qubit) => qubit,
+            Wire::Clbit(clbit) => self.qubits() + clbit,
+        }
+    }
+}
+
+/// Error returned by [`CircuitDag::push`], when the instruction can't be added into
+/// the graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidInstructionError {
+    /// Reason why the instruction is invalid.
+    pub message: String,
+}
+
+impl Display for InvalidInstructionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for InvalidInstructionError {}
+
+impl From<&QuantumCircuit> for CircuitDag {
+    fn from(circuit: &QuantumCircuit) -> CircuitDag {
+        CircuitDag::from_circuit(circuit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit_dag::{CircuitDag, Wire},
+        layout::Layout,
+        quantum_circuit::{Instruction, QuantumCircuit},
+    };
+
+    fn bell_circuit() -> QuantumCircuit {
+        let mut circuit = QuantumCircuit::with_clbits(3, 2);
+        circuit.add(Instruction::Hadamard(0));
+        circuit.add(Instruction::PauliX(2));
+        circuit.add(Instruction::ControlledNot {
+            control: 0,
+            target: 1,
+        });
+        circuit.add(Instruction::Measure { qubit: 0, clbit: 0 });
+        circuit.add(Instruction::Measure { qubit: 1, clbit: 0 });
+        circuit
+    }
+
+    #[test]
+    fn builds_wire_edges() {
+        let dag = CircuitDag::from_circuit(&bell_circuit());
+
+        assert_eq!(dag.node_count(), 5);
+        assert_eq!(
+            dag.edges(),
+            vec![
+                (0, 2, Wire::Qubit(0)),
+                (2, 3, Wire::Qubit(0)),
+                (2, 4, Wire::Qubit(1)),
+                (3, 4, Wire::Clbit(0)),
+            ]
+        );
+        assert_eq!(dag.node(4).unwrap().predecessors(), vec![2, 3]);
+    }
+
+    #[test]
+    fn connects_classical_bits_of_custom_gates() {
+        let mut inner = QuantumCircuit::with_clbits(1, 1);
+        inner.measure(0, 0);
+
+        let mut circuit = QuantumCircuit::with_clbits(2, 1);
+        circuit.custom("measure", inner, 0).measure(1, 0);
+
+        let dag = CircuitDag::from_circuit(&circuit);
+        assert_eq!(dag.edges(), vec![(0, 1, Wire::Clbit(0))]);
+        assert_eq!(dag.layers(), vec![vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn iterates_layers_in_topological_order() {
+        let dag = CircuitDag::from_circuit(&bell_circuit());
+
+        assert_eq!(dag.front_layer(), vec![0, 1]);
+        assert_eq!(dag.layers(), vec![vec![0, 1], vec![2], vec![3], vec![4]]);
+        assert_eq!(dag.topological_order(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(dag.to_circuit(), bell_circuit());
+    }
+
+    #[test]
+    fn removing_node_reconnects_wires() {
+        let mut dag = CircuitDag::from_circuit(&bell_circuit());
+
+        assert_eq!(
+            dag.remove_node(2),
+            Some(Instruction::ControlledNot {
+                control: 0,
+                target: 1
+            })
+        );
+        assert_eq!(dag.remove_node(2), None);
+        assert_eq!(dag.front_layer(), vec![0, 1]);
+        assert_eq!(dag.node(3).unwrap().predecessors(), vec![0]);
+        assert_eq!(dag.node(4).unwrap().predecessors(), vec![3]);
+
+        assert_eq!(dag.push(Instruction::Hadamard(0)), Ok(5));
+        assert_eq!(dag.push(Instruction::Hadamard(1)), Ok(6));
+        assert_eq!(dag.node(5).unwrap().predecessors(), vec![3]);
+        assert_eq!(dag.node(6).unwrap().predecessors(), vec![4]);
+    }
+
+    #[test]
+    fn rejects_invalid_instructions() {
+        let mut dag = CircuitDag::new(2, 1);
+
+        for (instruction, message) in [
+            (Instruction::Hadamard(2), "Invalid input qubit"),
+            (
+                Instruction::ControlledNot {
+                    control: 1,
+                    target: 1,
+                },
+                "Repeated input qubit",
+            ),
+            (
+                Instruction::Measure { qubit: 0, clbit: 1 },
+                "Invalid classical bit",
+            ),
+        ] {
+            let error = dag.push(instruction).unwrap_err();
+            assert!(error.message.contains(message), "{}", error);
+        }
+        assert_eq!(dag.node_count(), 0);
+    }
+
+    #[test]
+    fn keeps_circuit_metadata() {
+        let mut circuit = QuantumCircuit::new(0);
+        let data = circuit.add_qreg("data", 2);
+        let result = circuit.add_creg("result", 2);
+        circuit.set_layout(Layout::new(vec![2, 0], 3));
+        circuit.h(data.at(0)).cx(0, 1).measure(&data, &result);
+
+        let dag = CircuitDag::from_circuit(&circuit);
+        assert_eq!(dag.qubits(), 2);
+        assert_eq!(dag.clbits(), 2);
+        assert_eq!(dag.to_circuit(), circuit);
+    }
+}
@@ -1,9 +1,68 @@
-#[derive(Debug, PartialEq, Clone)]
+//! Implements classical register, that holds values of classical bits, like outcomes
+//! of measurements.
+//!
+//! Registers have arbitrary width. Bit `i` of the register is bit `i` of its value
+//! (little-endian order, see [`ClassicalRegister::from_value`]), while in bitstrings
+//! the last bit of the register is written first, just like in outcomes returned by
+//! [`StateVectorBackend::run_shots`](crate::statevector_backend::StateVectorBackend::run_shots):
+//!
+//! ```
+//! use quantum_crab::classical_register::ClassicalRegister;
+//!
+//! let mut register: ClassicalRegister = "0110".parse().unwrap();
+//! assert_eq!(register.value(), 6);
+//! assert!(register.get(1) && !register.get(3));
+//!
+//! register.flip(0);
+//! register.set(2, false);
+//! assert_eq!(register.to_string(), "0011");
+//!
+//! let mask = ClassicalRegister::from_value(4, 0b1010);
+//! assert_eq!((&register ^ &mask).to_string(), "1001");
+//! assert_eq!(register.iter().filter(|bit| *bit).count(), 2);
+//! ```
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not},
+    str::FromStr,
+};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "SerializedClassicalRegister")
+)]
 pub struct ClassicalRegister {
     bits: Vec<u8>,
 }
 
+/// Serialized form of the register, that is checked before being turned into one.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SerializedClassicalRegister {
+    bits: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedClassicalRegister> for ClassicalRegister {
+    type Error = String;
+
+    fn try_from(serialized: SerializedClassicalRegister) -> Result<ClassicalRegister, String> {
+        if serialized.bits.iter().any(|bit| *bit > 1) {
+            return Err(format!("Invalid classical bits: {:?}", serialized.bits));
+        }
+
+        Ok(ClassicalRegister {
+            bits: serialized.bits,
+        })
+    }
+}
+
 impl ClassicalRegister {
+    /// Constructs register out of bits, where every bit is either `0` or `1`.
     pub fn new(bits: Vec<u8>) -> ClassicalRegister {
         for bit in &bits {
             assert!(*bit == 0 || *bit == 1);
@@ -12,48 +71,365 @@ impl ClassicalRegister {
         ClassicalRegister { bits }
     }
 
+    /// Constructs register of `width` bits, that are all `0`.
     pub fn zeroed(width: usize) -> ClassicalRegister {
         ClassicalRegister::new(vec![0; width])
     }
 
+    /// Constructs register of `width` bits, where bit `i` is bit `i` of the value
+    /// (the least significant bit comes first).
+    ///
+    /// ```
+    /// use quantum_crab::classical_register::ClassicalRegister;
+    ///
+    /// assert_eq!(ClassicalRegister::from_value(3, 1).bits(), &[1, 0, 0]);
+    /// assert_eq!(ClassicalRegister::from_value(200, u128::MAX).value(), u128::MAX);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value doesn't fit into `width` bits.
+    pub fn from_value(width: usize, value: u128) -> ClassicalRegister {
+        assert!(
+            width >= 128 || value >> width == 0,
+            "Value {} doesn't fit into {} bits",
+            value,
+            width
+        );
+
+        let bits = (0..width)
+            .map(|i| if i < 128 { (value >> i & 1) as u8 } else { 0 })
+            .collect();
+
+        ClassicalRegister { bits }
+    }
+
+    /// Constructs register of `width` bits, where bit `i` is bit `width - 1 - i` of
+    /// the value (the most significant bit comes first).
+    ///
+    /// ```
+    /// use quantum_crab::classical_register::ClassicalRegister;
+    ///
+    /// assert_eq!(ClassicalRegister::from_value_be(3, 1).bits(), &[0, 0, 1]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value doesn't fit into `width` bits.
+    pub fn from_value_be(width: usize, value: u128) -> ClassicalRegister {
+        let mut register = ClassicalRegister::from_value(width, value);
+        register.bits.reverse();
+
+        register
+    }
+
+    /// Constructs register of `width` bits out of the value, like
+    /// [`ClassicalRegister::from_value`].
+    #[deprecated(note = "use `ClassicalRegister::from_value` instead")]
     pub fn from_bitstring(width: usize, bitstring: u32) -> ClassicalRegister {
-        ClassicalRegister::from_value(width, bitstring)
+        ClassicalRegister::from_value(width, u128::from(bitstring))
     }
 
-    pub fn from_value(width: usize, value: u32) -> ClassicalRegister {
-        assert!(value < 2u32.pow(width as u32));
+    /// Parses the bitstring, where the last bit of the register comes first (see
+    /// [`FromStr`] implementation).
+    pub fn parse_bitstring(bitstring: &str) -> Result<ClassicalRegister, ParseRegisterError> {
+        bitstring.parse()
+    }
 
-        let mut bits = Vec::new();
-        let mut remaining = value;
+    /// Amount of bits in the register.
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.bits.len()
+    }
 
-        for i in 0..width {
-            let position = (width - i - 1) as u32;
-            let value = 2u32.pow(position);
+    /// Bits of the register, where every bit is either `0` or `1`.
+    #[inline]
+    pub fn bits(&self) -> &[u8] {
+        &self.bits
+    }
 
-            if value <= remaining {
-                remaining -= value;
-                bits.insert(0, 1);
-            } else {
-                bits.insert(0, 0);
-            }
+    /// Value of the register, where bit `i` of the register is bit `i` of the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value doesn't fit into `u128`, see
+    /// [`ClassicalRegister::checked_value`].
+    pub fn value(&self) -> u128 {
+        self.checked_value()
+            .unwrap_or_else(|| panic!("Value of {} bits doesn't fit into u128", self.width()))
+    }
+
+    /// Value of the register (see [`ClassicalRegister::value`]), or `None` if any bit
+    /// above bit 127 is set.
+    pub fn checked_value(&self) -> Option<u128> {
+        if self.bits.iter().skip(128).any(|bit| *bit != 0) {
+            return None;
         }
 
-        ClassicalRegister::new(bits)
+        Some(
+            self.bits
+                .iter()
+                .take(128)
+                .enumerate()
+                .map(|(i, bit)| u128::from(*bit) << i)
+                .sum(),
+        )
     }
 
-    pub fn width(&self) -> usize {
-        self.bits.len()
+    /// Value of the register, where bit `i` of the register is bit `width - 1 - i`
+    /// of the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value doesn't fit into `u128`.
+    pub fn value_be(&self) -> u128 {
+        let mut reversed = self.clone();
+        reversed.bits.reverse();
+
+        reversed.value()
+    }
+
+    /// Returns bit `index` of the register.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of the register.
+    #[inline]
+    pub fn get(&self, index: usize) -> bool {
+        self.bits[index] == 1
+    }
+
+    /// Sets bit `index` of the register.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of the register.
+    #[inline]
+    pub fn set(&mut self, index: usize, bit: bool) {
+        self.bits[index] = u8::from(bit);
+    }
+
+    /// Flips bit `index` of the register.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of the register.
+    #[inline]
+    pub fn flip(&mut self, index: usize) {
+        self.bits[index] ^= 1;
+    }
+
+    /// Amount of bits, that are set.
+    pub fn count_ones(&self) -> usize {
+        self.bits.iter().filter(|bit| **bit == 1).count()
+    }
+
+    /// Iterates over bits of the register, starting from bit 0.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = bool> + ExactSizeIterator + '_ {
+        self.bits.iter().map(|bit| *bit == 1)
+    }
+
+    /// Combines bits of registers of equal widths with the operation.
+    fn zip_with(&mut self, other: &ClassicalRegister, operation: fn(u8, u8) -> u8) {
+        assert_eq!(
+            self.width(),
+            other.width(),
+            "Widths of classical registers don't match"
+        );
+
+        for (bit, other) in self.bits.iter_mut().zip(&other.bits) {
+            *bit = operation(*bit, *other);
+        }
+    }
+}
+
+/// Writes the register as a bitstring, where the last bit comes first.
+impl Display for ClassicalRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for bit in self.bits.iter().rev() {
+            write!(f, "{}", bit)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses the bitstring, where the last bit of the register comes first. Spaces, that
+/// separate classical registers in outcomes of
+/// [`StateVectorBackend::run_shots`](crate::statevector_backend::StateVectorBackend::run_shots),
+/// are skipped.
+impl FromStr for ClassicalRegister {
+    type Err = ParseRegisterError;
+
+    fn from_str(bitstring: &str) -> Result<ClassicalRegister, ParseRegisterError> {
+        let bits = bitstring
+            .chars()
+            .rev()
+            .filter(|c| *c != ' ')
+            .map(|c| match c {
+                '0' => Ok(0),
+                '1' => Ok(1),
+                _ => Err(ParseRegisterError { character: c }),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(ClassicalRegister { bits })
     }
+}
 
-    pub fn value(&self) -> u32 {
-        let mut value = 0;
+impl<'a> IntoIterator for &'a ClassicalRegister {
+    type Item = bool;
+    type IntoIter = std::iter::Map<std::slice::Iter<'a, u8>, fn(&u8) -> bool>;
 
-        for (position, bit) in self.bits.iter().enumerate() {
-            if *bit != 0 {
-                value += 2u32.pow(position as u32)
+    fn into_iter(self) -> Self::IntoIter {
+        self.bits.iter().map(|bit| *bit == 1)
+    }
+}
+
+/// Implements bitwise operator for registers of equal widths, panicking if widths
+/// don't match.
+macro_rules! impl_bitwise_operator {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $operator:tt) => {
+        impl $assign_trait<&ClassicalRegister> for ClassicalRegister {
+            fn $assign_method(&mut self, rhs: &ClassicalRegister) {
+                self.zip_with(rhs, |lhs, rhs| lhs $operator rhs);
+            }
+        }
+
+        impl $assign_trait for ClassicalRegister {
+            fn $assign_method(&mut self, rhs: ClassicalRegister) {
+                self.$assign_method(&rhs);
             }
         }
 
-        value
+        impl $trait<&ClassicalRegister> for &ClassicalRegister {
+            type Output = ClassicalRegister;
+
+            fn $method(self, rhs: &ClassicalRegister) -> ClassicalRegister {
+                let mut result = self.clone();
+                result.$assign_method(rhs);
+                result
+            }
+        }
+
+        impl $trait for ClassicalRegister {
+            type Output = ClassicalRegister;
+
+            fn $method(mut self, rhs: ClassicalRegister) -> ClassicalRegister {
+                self.$assign_method(&rhs);
+                self
+            }
+        }
+    };
+}
+
+impl_bitwise_operator!(BitAnd, bitand, BitAndAssign, bitand_assign, &);
+impl_bitwise_operator!(BitOr, bitor, BitOrAssign, bitor_assign, |);
+impl_bitwise_operator!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^);
+
+impl Not for &ClassicalRegister {
+    type Output = ClassicalRegister;
+
+    fn not(self) -> ClassicalRegister {
+        !self.clone()
+    }
+}
+
+impl Not for ClassicalRegister {
+    type Output = ClassicalRegister;
+
+    fn not(mut self) -> ClassicalRegister {
+        for bit in &mut self.bits {
+            *bit ^= 1;
+        }
+
+        self
+    }
+}
+
+/// Error returned when a bitstring contains characters other than `0`, `1` and space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRegisterError {
+    /// The invalid character.
+    pub character: char,
+}
+
+impl Display for ParseRegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid bit {:?} in bitstring", self.character)
+    }
+}
+
+impl std::error::Error for ParseRegisterError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::classical_register::{ClassicalRegister, ParseRegisterError};
+
+    #[test]
+    fn converts_values_in_both_orders() {
+        let register = ClassicalRegister::from_value(5, 0b00110);
+        assert_eq!(register.bits(), &[0, 1, 1, 0, 0]);
+        assert_eq!(register.value(), 6);
+        assert_eq!(register.value_be(), 0b01100);
+        assert_eq!(
+            ClassicalRegister::from_value_be(5, 0b01100),
+            ClassicalRegister::from_value(5, 0b00110)
+        );
+
+        let mut wide = ClassicalRegister::from_value(300, 1 << 127);
+        assert_eq!(wide.checked_value(), Some(1 << 127));
+        wide.set(128, true);
+        assert_eq!(wide.checked_value(), None);
+        assert_eq!(ClassicalRegister::zeroed(0).value(), 0);
+
+        let result = std::panic::catch_unwind(|| ClassicalRegister::from_value(3, 8));
+        assert!(result.is_err());
+
+        #[allow(deprecated)]
+        let register = ClassicalRegister::from_bitstring(3, 0b110);
+        assert_eq!(register, ClassicalRegister::from_value(3, 0b110));
+    }
+
+    #[test]
+    fn parses_and_formats_bitstrings() {
+        let register = ClassicalRegister::parse_bitstring("10 011").unwrap();
+        assert_eq!(register.bits(), &[1, 1, 0, 0, 1]);
+        assert_eq!(register.to_string(), "10011");
+        assert_eq!(register.to_string().parse(), Ok(register));
+
+        assert_eq!(
+            "0120".parse::<ClassicalRegister>(),
+            Err(ParseRegisterError { character: '2' })
+        );
+        assert_eq!("".parse(), Ok(ClassicalRegister::zeroed(0)));
+    }
+
+    #[test]
+    fn applies_bitwise_operations() {
+        let a = ClassicalRegister::from_value(4, 0b1100);
+        let b = ClassicalRegister::from_value(4, 0b1010);
+
+        assert_eq!((&a & &b).value(), 0b1000);
+        assert_eq!((&a | &b).value(), 0b1110);
+        assert_eq!((a.clone() ^ b.clone()).value(), 0b0110);
+        assert_eq!((!&a).value(), 0b0011);
+
+        let mut c = a.clone();
+        c |= &b;
+        c &= b.clone();
+        assert_eq!(c, b);
+        c ^= a;
+        c ^= &b;
+        assert_eq!(c.value(), 0b1100);
+        c = b.clone();
+        assert_eq!(c.count_ones(), 2);
+        assert_eq!(
+            (&c).into_iter().rev().collect::<Vec<_>>(),
+            vec![true, false, true, false]
+        );
+
+        let result = std::panic::catch_unwind(|| &c & &ClassicalRegister::zeroed(3));
+        assert!(result.is_err());
     }
 }
@@ -1,6 +1,8 @@
 //! Contains an implementation of complex number mathematics.
 use core::fmt;
 use num::{One, Zero};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::{
     fmt::Display,
     iter::Sum,
@@ -9,6 +11,7 @@ use std::{
 
 /// Represents complex number.
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Complex {
     /// Real part of the complex number.
     pub real: f64,
@@ -216,4 +219,25 @@ mod tests {
         approx_eq!(f64, b.real, 1f64, ulps = 2);
         approx_eq!(f64, b.imag, 1f64, ulps = 2);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_complex_matrices() {
+        use crate::matrix::Matrix;
+
+        let matrix = Matrix::new(1, 2, vec![Complex::new(1, -0.5), Complex::new(0, 2)]);
+        let json = serde_json::to_string(&matrix).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"rows":1,"cols":2,"data":[{"real":1.0,"imag":-0.5},{"real":0.0,"imag":2.0}]}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Matrix<Complex>>(&json).unwrap(),
+            matrix
+        );
+        assert!(
+            serde_json::from_str::<Matrix<Complex>>(r#"{"rows":2,"cols":2,"data":[]}"#).is_err()
+        );
+    }
 }
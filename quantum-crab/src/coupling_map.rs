@@ -0,0 +1,292 @@
+//! Implements coupling map of the quantum device.
+use std::collections::VecDeque;
+
+/// Represents connectivity of the quantum device: undirected graph, where physical
+/// qubits are nodes and edges connect pairs of qubits, that two-qubit gates can be
+/// applied to.
+///
+/// ```
+/// use quantum_crab::coupling_map::CouplingMap;
+///
+/// let map = CouplingMap::line(4);
+/// assert!(map.are_coupled(1, 2));
+/// assert!(!map.are_coupled(0, 2));
+/// assert_eq!(map.distance(0, 3), Some(3));
+/// assert_eq!(map.shortest_path(3, 1), Some(vec![3, 2, 1]));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CouplingMap {
+    edges: Vec<(usize, usize)>,
+    neighbours: Vec<Vec<usize>>,
+}
+
+impl CouplingMap {
+    /// Constructs coupling map with a concrete amount of physical qubits, that are
+    /// not connected to each other.
+    pub fn new(qubits: usize) -> CouplingMap {
+        CouplingMap {
+            edges: Vec::new(),
+            neighbours: vec![Vec::new(); qubits],
+        }
+    }
+
+    /// Constructs coupling map out of the list of coupled qubit pairs.
+    pub fn from_edges(qubits: usize, edges: &[(usize, usize)]) -> CouplingMap {
+        let mut map = CouplingMap::new(qubits);
+
+        for (qubit1, qubit2) in edges {
+            map.add_edge(*qubit1, *qubit2);
+        }
+
+        map
+    }
+
+    /// Qubits connected in a line: `0 - 1 - 2 - ... - (n - 1)`.
+    pub fn line(qubits: usize) -> CouplingMap {
+        let edges: Vec<_> = (1..qubits).map(|qubit| (qubit - 1, qubit)).collect();
+        CouplingMap::from_edges(qubits, &edges)
+    }
+
+    /// Qubits connected in a ring: a line, where the last qubit is also coupled to
+    /// the first one.
+    pub fn ring(qubits: usize) -> CouplingMap {
+        let mut map = CouplingMap::line(qubits);
+
+        if qubits > 2 {
+            map.add_edge(qubits - 1, 0);
+        }
+
+        map
+    }
+
+    /// Qubits placed in a rectangular grid with `rows` rows and `cols` columns, where
+    /// every qubit is coupled to its horizontal and vertical neighbours. Qubit in row
+    /// `i` and column `j` has index `i * cols + j`.
+    pub fn grid(rows: usize, cols: usize) -> CouplingMap {
+        let mut map = CouplingMap::new(rows * cols);
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let qubit = row * cols + col;
+
+                if col + 1 < cols {
+                    map.add_edge(qubit, qubit + 1);
+                }
+
+                if row + 1 < rows {
+                    map.add_edge(qubit, qubit + cols);
+                }
+            }
+        }
+
+        map
+    }
+
+    /// Heavy-hexagon lattice with `rows` rows of `cols` hexagons each.
+    ///
+    /// The lattice is a hexagonal lattice, where every edge has an additional qubit
+    /// placed on it, so that every qubit has at most three neighbours. Qubits in the
+    /// vertices of hexagons come first (row by row), followed by qubits on the edges.
+    ///
+    /// ```
+    /// use quantum_crab::coupling_map::CouplingMap;
+    ///
+    /// // Single heavy hexagon is a ring of 12 qubits.
+    /// let map = CouplingMap::heavy_hex(1, 1);
+    /// assert_eq!(map.qubits(), 12);
+    /// assert_eq!(map.edges().len(), 12);
+    /// ```
+    pub fn heavy_hex(rows: usize, cols: usize) -> CouplingMap {
+        if rows == 0 || cols == 0 {
+            return CouplingMap::new(0);
+        }
+
+        // Hexagonal lattice is built as a "brick wall": `rows + 1` rows of vertices,
+        // where vertical edges between neighbouring rows alternate between even and
+        // odd columns.
+        let width = 2 * cols + 2;
+        let vertex = |row: usize, col: usize| row * width + col;
+
+        let mut hexagonal = CouplingMap::new((rows + 1) * width);
+        for row in 0..=rows {
+            for col in 0..width {
+                if col + 1 < width {
+                    hexagonal.add_edge(vertex(row, col), vertex(row, col + 1));
+                }
+
+                if row < rows && col % 2 == row % 2 {
+                    hexagonal.add_edge(vertex(row, col), vertex(row + 1, col));
+                }
+            }
+        }
+
+        // Corners of the brick wall don't belong to any hexagon.
+        let vertices: Vec<usize> = (0..hexagonal.qubits())
+            .filter(|qubit| hexagonal.neighbours(*qubit).len() > 1)
+            .collect();
+        let index = |qubit: usize| vertices.binary_search(&qubit).unwrap();
+
+        let edges: Vec<(usize, usize)> = hexagonal
+            .edges()
+            .iter()
+            .filter(|(qubit1, qubit2)| {
+                vertices.binary_search(qubit1).is_ok() && vertices.binary_search(qubit2).is_ok()
+            })
+            .map(|(qubit1, qubit2)| (index(*qubit1), index(*qubit2)))
+            .collect();
+
+        let mut map = CouplingMap::new(vertices.len() + edges.len());
+        for (i, (qubit1, qubit2)) in edges.iter().enumerate() {
+            let edge_qubit = vertices.len() + i;
+            map.add_edge(*qubit1, edge_qubit);
+            map.add_edge(edge_qubit, *qubit2);
+        }
+
+        map
+    }
+
+    /// Couples two physical qubits. Adding the same pair twice doesn't do anything.
+    pub fn add_edge(&mut self, qubit1: usize, qubit2: usize) {
+        assert!(
+            qubit1 < self.qubits() && qubit2 < self.qubits(),
+            "Invalid edge ({}, {}) in coupling map with {} qubits",
+            qubit1,
+            qubit2,
+            self.qubits()
+        );
+        assert_ne!(
+            qubit1, qubit2,
+            "Qubit {} can't be coupled to itself",
+            qubit1
+        );
+
+        if self.are_coupled(qubit1, qubit2) {
+            return;
+        }
+
+        self.edges.push((qubit1, qubit2));
+        self.neighbours[qubit1].push(qubit2);
+        self.neighbours[qubit2].push(qubit1);
+    }
+
+    /// Amount of physical qubits.
+    #[inline]
+    pub fn qubits(&self) -> usize {
+        self.neighbours.len()
+    }
+
+    /// Pairs of coupled qubits, in order they were added.
+    #[inline]
+    pub fn edges(&self) -> &[(usize, usize)] {
+        &self.edges
+    }
+
+    /// Qubits coupled to the given one.
+    #[inline]
+    pub fn neighbours(&self, qubit: usize) -> &[usize] {
+        &self.neighbours[qubit]
+    }
+
+    /// Checks if two-qubit gates can be applied to given qubits.
+    #[inline]
+    pub fn are_coupled(&self, qubit1: usize, qubit2: usize) -> bool {
+        self.neighbours[qubit1].contains(&qubit2)
+    }
+
+    /// Amount of edges in the shortest path between two qubits, if it exists.
+    pub fn distance(&self, from: usize, to: usize) -> Option<usize> {
+        self.distances_from(from)[to]
+    }
+
+    /// Distances from the qubit to every other qubit (see [`CouplingMap::distance`]).
+    pub fn distances_from(&self, from: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.qubits()];
+        distances[from] = Some(0);
+
+        let mut queue = VecDeque::from([from]);
+        while let Some(qubit) = queue.pop_front() {
+            let distance = distances[qubit].unwrap();
+
+            for neighbour in &self.neighbours[qubit] {
+                if distances[*neighbour].is_none() {
+                    distances[*neighbour] = Some(distance + 1);
+                    queue.push_back(*neighbour);
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// Distances between every pair of qubits (see [`CouplingMap::distance`]).
+    pub fn distance_matrix(&self) -> Vec<Vec<Option<usize>>> {
+        (0..self.qubits())
+            .map(|qubit| self.distances_from(qubit))
+            .collect()
+    }
+
+    /// Shortest path between two qubits (including both of them), if it exists.
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let distances = self.distances_from(to);
+        distances[from]?;
+
+        let mut path = vec![from];
+        let mut qubit = from;
+
+        while qubit != to {
+            qubit = *self.neighbours[qubit]
+                .iter()
+                .filter(|neighbour| distances[**neighbour].is_some())
+                .min_by_key(|neighbour| (distances[**neighbour], **neighbour))
+                .unwrap();
+            path.push(qubit);
+        }
+
+        Some(path)
+    }
+
+    /// Checks if there is a path between every pair of qubits.
+    pub fn is_connected(&self) -> bool {
+        self.qubits() == 0
+            || self
+                .distances_from(0)
+                .iter()
+                .all(|distance| distance.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coupling_map::CouplingMap;
+
+    #[test]
+    fn constructs_standard_topologies() {
+        let ring = CouplingMap::ring(5);
+        assert_eq!(ring.edges().len(), 5);
+        assert_eq!(ring.distance(0, 3), Some(2));
+
+        let grid = CouplingMap::grid(2, 3);
+        assert_eq!(grid.edges().len(), 7);
+        assert_eq!(grid.neighbours(4), &[1, 3, 5]);
+        assert_eq!(grid.distance(0, 5), Some(3));
+
+        let heavy_hex = CouplingMap::heavy_hex(2, 2);
+        assert!(heavy_hex.is_connected());
+        assert!((0..heavy_hex.qubits()).all(|qubit| heavy_hex.neighbours(qubit).len() <= 3));
+        // 4 hexagons share 19 edges, each edge gets an additional qubit.
+        assert_eq!(heavy_hex.qubits(), 16 + 19);
+        assert_eq!(heavy_hex.edges().len(), 2 * 19);
+    }
+
+    #[test]
+    fn finds_shortest_paths() {
+        let mut map = CouplingMap::grid(3, 3);
+        assert_eq!(map.shortest_path(0, 8), Some(vec![0, 1, 2, 5, 8]));
+        assert_eq!(map.shortest_path(4, 4), Some(vec![4]));
+
+        map = CouplingMap::from_edges(4, &[(0, 1), (2, 3)]);
+        assert!(!map.is_connected());
+        assert_eq!(map.distance(0, 3), None);
+        assert_eq!(map.shortest_path(0, 3), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Implements gate fusion, that merges clusters of gates acting on a few qubits
+//! into dense unitaries, so that the state vector backend makes fewer passes over
+//! amplitudes.
+//!
+//! Instructions are scanned in order, and every instruction acting on at most
+//! `max_qubits` qubits joins the open clusters sharing qubits with it, as long as
+//! the merged cluster still acts on at most `max_qubits` qubits. Otherwise these
+//! clusters are closed and emitted. As open clusters act on disjoint qubits, they
+//! commute with each other and with instructions emitted in the meantime.
+use crate::{
+    complex::Complex,
+    matrix::Matrix,
+    quantum_circuit::{Instruction, QuantumCircuit},
+    statevector_backend::unitary,
+};
+
+/// Gate, that is executed by the state vector backend after fusion.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum FusedGate {
+    /// Single qubit gate with the 2x2 unitary.
+    Single {
+        qubit: usize,
+        matrix: Matrix<Complex>,
+    },
+    /// Two qubit gate with the 4x4 unitary, indexed by `bit(qubit0) + 2 * bit(qubit1)`.
+    Two {
+        qubit0: usize,
+        qubit1: usize,
+        matrix: Matrix<Complex>,
+    },
+    /// Instruction, that was not fused with any other one.
+    Instruction(Instruction),
+}
+
+/// Instructions, that act on the same qubits and are fused together.
+#[derive(Debug)]
+struct Cluster {
+    qubits: Vec<usize>,
+    instructions: Vec<Instruction>,
+}
+
+impl Cluster {
+    fn into_gate(self) -> FusedGate {
+        if self.instructions.len() == 1 {
+            return FusedGate::Instruction(self.instructions.into_iter().next().unwrap());
+        }
+
+        let mut circuit = QuantumCircuit::new(self.qubits.len());
+        for instruction in &self.instructions {
+            // Qubits of gates inside controlled gates are ignored, so they may be
+            // outside of the cluster.
+            circuit.add(instruction.map_qubits(|qubit| {
+                self.qubits
+                    .iter()
+                    .position(|q| *q == qubit)
+                    .unwrap_or_default()
+            }));
+        }
+        let matrix = unitary(&circuit);
+
+        match self.qubits[..] {
+            [qubit] => FusedGate::Single { qubit, matrix },
+            [qubit0, qubit1] => FusedGate::Two {
+                qubit0,
+                qubit1,
+                matrix,
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Fuses instructions of the flattened circuit into gates acting on at most
+/// `max_qubits` (1 or 2) qubits.
+pub(crate) fn fuse(circuit: &QuantumCircuit, max_qubits: usize) -> Vec<FusedGate> {
+    assert!(
+        (1..=2).contains(&max_qubits),
+        "Gates can be fused on 1 or 2 qubits, not {}",
+        max_qubits
+    );
+
+    let mut gates = Vec::new();
+    let mut clusters: Vec<Cluster> = Vec::new();
+
+    for instruction in circuit.flatten().instructions() {
+        let qubits = instruction.qubits();
+        let fusable = !instruction.is_dynamic()
+            && !matches!(instruction, Instruction::Barrier(..))
+            && qubits.len() <= max_qubits;
+
+        if matches!(instruction, Instruction::Identity(..)) {
+            continue;
+        }
+
+        // Take out clusters sharing qubits with the instruction.
+        let (touching, rest): (Vec<_>, Vec<_>) = clusters
+            .into_iter()
+            .partition(|cluster| cluster.qubits.iter().any(|qubit| qubits.contains(qubit)));
+        clusters = rest;
+
+        let mut merged_qubits: Vec<usize> = touching
+            .iter()
+            .flat_map(|cluster| cluster.qubits.iter().copied())
+            .chain(qubits.iter().copied())
+            .collect();
+        merged_qubits.sort_unstable();
+        merged_qubits.dedup();
+
+        if fusable && merged_qubits.len() <= max_qubits {
+            let mut instructions: Vec<_> = touching
+                .into_iter()
+                .flat_map(|cluster| cluster.instructions)
+                .collect();
+            instructions.push(instruction.clone());
+
+            clusters.push(Cluster {
+                qubits: merged_qubits,
+                instructions,
+            });
+            continue;
+        }
+
+        gates.extend(touching.into_iter().map(Cluster::into_gate));
+
+        if fusable {
+            clusters.push(Cluster {
+                qubits,
+                instructions: vec![instruction.clone()],
+            });
+        } else if !matches!(instruction, Instruction::Barrier(..)) {
+            gates.push(FusedGate::Instruction(instruction.clone()));
+        }
+    }
+
+    gates.extend(clusters.into_iter().map(Cluster::into_gate));
+    gates
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        gate_fusion::{fuse, FusedGate},
+        quantum_circuit::{Instruction, QuantumCircuit},
+    };
+
+    #[test]
+    fn fuses_gates() {
+        let mut circuit = QuantumCircuit::with_clbits(3, 1);
+        circuit
+            .h(0)
+            .t(0)
+            .x(2)
+            .h(0)
+            .y(1)
+            .cx(0, 1)
+            .rz(1, 0.5)
+            .ccx(0, 1, 2)
+            .z(2)
+            .barrier()
+            .h(2)
+            .s(2)
+            .measure(0, 0);
+
+        let gates = fuse(&circuit, 2);
+        assert_eq!(gates.len(), 6);
+        assert_eq!(gates[0], FusedGate::Instruction(Instruction::PauliX(2)));
+        assert!(matches!(
+            gates[1],
+            FusedGate::Two {
+                qubit0: 0,
+                qubit1: 1,
+                ..
+            }
+        ));
+        assert!(matches!(
+            gates[2],
+            FusedGate::Instruction(Instruction::Toffoli { .. })
+        ));
+        assert_eq!(gates[3], FusedGate::Instruction(Instruction::PauliZ(2)));
+        assert_eq!(
+            gates[4],
+            FusedGate::Instruction(Instruction::Measure { qubit: 0, clbit: 0 })
+        );
+        assert!(matches!(gates[5], FusedGate::Single { qubit: 2, .. }));
+
+        // Without two qubit clusters, controlled not gate separates the clusters.
+        let gates = fuse(&circuit, 1);
+        assert!(matches!(gates[0], FusedGate::Single { qubit: 0, .. }));
+        assert_eq!(gates[1], FusedGate::Instruction(Instruction::PauliY(1)));
+    }
+}
@@ -0,0 +1,363 @@
+//! Implements jobs, that execute circuits on backends in background threads, so that
+//! long-running simulations don't block the caller and can be cancelled.
+//!
+//! Jobs are created by [`Backend::submit`]. With the `async` feature, [`Job`] also
+//! implements [`Future`](std::future::Future), resolving to the result of the
+//! execution.
+//!
+//! ```
+//! use quantum_crab::{
+//!     backend::{Backend, RunOptions},
+//!     job::JobStatus,
+//!     quantum_circuit::QuantumCircuit,
+//!     statevector_backend::StateVectorBackend,
+//! };
+//!
+//! let mut circuit = QuantumCircuit::new(2);
+//! circuit.h(0).cx(0, 1);
+//!
+//! let job = StateVectorBackend::default().submit(&circuit, &RunOptions::default());
+//! assert_ne!(job.status(), JobStatus::Cancelled);
+//!
+//! let state = job.wait().unwrap();
+//! assert!((state.amplitude("11").real - 0.5f64.sqrt()).abs() < 1e-12);
+//! ```
+use crate::{
+    backend::{Backend, BackendError, RunOptions},
+    quantum_circuit::QuantumCircuit,
+};
+#[cfg(feature = "async")]
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+};
+
+/// Token, that is shared between the job and the backend executing it, to request
+/// cancellation. Clones of the token refer to the same cancellation flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Constructs token, that is not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Requests cancellation of executions using the token.
+    #[inline]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks if cancellation was requested.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &CancellationToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+/// Status of the job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Job is waiting for its thread to start.
+    Queued,
+    /// Circuit is being executed.
+    Running,
+    /// Execution finished successfully.
+    Completed,
+    /// Backend returned an error or panicked.
+    Failed,
+    /// Execution was cancelled.
+    Cancelled,
+}
+
+#[derive(Debug)]
+struct State<T> {
+    status: JobStatus,
+    result: Option<Result<T, BackendError>>,
+    #[cfg(feature = "async")]
+    waker: Option<Waker>,
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    finished: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().expect("Job state is poisoned")
+    }
+
+    fn finish(&self, result: Result<T, BackendError>) {
+        let mut state = self.lock();
+        state.status = match result {
+            Ok(..) => JobStatus::Completed,
+            Err(BackendError::Cancelled) => JobStatus::Cancelled,
+            Err(..) => JobStatus::Failed,
+        };
+        state.result = Some(result);
+
+        #[cfg(feature = "async")]
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+
+        self.finished.notify_all();
+    }
+}
+
+/// Execution of the circuit in a background thread. Dropping the job doesn't stop
+/// the execution, [`Job::cancel`] should be used for that.
+#[derive(Debug)]
+pub struct Job<T> {
+    shared: Arc<Shared<T>>,
+    cancellation: CancellationToken,
+}
+
+impl<T: Send + 'static> Job<T> {
+    /// Starts execution of the circuit on the backend. Cancellation token of the
+    /// options is reused, if there is one.
+    pub(crate) fn spawn<B>(backend: B, circuit: QuantumCircuit, mut options: RunOptions) -> Job<T>
+    where
+        B: Backend<Output = T> + Send + 'static,
+    {
+        let cancellation = options
+            .cancellation
+            .get_or_insert_with(CancellationToken::new)
+            .clone();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                status: JobStatus::Queued,
+                result: None,
+                #[cfg(feature = "async")]
+                waker: None,
+            }),
+            finished: Condvar::new(),
+        });
+
+        let thread_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            thread_shared.lock().status = JobStatus::Running;
+
+            // Waiting for the job never ends if the backend panics before finishing it.
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                options
+                    .check_cancelled()
+                    .and_then(|_| backend.run(&circuit, &options))
+            }))
+            .unwrap_or_else(|payload| Err(BackendError::Panicked(panic_message(&*payload))));
+            thread_shared.finish(result);
+        });
+
+        Job {
+            shared,
+            cancellation,
+        }
+    }
+}
+
+/// Extracts message of the panic from its payload.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
+}
+
+impl<T> Job<T> {
+    /// Current status of the job.
+    pub fn status(&self) -> JobStatus {
+        self.shared.lock().status
+    }
+
+    /// Checks if the job finished, successfully or not. The result may have already
+    /// been taken by polling the job.
+    pub fn is_finished(&self) -> bool {
+        !matches!(self.status(), JobStatus::Queued | JobStatus::Running)
+    }
+
+    /// Requests cancellation of the job. The backend stops before the next
+    /// instruction, and the job fails with [`BackendError::Cancelled`]. Jobs, that
+    /// have already finished, are not affected.
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+
+    /// Blocks until the job finishes, returning the result of the execution.
+    pub fn wait(self) -> Result<T, BackendError> {
+        let mut state = self.shared.lock();
+
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+
+            state = self
+                .shared
+                .finished
+                .wait(state)
+                .expect("Job state is poisoned");
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> Future for Job<T> {
+    type Output = Result<T, BackendError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.lock();
+
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{Backend, BackendError, RunOptions},
+        job::{CancellationToken, JobStatus},
+        quantum_circuit::QuantumCircuit,
+        sparse_backend::SparseBackend,
+        stabilizer_backend::StabilizerBackend,
+        statevector_backend::StateVectorBackend,
+    };
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn completes_jobs() {
+        let mut circuit = QuantumCircuit::with_clbits(3, 3);
+        circuit.x(0).cx(0, 2).measure(0..3, 0..3);
+
+        let job = StabilizerBackend.submit(&circuit, &RunOptions::default());
+        while !job.is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(job.status(), JobStatus::Completed);
+        assert_eq!(job.wait().unwrap().bits(), &[1, 0, 1]);
+
+        circuit.t(1);
+        let job = StabilizerBackend.submit(&circuit, &RunOptions::default());
+        assert!(matches!(
+            job.wait(),
+            Err(BackendError::UnsupportedInstructions(..))
+        ));
+    }
+
+    #[test]
+    fn fails_jobs_when_backend_panics() {
+        #[derive(Clone)]
+        struct PanickingBackend;
+
+        impl Backend for PanickingBackend {
+            type Output = ();
+
+            fn run(&self, _: &QuantumCircuit, _: &RunOptions) -> Result<(), BackendError> {
+                panic!("backend failure")
+            }
+        }
+
+        let job = PanickingBackend.submit(&QuantumCircuit::new(1), &RunOptions::default());
+        while !job.is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(job.status(), JobStatus::Failed);
+        assert_eq!(
+            job.wait(),
+            Err(BackendError::Panicked("backend failure".to_owned()))
+        );
+    }
+
+    #[test]
+    fn cancels_jobs() {
+        let mut circuit = QuantumCircuit::new(20);
+        for _ in 0..1000 {
+            circuit.h(0..20).cx(0, 19);
+        }
+
+        let backend = StateVectorBackend::default();
+        let job = backend.submit(&circuit, &RunOptions::default());
+        job.cancel();
+
+        while !job.is_finished() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(job.status(), JobStatus::Cancelled);
+        assert_eq!(job.wait(), Err(BackendError::Cancelled));
+
+        // Cancellation token of the options cancels the job as well.
+        let options = RunOptions {
+            cancellation: Some(CancellationToken::new()),
+            ..RunOptions::default()
+        };
+        options.cancellation.as_ref().unwrap().cancel();
+        let job = SparseBackend::default().submit(&QuantumCircuit::new(1), &options);
+        assert_eq!(job.wait(), Err(BackendError::Cancelled));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn awaits_jobs() {
+        use std::{
+            future::Future,
+            pin::pin,
+            sync::Arc,
+            task::{Context, Poll, Wake},
+        };
+
+        struct ThreadWaker(thread::Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let mut circuit = QuantumCircuit::new(3);
+        circuit.h(0).cx(0, 1).cx(1, 2);
+
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut context = Context::from_waker(&waker);
+        let mut job = pin!(StateVectorBackend::default().submit(&circuit, &RunOptions::default()));
+
+        let state = loop {
+            match job.as_mut().poll(&mut context) {
+                Poll::Ready(result) => break result.unwrap(),
+                Poll::Pending => thread::park(),
+            }
+        };
+        assert!((state.amplitude("111").real - 0.5f64.sqrt()).abs() < 1e-12);
+        assert!(job.is_finished());
+        assert_eq!(job.status(), JobStatus::Completed);
+    }
+}
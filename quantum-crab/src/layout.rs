@@ -0,0 +1,572 @@
+//! Implements mapping between logical qubits of the circuit and physical qubits
+//! of the device, and layout selection passes.
+//!
+//! Layout selection passes choose initial [`Layout`] for the circuit, trying to
+//! minimize the amount of swaps, that routing (see [`crate::routing`]) has to insert:
+//! - [`trivial_layout`] maps logical qubit `i` into physical qubit `i`;
+//! - [`dense_layout`] places the circuit onto the most densely connected part of the
+//!   device, putting interacting qubits close to each other;
+//! - [`noise_aware_layout`] additionally avoids qubits with high error rates.
+use crate::{
+    coupling_map::CouplingMap,
+    quantum_circuit::{Instruction, QuantumCircuit},
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Maximum amount of physical qubits of deserialized layouts. Physical qubits take
+/// memory even if no logical qubit is mapped into them, so untrusted inputs could
+/// otherwise request huge allocations.
+#[cfg(feature = "serde")]
+pub const MAX_DESERIALIZED_PHYSICAL_QUBITS: usize = 1 << 20;
+
+/// Represents one-to-one mapping of logical qubits into physical qubits.
+///
+/// ```
+/// use quantum_crab::layout::Layout;
+///
+/// let mut layout = Layout::new(vec![2, 0], 3);
+/// assert_eq!(layout.physical(0), 2);
+/// assert_eq!(layout.logical(0), Some(1));
+/// assert_eq!(layout.logical(1), None);
+///
+/// layout.swap_physical(0, 1);
+/// assert_eq!(layout.physical(1), 1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "SerializedLayout", try_from = "SerializedLayout")
+)]
+pub struct Layout {
+    logical_to_physical: Vec<usize>,
+    physical_to_logical: Vec<Option<usize>>,
+}
+
+impl Layout {
+    /// Constructs the layout, where logical qubit `i` is mapped into physical qubit
+    /// `logical_to_physical[i]`.
+    pub fn new(logical_to_physical: Vec<usize>, physical_qubits: usize) -> Layout {
+        Layout::try_new(logical_to_physical, physical_qubits).unwrap_or_else(|message| {
+            panic!("{}", message);
+        })
+    }
+
+    /// Constructs the layout (see [`Layout::new`]), returning the reason why the
+    /// mapping is invalid instead of panicking.
+    fn try_new(logical_to_physical: Vec<usize>, physical_qubits: usize) -> Result<Layout, String> {
+        let mut physical_to_logical = vec![None; physical_qubits];
+
+        for (logical, physical) in logical_to_physical.iter().enumerate() {
+            if *physical >= physical_qubits {
+                return Err(format!(
+                    "Logical qubit {} is mapped into invalid physical qubit {}",
+                    logical, physical
+                ));
+            }
+
+            if physical_to_logical[*physical].is_some() {
+                return Err(format!(
+                    "Physical qubit {} is used twice in the layout",
+                    physical
+                ));
+            }
+
+            physical_to_logical[*physical] = Some(logical);
+        }
+
+        Ok(Layout {
+            logical_to_physical,
+            physical_to_logical,
+        })
+    }
+
+    /// Maps logical qubit `i` into physical qubit `i`.
+    pub fn trivial(logical_qubits: usize, physical_qubits: usize) -> Layout {
+        Layout::new((0..logical_qubits).collect(), physical_qubits)
+    }
+
+    /// Amount of mapped logical qubits.
+    #[inline]
+    pub fn logical_qubits(&self) -> usize {
+        self.logical_to_physical.len()
+    }
+
+    /// Amount of physical qubits.
+    #[inline]
+    pub fn physical_qubits(&self) -> usize {
+        self.physical_to_logical.len()
+    }
+
+    /// Physical qubit, that the logical qubit is mapped into.
+    #[inline]
+    pub fn physical(&self, logical: usize) -> usize {
+        self.logical_to_physical[logical]
+    }
+
+    /// Logical qubit mapped into the physical qubit, if there is any.
+    #[inline]
+    pub fn logical(&self, physical: usize) -> Option<usize> {
+        self.physical_to_logical[physical]
+    }
+
+    /// Physical qubits, that logical qubits are mapped into.
+    #[inline]
+    pub fn as_slice(&self) -> &[usize] {
+        &self.logical_to_physical
+    }
+
+    /// Swaps contents of two physical qubits.
+    pub fn swap_physical(&mut self, physical1: usize, physical2: usize) {
+        self.physical_to_logical.swap(physical1, physical2);
+
+        for physical in [physical1, physical2] {
+            if let Some(logical) = self.physical_to_logical[physical] {
+                self.logical_to_physical[logical] = physical;
+            }
+        }
+    }
+}
+
+/// Serialized form of the layout.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SerializedLayout {
+    logical_to_physical: Vec<usize>,
+    physical_qubits: usize,
+}
+
+#[cfg(feature = "serde")]
+impl From<Layout> for SerializedLayout {
+    fn from(layout: Layout) -> SerializedLayout {
+        SerializedLayout {
+            physical_qubits: layout.physical_qubits(),
+            logical_to_physical: layout.logical_to_physical,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedLayout> for Layout {
+    type Error = String;
+
+    fn try_from(serialized: SerializedLayout) -> Result<Layout, String> {
+        if serialized.physical_qubits > MAX_DESERIALIZED_PHYSICAL_QUBITS {
+            return Err(format!(
+                "Layout can't have more than {} physical qubits",
+                MAX_DESERIALIZED_PHYSICAL_QUBITS
+            ));
+        }
+
+        Layout::try_new(serialized.logical_to_physical, serialized.physical_qubits)
+    }
+}
+
+/// Layout selection method used by [`select_layout`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutMethod<'a> {
+    /// See [`trivial_layout`].
+    Trivial,
+
+    /// See [`dense_layout`].
+    Dense,
+
+    /// See [`noise_aware_layout`]. Contains error rate of every physical qubit.
+    NoiseAware(&'a [f64]),
+}
+
+/// Chooses layout for the circuit and returns the circuit with the layout recorded
+/// (see [`QuantumCircuit::layout`]).
+///
+/// ```
+/// use quantum_crab::{
+///   coupling_map::CouplingMap,
+///   layout::{select_layout, LayoutMethod},
+///   quantum_circuit::{QuantumCircuit, Instruction}
+/// };
+///
+/// let mut circuit = QuantumCircuit::new(2);
+/// circuit.add(Instruction::ControlledNot { control: 0, target: 1 });
+///
+/// let circuit = select_layout(
+///     &circuit,
+///     &CouplingMap::line(4),
+///     LayoutMethod::NoiseAware(&[0.1, 0.2, 0.01, 0.02]),
+/// );
+/// assert_eq!(circuit.layout().unwrap().as_slice(), &[2, 3]);
+/// ```
+pub fn select_layout(
+    circuit: &QuantumCircuit,
+    coupling_map: &CouplingMap,
+    method: LayoutMethod,
+) -> QuantumCircuit {
+    let layout = match method {
+        LayoutMethod::Trivial => trivial_layout(circuit, coupling_map),
+        LayoutMethod::Dense => dense_layout(circuit, coupling_map),
+        LayoutMethod::NoiseAware(error_rates) => {
+            noise_aware_layout(circuit, coupling_map, error_rates)
+        }
+    };
+
+    let mut circuit = circuit.clone();
+    circuit.set_layout(layout);
+    circuit
+}
+
+/// Maps logical qubit `i` into physical qubit `i`.
+pub fn trivial_layout(circuit: &QuantumCircuit, coupling_map: &CouplingMap) -> Layout {
+    assert_fits(circuit, coupling_map);
+    Layout::trivial(circuit.qubits(), coupling_map.qubits())
+}
+
+/// Finds the connected set of physical qubits with the most couplings inside of it,
+/// and places logical qubits there, so that qubits, that interact the most, are
+/// the closest to each other.
+pub fn dense_layout(circuit: &QuantumCircuit, coupling_map: &CouplingMap) -> Layout {
+    assert_fits(circuit, coupling_map);
+
+    // There is no connected subset of zero qubits to grow.
+    if circuit.qubits() == 0 {
+        return Layout::trivial(0, coupling_map.qubits());
+    }
+
+    let subset = densest_subset(coupling_map, circuit.qubits());
+    place_qubits(
+        circuit,
+        coupling_map,
+        &subset,
+        &vec![0f64; coupling_map.qubits()],
+    )
+}
+
+/// Places logical qubits, so that qubits, that interact the most, are the closest to
+/// each other, while avoiding physical qubits with high error rates.
+///
+/// `error_rates[i]` is an error rate of physical qubit `i`. The pass minimizes the
+/// estimated amount of swaps plus the expected amount of errors, that is a sum of
+/// error rates of physical qubits weighted by the amount of gates applied to them.
+pub fn noise_aware_layout(
+    circuit: &QuantumCircuit,
+    coupling_map: &CouplingMap,
+    error_rates: &[f64],
+) -> Layout {
+    assert_fits(circuit, coupling_map);
+    assert_eq!(
+        error_rates.len(),
+        coupling_map.qubits(),
+        "Error rates don't match the amount of qubits in the coupling map"
+    );
+
+    let candidates: Vec<usize> = (0..coupling_map.qubits()).collect();
+    place_qubits(circuit, coupling_map, &candidates, error_rates)
+}
+
+/// Estimates the amount of swaps, that routing has to insert into the circuit, as
+/// the sum of distances (minus one) between qubits of every two-qubit interaction.
+pub fn estimate_swaps(
+    circuit: &QuantumCircuit,
+    coupling_map: &CouplingMap,
+    layout: &Layout,
+) -> usize {
+    let interactions = interactions(circuit);
+    let distances = coupling_map.distance_matrix();
+
+    let mut swaps = 0;
+    for (logical1, row) in interactions.iter().enumerate() {
+        for (logical2, count) in row.iter().enumerate().skip(logical1 + 1) {
+            if *count == 0 {
+                continue;
+            }
+
+            let distance = distances[layout.physical(logical1)][layout.physical(logical2)]
+                .expect("Interacting qubits are placed on disconnected physical qubits");
+            swaps += count * (distance - 1);
+        }
+    }
+
+    swaps
+}
+
+/// Checks that the device has enough qubits for the circuit.
+fn assert_fits(circuit: &QuantumCircuit, coupling_map: &CouplingMap) {
+    assert!(
+        circuit.qubits() <= coupling_map.qubits(),
+        "Circuit with {} qubits doesn't fit into coupling map with {} qubits",
+        circuit.qubits(),
+        coupling_map.qubits()
+    );
+}
+
+/// Counts two-qubit interactions between every pair of logical qubits. Gates on more
+/// than two qubits count as interactions between every pair of their qubits.
+fn interactions(circuit: &QuantumCircuit) -> Vec<Vec<usize>> {
+    let mut interactions = vec![vec![0; circuit.qubits()]; circuit.qubits()];
+
+    for instruction in circuit.flatten().instructions() {
+        if let Instruction::Barrier(..) = instruction {
+            continue;
+        }
+
+        let qubits = instruction.qubits();
+
+        for (i, qubit1) in qubits.iter().enumerate() {
+            for qubit2 in &qubits[i + 1..] {
+                interactions[*qubit1][*qubit2] += 1;
+                interactions[*qubit2][*qubit1] += 1;
+            }
+        }
+    }
+
+    interactions
+}
+
+/// Grows a connected set of `size` physical qubits from every qubit, greedily adding
+/// the neighbour with the most couplings into the set, and returns the set with the
+/// most couplings inside of it.
+fn densest_subset(coupling_map: &CouplingMap, size: usize) -> Vec<usize> {
+    let mut best: Option<(usize, Vec<usize>)> = None;
+
+    for start in 0..coupling_map.qubits() {
+        let mut subset = vec![start];
+        let mut couplings = 0;
+
+        while subset.len() < size {
+            let next = subset
+                .iter()
+                .flat_map(|qubit| coupling_map.neighbours(*qubit))
+                .filter(|qubit| !subset.contains(qubit))
+                .map(|qubit| {
+                    let links = coupling_map
+                        .neighbours(*qubit)
+                        .iter()
+                        .filter(|neighbour| subset.contains(neighbour))
+                        .count();
+                    (links, std::cmp::Reverse(*qubit))
+                })
+                .max();
+
+            match next {
+                Some((links, std::cmp::Reverse(qubit))) => {
+                    subset.push(qubit);
+                    couplings += links;
+                }
+                None => break,
+            }
+        }
+
+        if subset.len() == size && best.as_ref().is_none_or(|(best, _)| couplings > *best) {
+            best = Some((couplings, subset));
+        }
+    }
+
+    best.map(|(_, subset)| subset)
+        .expect("Coupling map doesn't have enough connected qubits for the circuit")
+}
+
+/// Greedily places logical qubits onto the `candidates` physical qubits.
+///
+/// Logical qubits are placed in order of their interactions with already placed ones.
+/// Every logical qubit goes to the free candidate, that minimizes estimated amount of
+/// swaps for interactions with already placed qubits plus the error rate of the
+/// candidate weighted by the amount of gates on the logical qubit. Placement is tried
+/// starting from every candidate and the cheapest layout is returned.
+fn place_qubits(
+    circuit: &QuantumCircuit,
+    coupling_map: &CouplingMap,
+    candidates: &[usize],
+    error_rates: &[f64],
+) -> Layout {
+    let interactions = interactions(circuit);
+    let distances = coupling_map.distance_matrix();
+
+    let mut gate_counts = vec![0; circuit.qubits()];
+    for instruction in circuit.flatten().instructions() {
+        if let Instruction::Barrier(..) = instruction {
+            continue;
+        }
+
+        for qubit in instruction.qubits() {
+            gate_counts[qubit] += 1;
+        }
+    }
+
+    // Interactions between disconnected qubits are as expensive as possible.
+    let swaps = |logical: usize, physical: usize, placement: &[Option<usize>]| -> usize {
+        (0..circuit.qubits())
+            .filter_map(|other| Some((other, placement[other]?)))
+            .filter(|(other, _)| interactions[logical][*other] > 0)
+            .map(|(other, other_physical)| {
+                let distance = distances[physical][other_physical].unwrap_or(usize::MAX);
+                interactions[logical][other].saturating_mul(distance - 1)
+            })
+            .fold(0, usize::saturating_add)
+    };
+    let errors = |logical: usize, physical: usize| -> f64 {
+        error_rates[physical] * gate_counts[logical] as f64
+    };
+
+    let mut best: Option<(f64, Vec<Option<usize>>)> = None;
+
+    for first in candidates {
+        let mut placement: Vec<Option<usize>> = vec![None; circuit.qubits()];
+        let mut free: Vec<usize> = candidates.to_vec();
+        let mut total_cost = 0f64;
+
+        for _ in 0..circuit.qubits() {
+            let placed_weight = |logical: usize| -> usize {
+                (0..circuit.qubits())
+                    .filter(|other| placement[*other].is_some())
+                    .map(|other| interactions[logical][other])
+                    .sum()
+            };
+            let total_weight = |logical: usize| -> usize { interactions[logical].iter().sum() };
+
+            let logical = (0..circuit.qubits())
+                .filter(|logical| placement[*logical].is_none())
+                .max_by_key(|logical| {
+                    (
+                        placed_weight(*logical),
+                        total_weight(*logical),
+                        std::cmp::Reverse(*logical),
+                    )
+                })
+                .unwrap();
+
+            let cost = |physical: usize| -> f64 {
+                swaps(logical, physical, &placement) as f64 + errors(logical, physical)
+            };
+
+            let position = if free.len() == candidates.len() {
+                free.iter().position(|physical| physical == first).unwrap()
+            } else {
+                // Among equally good qubits prefer ones with more free neighbours, so
+                // that partners placed later can be put next to it.
+                let free_neighbours = |physical: usize| {
+                    coupling_map
+                        .neighbours(physical)
+                        .iter()
+                        .filter(|neighbour| free.contains(neighbour))
+                        .count()
+                };
+
+                (0..free.len())
+                    .min_by(|a, b| {
+                        cost(free[*a])
+                            .total_cmp(&cost(free[*b]))
+                            .then(free_neighbours(free[*b]).cmp(&free_neighbours(free[*a])))
+                    })
+                    .unwrap()
+            };
+
+            let physical = free.remove(position);
+            total_cost += cost(physical);
+            placement[logical] = Some(physical);
+        }
+
+        if best.as_ref().is_none_or(|(best, _)| total_cost < *best) {
+            best = Some((total_cost, placement));
+        }
+    }
+
+    let placement = best.map_or_else(Vec::new, |(_, placement)| placement);
+    Layout::new(
+        placement.into_iter().map(Option::unwrap).collect(),
+        coupling_map.qubits(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        coupling_map::CouplingMap,
+        layout::{
+            dense_layout, estimate_swaps, noise_aware_layout, select_layout, trivial_layout,
+            LayoutMethod,
+        },
+        quantum_circuit::{Instruction, QuantumCircuit},
+        routing::route,
+    };
+
+    fn cycle_circuit() -> QuantumCircuit {
+        let mut circuit = QuantumCircuit::new(4);
+        for control in 0..4 {
+            circuit.add(Instruction::ControlledNot {
+                control,
+                target: (control + 1) % 4,
+            });
+        }
+        circuit
+    }
+
+    #[test]
+    fn dense_layout_avoids_swaps() {
+        let circuit = cycle_circuit();
+        let coupling_map = CouplingMap::grid(3, 3);
+
+        let trivial = trivial_layout(&circuit, &coupling_map);
+        assert_eq!(estimate_swaps(&circuit, &coupling_map, &trivial), 2);
+
+        let dense = dense_layout(&circuit, &coupling_map);
+        let mut physical = dense.as_slice().to_vec();
+        physical.sort_unstable();
+        assert_eq!(physical, vec![0, 1, 3, 4]);
+        assert_eq!(estimate_swaps(&circuit, &coupling_map, &dense), 0);
+    }
+
+    #[test]
+    fn noise_aware_layout_avoids_noisy_qubits() {
+        let mut circuit = QuantumCircuit::new(3);
+        circuit.add(Instruction::ControlledNot {
+            control: 0,
+            target: 1,
+        });
+        circuit.add(Instruction::ControlledNot {
+            control: 1,
+            target: 2,
+        });
+
+        let coupling_map = CouplingMap::ring(6);
+        let error_rates = [0.3, 0.01, 0.02, 0.01, 0.2, 0.4];
+        let layout = noise_aware_layout(&circuit, &coupling_map, &error_rates);
+
+        assert_eq!(layout.as_slice(), &[1, 2, 3]);
+        assert_eq!(estimate_swaps(&circuit, &coupling_map, &layout), 0);
+    }
+
+    #[test]
+    fn places_qubits_on_disconnected_coupling_maps() {
+        let mut circuit = QuantumCircuit::new(3);
+        circuit.cx(0, 1).cx(0, 1).cx(1, 0).cx(0, 1);
+        circuit.cx(1, 2).cx(2, 1).cx(1, 2);
+
+        let coupling_map = CouplingMap::from_edges(3, &[(0, 1)]);
+        let layout = noise_aware_layout(&circuit, &coupling_map, &[0f64; 3]);
+
+        assert!(coupling_map.are_coupled(layout.physical(0), layout.physical(1)));
+        assert_eq!(layout.physical(2), 2);
+    }
+
+    #[test]
+    fn lays_out_empty_circuits() {
+        let circuit = QuantumCircuit::new(0);
+        let coupling_map = CouplingMap::line(3);
+
+        for method in [LayoutMethod::Trivial, LayoutMethod::Dense] {
+            let circuit = select_layout(&circuit, &coupling_map, method);
+            assert_eq!(circuit.layout().unwrap().as_slice(), &[] as &[usize]);
+        }
+        let layout = noise_aware_layout(&circuit, &coupling_map, &[0.1; 3]);
+        assert_eq!(layout.physical_qubits(), 3);
+    }
+
+    #[test]
+    fn routing_uses_recorded_layout() {
+        let coupling_map = CouplingMap::grid(3, 3);
+        let circuit = select_layout(&cycle_circuit(), &coupling_map, LayoutMethod::Dense);
+
+        let routed = route(&circuit, &coupling_map);
+        assert_eq!(routed.swaps, 0);
+        assert_eq!(&routed.initial_layout, circuit.layout().unwrap());
+    }
+}
@@ -2,8 +2,26 @@
 pub mod complex;
 #[macro_use]
 pub mod matrix;
+pub mod amplitude_buffer;
 pub mod ascii_circuit_visualizer;
 pub mod backend;
+pub mod binary_format;
+#[macro_use]
+pub mod circuit_builder;
+pub mod circuit_dag;
 pub mod classical_register;
+pub mod coupling_map;
+mod gate_fusion;
+pub mod job;
+pub mod layout;
+pub mod mps_backend;
+pub mod peephole_optimizer;
 pub mod quantum_circuit;
+pub mod quil;
+pub mod register;
+pub mod routing;
+pub mod sparse_backend;
+pub mod stabilizer_backend;
+pub mod state_preparation;
+pub mod statevector;
 pub mod statevector_backend;
@@ -3,6 +3,8 @@
 use crate::complex::Complex;
 use core::fmt;
 use num::{One, Zero};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::{
     fmt::{Debug, Display, Write},
     ops::{Add, Mul},
@@ -10,12 +12,48 @@ use std::{
 
 /// Represents matrix data structure.
 #[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "SerializedMatrix<T>")
+)]
 pub struct Matrix<T> {
     rows: usize,
     cols: usize,
     data: Vec<T>,
 }
 
+/// Serialized form of the matrix, that is checked before being turned into one.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SerializedMatrix<T> {
+    rows: usize,
+    cols: usize,
+    data: Vec<T>,
+}
+
+#[cfg(feature = "serde")]
+impl<T> TryFrom<SerializedMatrix<T>> for Matrix<T> {
+    type Error = String;
+
+    fn try_from(serialized: SerializedMatrix<T>) -> Result<Matrix<T>, String> {
+        if serialized.rows.checked_mul(serialized.cols) != Some(serialized.data.len()) {
+            return Err(format!(
+                "Matrix {}x{} can't contain {} elements",
+                serialized.rows,
+                serialized.cols,
+                serialized.data.len()
+            ));
+        }
+
+        Ok(Matrix {
+            rows: serialized.rows,
+            cols: serialized.cols,
+            data: serialized.data,
+        })
+    }
+}
+
 impl<T: Clone + Default + Debug> Matrix<T> {
     /// Constructs a new matrix with elements being initialized using
     /// [`Default::default()`].
@@ -214,7 +252,7 @@ where
     }
 }
 
-impl<'a, T> Add<&'a Matrix<T>> for Matrix<T>
+impl<T> Add<&Matrix<T>> for Matrix<T>
 where
     T: Add<Output = T> + Clone + Default + Debug,
 {
@@ -247,7 +285,7 @@ where
     }
 }
 
-impl<'a, T> Mul<T> for Matrix<T>
+impl<T> Mul<T> for Matrix<T>
 where
     T: Mul<Output = T> + Clone + Default + Copy,
 {
@@ -342,3 +380,18 @@ fn test_matrix_addition() {
     let expected = matrix![[6, 8], [10, 12]];
     assert_eq!(sum, expected);
 }
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_deserialization_checks_size() {
+    let matrix: Matrix<i32> = serde_json::from_str(r#"{"rows":1,"cols":2,"data":[1,2]}"#).unwrap();
+    assert_eq!(matrix, matrix![[1, 2]]);
+
+    for json in [
+        r#"{"rows":2,"cols":2,"data":[1,2]}"#,
+        r#"{"rows":4294967296,"cols":4294967296,"data":[]}"#,
+    ] {
+        let error = serde_json::from_str::<Matrix<i32>>(json).unwrap_err();
+        assert!(error.to_string().contains("can't contain"), "{}", error);
+    }
+}
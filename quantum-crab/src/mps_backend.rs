@@ -0,0 +1,767 @@
+//! Implements matrix product state backend, that simulates circuits with low
+//! entanglement on many qubits.
+//!
+//! The state is stored as a chain of tensors, one per qubit, connected by bonds,
+//! whose dimension grows with the entanglement between the two halves of the chain.
+//! Two-qubit gates are applied to neighbouring qubits, followed by the singular value
+//! decomposition, where the smallest singular values are truncated. Gates on distant
+//! qubits are executed by moving qubits next to each other with swaps.
+//!
+//! ```
+//! use quantum_crab::{
+//!     backend::Backend,
+//!     mps_backend::MpsBackend,
+//!     quantum_circuit::QuantumCircuit,
+//! };
+//! use std::f64::consts::FRAC_1_SQRT_2;
+//!
+//! let mut circuit = QuantumCircuit::new(80);
+//! circuit.h(0);
+//! for qubit in 1..80 {
+//!     circuit.cx(qubit - 1, qubit);
+//! }
+//!
+//! let state = MpsBackend::execute(circuit);
+//! assert!((state.amplitude(&[1; 80]).real - FRAC_1_SQRT_2).abs() < 1e-9);
+//! assert!(state.bond_dimensions().iter().all(|dimension| *dimension <= 2));
+//! ```
+use crate::{
+    backend::{check_instructions, Backend, BackendError, CheckedInitialState, RunOptions},
+    complex::Complex,
+    matrix::Matrix,
+    quantum_circuit::{Instruction, QuantumCircuit},
+    statevector::StateVector,
+    statevector_backend::{single_qubit_gate_matrix, unitary},
+};
+use num::{One, Zero};
+
+/// Matrix product state backend with truncation settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MpsBackend {
+    /// Maximum dimension of bonds between neighbouring qubits, must be positive (see
+    /// [`MpsBackend::with_max_bond_dimension`]).
+    pub max_bond_dimension: usize,
+    /// Maximum fraction of the state norm, that can be discarded in a single
+    /// truncation of the bond, must be in `[0, 1)`.
+    pub truncation_threshold: f64,
+}
+
+impl Default for MpsBackend {
+    fn default() -> MpsBackend {
+        MpsBackend {
+            max_bond_dimension: 64,
+            truncation_threshold: 1e-12,
+        }
+    }
+}
+
+impl MpsBackend {
+    /// Sets maximum dimension of bonds between neighbouring qubits, rejecting zero.
+    ///
+    /// ```
+    /// use quantum_crab::{backend::BackendError, mps_backend::MpsBackend};
+    ///
+    /// let backend = MpsBackend::default().with_max_bond_dimension(16).unwrap();
+    /// assert_eq!(backend.max_bond_dimension, 16);
+    /// assert!(matches!(
+    ///     MpsBackend::default().with_max_bond_dimension(0),
+    ///     Err(BackendError::InvalidSettings(..))
+    /// ));
+    /// ```
+    pub fn with_max_bond_dimension(
+        self,
+        max_bond_dimension: usize,
+    ) -> Result<MpsBackend, BackendError> {
+        let backend = MpsBackend {
+            max_bond_dimension,
+            ..self
+        };
+
+        backend.check_settings()?;
+        Ok(backend)
+    }
+
+    /// Checks the settings, which can also be set directly through public fields.
+    fn check_settings(&self) -> Result<(), BackendError> {
+        if self.max_bond_dimension == 0 {
+            return Err(BackendError::InvalidSettings(
+                "Maximum bond dimension must be positive".to_owned(),
+            ));
+        }
+
+        if !(0f64..1f64).contains(&self.truncation_threshold) {
+            return Err(BackendError::InvalidSettings(format!(
+                "Truncation threshold must be in [0, 1), not {}",
+                self.truncation_threshold
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Backend for MpsBackend {
+    type Output = MatrixProductState;
+
+    /// Executes the circuit with the backend settings. Measurements, resets and
+    /// conditioned instructions are not supported.
+    fn run(
+        &self,
+        circuit: &QuantumCircuit,
+        options: &RunOptions,
+    ) -> Result<MatrixProductState, BackendError> {
+        self.check_settings()?;
+        check_instructions(circuit, |instruction| !instruction.is_dynamic())?;
+
+        let mut state = MatrixProductState::new(circuit.qubits());
+        match options.initial_state(circuit.qubits())? {
+            CheckedInitialState::Basis(bits) => {
+                for (qubit, bit) in bits.iter().enumerate() {
+                    if *bit == 1 {
+                        state.apply_instruction(&Instruction::PauliX(qubit), self);
+                    }
+                }
+            }
+            CheckedInitialState::StateVector(..) => {
+                return Err(BackendError::UnsupportedInitialState)
+            }
+        }
+
+        for instruction in circuit.flatten().instructions() {
+            options.check_cancelled()?;
+            state.apply_instruction(instruction, self);
+        }
+
+        Ok(state)
+    }
+}
+
+/// Tensor of a single qubit, with elements `data[(left * 2 + bit) * right + right_index]`,
+/// so that it can be viewed both as `(left * 2) x right` and `left x (2 * right)` matrix.
+#[derive(Debug, Clone, PartialEq)]
+struct Site {
+    left: usize,
+    right: usize,
+    data: Vec<Complex>,
+}
+
+/// State of qubits, represented by the matrix product state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixProductState {
+    sites: Vec<Site>,
+    /// Site, to the left of which all tensors are left-canonical and to the right
+    /// of which all tensors are right-canonical.
+    center: usize,
+    fidelity: f64,
+}
+
+impl MatrixProductState {
+    /// Constructs state, where all qubits are `|0>`.
+    pub fn new(qubits: usize) -> MatrixProductState {
+        MatrixProductState {
+            sites: vec![
+                Site {
+                    left: 1,
+                    right: 1,
+                    data: vec![Complex::one(), Complex::zero()],
+                };
+                qubits
+            ],
+            center: 0,
+            fidelity: 1f64,
+        }
+    }
+
+    /// Amount of qubits.
+    #[inline]
+    pub fn qubits(&self) -> usize {
+        self.sites.len()
+    }
+
+    /// Estimated fidelity of the state with the exact one, that is the product of
+    /// norms kept after every truncation.
+    #[inline]
+    pub fn fidelity(&self) -> f64 {
+        self.fidelity
+    }
+
+    /// Dimensions of bonds between qubits `i` and `i + 1`.
+    pub fn bond_dimensions(&self) -> Vec<usize> {
+        self.sites.iter().skip(1).map(|site| site.left).collect()
+    }
+
+    /// Returns amplitude of the basis state, where qubit `i` has value `bits[i]`.
+    pub fn amplitude(&self, bits: &[u8]) -> Complex {
+        assert_eq!(bits.len(), self.qubits(), "Invalid amount of bits");
+
+        let mut vector = vec![Complex::one()];
+        for (site, bit) in self.sites.iter().zip(bits) {
+            assert!(*bit <= 1, "Invalid bit {}", bit);
+
+            vector = (0..site.right)
+                .map(|right| {
+                    vector
+                        .iter()
+                        .enumerate()
+                        .map(|(left, value)| {
+                            *value * site.data[(left * 2 + *bit as usize) * site.right + right]
+                        })
+                        .sum()
+                })
+                .collect();
+        }
+
+        vector[0]
+    }
+
+    /// Returns the state vector with amplitudes of all basis states, if its size
+    /// fits into `usize`.
+    pub fn to_statevector(&self) -> Result<StateVector, BackendError> {
+        let size = u32::try_from(self.qubits())
+            .ok()
+            .and_then(|qubits| 1usize.checked_shl(qubits))
+            .ok_or(BackendError::TooManyQubits {
+                qubits: self.qubits(),
+                max_qubits: usize::BITS as usize - 1,
+            })?;
+
+        let statevector = (0..size)
+            .map(|index| {
+                let bits: Vec<_> = (0..self.qubits())
+                    .map(|qubit| (index >> qubit & 1) as u8)
+                    .collect();
+                self.amplitude(&bits)
+            })
+            .collect();
+
+        Ok(StateVector::new(statevector))
+    }
+
+    fn apply_instruction(&mut self, instruction: &Instruction, backend: &MpsBackend) {
+        match *instruction {
+            Instruction::Identity(..) | Instruction::Barrier(..) => {}
+            Instruction::Measure { .. } | Instruction::Reset(..) | Instruction::If { .. } => {
+                unreachable!("Measurements are rejected by the backend")
+            }
+            Instruction::Toffoli { .. } => {
+                for instruction in instruction.decompose_toffoli().unwrap() {
+                    self.apply_instruction(&instruction, backend);
+                }
+            }
+            Instruction::ControlledNot { .. }
+            | Instruction::ControlledU { .. }
+            | Instruction::Swap(..) => {
+                let qubits = instruction.qubits();
+                let (low, high) = (qubits[0].min(qubits[1]), qubits[0].max(qubits[1]));
+
+                let mut circuit = QuantumCircuit::new(2);
+                circuit.add(instruction.map_qubits(|qubit| (qubit == high) as usize));
+                let gate = two_qubit_gate(&unitary(&circuit));
+
+                // Move the high qubit next to the low one and back.
+                let swap = two_qubit_gate(&unitary(&swap_circuit()));
+                for site in (low + 1..high).rev() {
+                    self.apply_two_qubit_gate(site, &swap, backend);
+                }
+                self.apply_two_qubit_gate(low, &gate, backend);
+                for site in low + 1..high {
+                    self.apply_two_qubit_gate(site, &swap, backend);
+                }
+            }
+            _ => self.apply_single_qubit_gate(
+                instruction.qubits()[0],
+                &single_qubit_gate_matrix(instruction),
+            ),
+        }
+    }
+
+    fn apply_single_qubit_gate(&mut self, qubit: usize, gate: &Matrix<Complex>) {
+        let site = &mut self.sites[qubit];
+
+        for left in 0..site.left {
+            for right in 0..site.right {
+                let zero = site.data[left * 2 * site.right + right];
+                let one = site.data[(left * 2 + 1) * site.right + right];
+
+                site.data[left * 2 * site.right + right] =
+                    gate.get(0, 0) * zero + gate.get(0, 1) * one;
+                site.data[(left * 2 + 1) * site.right + right] =
+                    gate.get(1, 0) * zero + gate.get(1, 1) * one;
+            }
+        }
+    }
+
+    /// Moves the orthogonality center to the site by exact decompositions.
+    fn move_center(&mut self, site: usize) {
+        while self.center < site {
+            let center = self.center;
+            let (left, right) = (self.sites[center].left, self.sites[center].right);
+            let svd = Svd::new(left * 2, right, &self.sites[center].data);
+            let rank = svd.values.len();
+
+            let next = &self.sites[center + 1];
+            let data = multiply(
+                &svd.singular_vh(rank),
+                rank,
+                right,
+                &next.data,
+                2 * next.right,
+            );
+            self.sites[center + 1] = Site {
+                left: rank,
+                right: next.right,
+                data,
+            };
+            self.sites[center] = Site {
+                left,
+                right: rank,
+                data: svd.u(rank),
+            };
+            self.center += 1;
+        }
+
+        while self.center > site {
+            let center = self.center;
+            let (left, right) = (self.sites[center].left, self.sites[center].right);
+            let svd = Svd::new(left, 2 * right, &self.sites[center].data);
+            let rank = svd.values.len();
+
+            let previous = &self.sites[center - 1];
+            let data = multiply(
+                &previous.data,
+                previous.left * 2,
+                left,
+                &svd.u_singular(rank),
+                rank,
+            );
+            self.sites[center - 1] = Site {
+                left: previous.left,
+                right: rank,
+                data,
+            };
+            self.sites[center] = Site {
+                left: rank,
+                right,
+                data: svd.vh(rank),
+            };
+            self.center -= 1;
+        }
+    }
+
+    /// Applies the gate to qubits `site` and `site + 1`, where the gate matrix is
+    /// indexed by `bit(site) * 2 + bit(site + 1)`.
+    fn apply_two_qubit_gate(
+        &mut self,
+        site: usize,
+        gate: &[[Complex; 4]; 4],
+        backend: &MpsBackend,
+    ) {
+        self.move_center(site);
+
+        let (first, second) = (&self.sites[site], &self.sites[site + 1]);
+        let (left, right) = (first.left, second.right);
+        let theta = multiply(&first.data, left * 2, first.right, &second.data, 2 * right);
+
+        // Apply the gate to the tensor viewed as theta[left][bit1][bit2][right].
+        let mut updated = vec![Complex::zero(); theta.len()];
+        for l in 0..left {
+            for r in 0..right {
+                let index =
+                    |bits: usize| (l * 2 + (bits >> 1)) * 2 * right + (bits & 1) * right + r;
+
+                for (output, row) in gate.iter().enumerate() {
+                    updated[index(output)] =
+                        (0..4).map(|input| row[input] * theta[index(input)]).sum();
+                }
+            }
+        }
+
+        let svd = Svd::new(left * 2, 2 * right, &updated);
+        let total: f64 = svd.values.iter().map(|value| value * value).sum();
+
+        let mut rank = svd.values.len();
+        let mut discarded = 0f64;
+        while rank > 1 {
+            let weight = svd.values[rank - 1] * svd.values[rank - 1];
+            if discarded + weight > backend.truncation_threshold * total {
+                break;
+            }
+
+            discarded += weight;
+            rank -= 1;
+        }
+        rank = rank.min(backend.max_bond_dimension);
+
+        let kept: f64 = svd.values[..rank].iter().map(|value| value * value).sum();
+        self.fidelity *= kept / total;
+
+        let mut second = svd.singular_vh(rank);
+        let scale = Complex::from((total / kept).sqrt());
+        for value in &mut second {
+            *value *= scale;
+        }
+
+        self.sites[site] = Site {
+            left,
+            right: rank,
+            data: svd.u(rank),
+        };
+        self.sites[site + 1] = Site {
+            left: rank,
+            right,
+            data: second,
+        };
+        self.center = site + 1;
+    }
+}
+
+fn swap_circuit() -> QuantumCircuit {
+    let mut circuit = QuantumCircuit::new(2);
+    circuit.swap(0, 1);
+    circuit
+}
+
+/// Converts unitary of the two-qubit circuit into the gate indexed by
+/// `bit(0) * 2 + bit(1)`.
+fn two_qubit_gate(unitary: &Matrix<Complex>) -> [[Complex; 4]; 4] {
+    let mut gate = [[Complex::zero(); 4]; 4];
+    let index = |bits: usize| (bits >> 1) | (bits & 1) << 1;
+
+    for (output, row) in gate.iter_mut().enumerate() {
+        for (input, value) in row.iter_mut().enumerate() {
+            *value = unitary.get(index(output), index(input));
+        }
+    }
+
+    gate
+}
+
+/// Multiplies row-major `rows x inner` and `inner x cols` matrices.
+fn multiply(
+    lhs: &[Complex],
+    rows: usize,
+    inner: usize,
+    rhs: &[Complex],
+    cols: usize,
+) -> Vec<Complex> {
+    let mut result = vec![Complex::zero(); rows * cols];
+
+    for row in 0..rows {
+        for k in 0..inner {
+            let value = lhs[row * inner + k];
+            if value == Complex::zero() {
+                continue;
+            }
+
+            for col in 0..cols {
+                result[row * cols + col] += value * rhs[k * cols + col];
+            }
+        }
+    }
+
+    result
+}
+
+/// Singular value decomposition `A = U S V^dagger` of the row-major matrix, with
+/// singular values sorted in descending order.
+struct Svd {
+    rows: usize,
+    cols: usize,
+    /// Columns of `U`.
+    u: Vec<Vec<Complex>>,
+    values: Vec<f64>,
+    /// Columns of `V`.
+    v: Vec<Vec<Complex>>,
+}
+
+impl Svd {
+    fn new(rows: usize, cols: usize, data: &[Complex]) -> Svd {
+        if rows < cols {
+            // A^dagger = U S V^dagger, so A = V S U^dagger.
+            let mut adjoint = vec![Complex::zero(); rows * cols];
+            for row in 0..rows {
+                for col in 0..cols {
+                    adjoint[col * rows + row] = data[row * cols + col].conjugate();
+                }
+            }
+
+            let svd = Svd::new(cols, rows, &adjoint);
+            return Svd {
+                rows,
+                cols,
+                u: svd.v,
+                values: svd.values,
+                v: svd.u,
+            };
+        }
+
+        // One-sided Jacobi method: rotate columns of A until they are orthogonal, then
+        // A V = U S.
+        let mut a: Vec<Vec<Complex>> = (0..cols)
+            .map(|col| (0..rows).map(|row| data[row * cols + col]).collect())
+            .collect();
+        let mut v: Vec<Vec<Complex>> = (0..cols)
+            .map(|col| {
+                (0..cols)
+                    .map(|row| {
+                        if row == col {
+                            Complex::one()
+                        } else {
+                            Complex::zero()
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for _ in 0..100 {
+            let mut rotated = false;
+
+            for p in 0..cols {
+                for q in p + 1..cols {
+                    let alpha: f64 = a[p].iter().map(|value| value.norm().powi(2)).sum();
+                    let beta: f64 = a[q].iter().map(|value| value.norm().powi(2)).sum();
+                    let gamma: Complex = a[p]
+                        .iter()
+                        .zip(&a[q])
+                        .map(|(x, y)| x.conjugate() * *y)
+                        .sum();
+
+                    let magnitude = gamma.norm();
+                    if magnitude <= 1e-15 * (alpha * beta).sqrt() || magnitude < f64::MIN_POSITIVE {
+                        continue;
+                    }
+                    rotated = true;
+
+                    // Make the inner product real, then apply real Jacobi rotation.
+                    let phase = Complex::new(gamma.real / magnitude, -gamma.imag / magnitude);
+                    let zeta = (beta - alpha) / (2f64 * magnitude);
+                    let t = if zeta == 0f64 {
+                        1f64
+                    } else {
+                        zeta.signum() / (zeta.abs() + (1f64 + zeta * zeta).sqrt())
+                    };
+                    let c = 1f64 / (1f64 + t * t).sqrt();
+                    let s = c * t;
+
+                    for columns in [&mut a, &mut v] {
+                        for i in 0..columns[p].len() {
+                            let x = columns[p][i];
+                            let y = columns[q][i] * phase;
+                            columns[p][i] = Complex::from(c) * x - Complex::from(s) * y;
+                            columns[q][i] = Complex::from(s) * x + Complex::from(c) * y;
+                        }
+                    }
+                }
+            }
+
+            if !rotated {
+                break;
+            }
+        }
+
+        let norms: Vec<f64> = a
+            .iter()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|value| value.norm().powi(2))
+                    .sum::<f64>()
+                    .sqrt()
+            })
+            .collect();
+        let mut order: Vec<usize> = (0..cols).collect();
+        order.sort_by(|i, j| norms[*j].total_cmp(&norms[*i]));
+
+        Svd {
+            rows,
+            cols,
+            u: order
+                .iter()
+                .map(|i| {
+                    a[*i]
+                        .iter()
+                        .map(|value| {
+                            if norms[*i] > 0f64 {
+                                *value * Complex::from(1f64 / norms[*i])
+                            } else {
+                                Complex::zero()
+                            }
+                        })
+                        .collect()
+                })
+                .collect(),
+            values: order.iter().map(|i| norms[*i]).collect(),
+            v: order.iter().map(|i| v[*i].clone()).collect(),
+        }
+    }
+
+    /// First `rank` columns of `U` as row-major `rows x rank` matrix.
+    fn u(&self, rank: usize) -> Vec<Complex> {
+        (0..self.rows * rank)
+            .map(|index| self.u[index % rank][index / rank])
+            .collect()
+    }
+
+    /// First `rank` columns of `U S` as row-major `rows x rank` matrix.
+    fn u_singular(&self, rank: usize) -> Vec<Complex> {
+        (0..self.rows * rank)
+            .map(|index| {
+                self.u[index % rank][index / rank] * Complex::from(self.values[index % rank])
+            })
+            .collect()
+    }
+
+    /// First `rank` rows of `V^dagger` as row-major `rank x cols` matrix.
+    fn vh(&self, rank: usize) -> Vec<Complex> {
+        (0..rank * self.cols)
+            .map(|index| self.v[index / self.cols][index % self.cols].conjugate())
+            .collect()
+    }
+
+    /// First `rank` rows of `S V^dagger` as row-major `rank x cols` matrix.
+    fn singular_vh(&self, rank: usize) -> Vec<Complex> {
+        (0..rank * self.cols)
+            .map(|index| {
+                self.v[index / self.cols][index % self.cols].conjugate()
+                    * Complex::from(self.values[index / self.cols])
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{Backend, BackendError, RunOptions},
+        complex::Complex,
+        mps_backend::MpsBackend,
+        quantum_circuit::{Instruction, QuantumCircuit},
+        statevector_backend::StateVectorBackend,
+    };
+    use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+    fn assert_close(actual: Complex, expected: Complex) {
+        assert!(
+            (actual.real - expected.real).abs() < 1e-9
+                && (actual.imag - expected.imag).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn matches_statevector_backend() {
+        let mut inner = QuantumCircuit::new(2);
+        inner.h(0).cx(0, 1);
+
+        let mut circuit = QuantumCircuit::new(5);
+        circuit
+            .h(0)
+            .rx(1, 0.3)
+            .cx(0, 4)
+            .ry(3, 1.1)
+            .cu(
+                Instruction::RotationZ {
+                    qubit: 0,
+                    phase: PI / 3f64,
+                },
+                3,
+                1,
+            )
+            .custom("bell", inner, [2, 3])
+            .ccx(4, 1, 2)
+            .swap(0, 3)
+            .t(2)
+            .cx(4, 0);
+
+        let state = MpsBackend::execute(circuit.clone())
+            .to_statevector()
+            .unwrap();
+        let expected = StateVectorBackend::execute(circuit);
+
+        for index in 0..32 {
+            assert_close(state.amplitudes()[index], expected.amplitudes()[index]);
+        }
+    }
+
+    #[test]
+    fn simulates_wide_circuits() {
+        let mut circuit = QuantumCircuit::new(100);
+        circuit.h(0);
+        for qubit in 1..50 {
+            circuit.cx(qubit - 1, qubit);
+        }
+        circuit.cx(0, 99).x(70);
+
+        let state = MpsBackend::execute(circuit);
+        let mut bits = vec![0; 100];
+        bits[70] = 1;
+        assert_close(state.amplitude(&bits), FRAC_1_SQRT_2.into());
+
+        bits[..50].fill(1);
+        bits[99] = 1;
+        assert_close(state.amplitude(&bits), FRAC_1_SQRT_2.into());
+        assert_eq!(state.fidelity(), 1f64);
+        assert!(state
+            .bond_dimensions()
+            .iter()
+            .all(|dimension| *dimension <= 2));
+    }
+
+    #[test]
+    fn truncates_bonds() {
+        let mut circuit = QuantumCircuit::new(2);
+        circuit.ry(0, PI / 3f64).cx(0, 1);
+
+        let backend = MpsBackend {
+            max_bond_dimension: 1,
+            ..MpsBackend::default()
+        };
+        let state = backend.run(&circuit, &RunOptions::default()).unwrap();
+
+        // Only the larger Schmidt coefficient cos(pi / 6) is kept.
+        assert!((state.fidelity() - 0.75).abs() < 1e-12);
+        assert_eq!(state.bond_dimensions(), vec![1]);
+        assert_close(state.amplitude(&[0, 0]), 1.into());
+
+        let backend = MpsBackend {
+            max_bond_dimension: 0,
+            ..MpsBackend::default()
+        };
+        assert!(matches!(
+            backend.run(&circuit, &RunOptions::default()),
+            Err(BackendError::InvalidSettings(..))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_truncation_thresholds() {
+        let circuit = circuit!(2; h 0; cx 0 1);
+
+        for truncation_threshold in [f64::NAN, f64::INFINITY, -0.5, 1.0, 2.0] {
+            let backend = MpsBackend {
+                truncation_threshold,
+                ..MpsBackend::default()
+            };
+            assert!(matches!(
+                backend.run(&circuit, &RunOptions::default()),
+                Err(BackendError::InvalidSettings(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_too_large_state_vectors() {
+        let state = MpsBackend::execute(QuantumCircuit::new(64));
+
+        assert_eq!(
+            state.to_statevector(),
+            Err(BackendError::TooManyQubits {
+                qubits: 64,
+                max_qubits: 63
+            })
+        );
+    }
+}
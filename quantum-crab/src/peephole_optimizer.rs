@@ -0,0 +1,500 @@
+//! Implements peephole optimization pass over quantum circuits.
+//!
+//! The pass looks at small windows of instructions and removes redundancy:
+//! - adjacent inverse pairs (`H·H`, `X·X`, `CNOT·CNOT`, `T·T dagger`, ...) are cancelled;
+//! - consecutive rotations around the same axis are merged into a single rotation;
+//! - [`Instruction::Identity`] and zero-angle rotations are dropped.
+//!
+//! Instructions are not required to be directly adjacent: an instruction is moved back
+//! through every instruction it commutes with (instructions on other qubits, diagonal
+//! gates, controls of controlled gates, ...) while looking for a partner.
+use crate::quantum_circuit::{Instruction, QuantumCircuit};
+use std::f64::consts::PI;
+
+/// Angles closer than this to the identity rotation are treated as zero.
+const ANGLE_TOLERANCE: f64 = 1e-12;
+
+/// Gate counts and depth of the circuit before and after the optimization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizationReport {
+    /// Amount of instructions in the original circuit.
+    pub gates_before: usize,
+    /// Amount of instructions in the optimized circuit.
+    pub gates_after: usize,
+    /// Depth of the original circuit.
+    pub depth_before: usize,
+    /// Depth of the optimized circuit.
+    pub depth_after: usize,
+}
+
+/// Runs peephole optimization over the circuit, returning optimized circuit and
+/// the report.
+///
+/// ```
+/// use quantum_crab::{
+///   peephole_optimizer::optimize,
+///   quantum_circuit::{QuantumCircuit, Instruction}
+/// };
+///
+/// let mut circuit = QuantumCircuit::new(2);
+/// circuit.add(Instruction::Hadamard(0));
+/// circuit.add(Instruction::T(1));
+/// circuit.add(Instruction::Hadamard(0));
+/// circuit.add(Instruction::TDagger(1));
+///
+/// let (optimized, report) = optimize(&circuit);
+/// assert!(optimized.instructions().is_empty());
+/// assert_eq!(report.gates_before, 4);
+/// assert_eq!(report.gates_after, 0);
+/// ```
+pub fn optimize(circuit: &QuantumCircuit) -> (QuantumCircuit, OptimizationReport) {
+    let optimized = optimize_circuit(circuit);
+
+    let report = OptimizationReport {
+        gates_before: circuit.instructions().len(),
+        gates_after: optimized.instructions().len(),
+        depth_before: circuit.depth(),
+        depth_after: optimized.depth(),
+    };
+
+    (optimized, report)
+}
+
+/// Optimizes the circuit, including inner circuits of custom gates.
+fn optimize_circuit(circuit: &QuantumCircuit) -> QuantumCircuit {
+    let mut instructions = circuit.instructions().clone();
+
+    loop {
+        let optimized = optimize_instructions(&instructions);
+        let changed = optimized.len() != instructions.len();
+        instructions = optimized;
+
+        if !changed {
+            break;
+        }
+    }
+
+    let mut optimized = circuit.empty_copy();
+    for instruction in instructions {
+        optimized.add(instruction);
+    }
+
+    optimized
+}
+
+/// Runs a single optimization sweep over the list of instructions.
+fn optimize_instructions(instructions: &[Instruction]) -> Vec<Instruction> {
+    let mut result: Vec<Instruction> = Vec::with_capacity(instructions.len());
+
+    'instructions: for instruction in instructions {
+        let instruction = match simplify(instruction) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+
+        for index in (0..result.len()).rev() {
+            let previous = &result[index];
+
+            if cancels(previous, &instruction) {
+                result.remove(index);
+                continue 'instructions;
+            }
+
+            if let Some(merged) = merge(previous, &instruction) {
+                match simplify(&merged) {
+                    Some(merged) => result[index] = merged,
+                    None => {
+                        result.remove(index);
+                    }
+                }
+
+                continue 'instructions;
+            }
+
+            if !commutes(previous, &instruction) {
+                break;
+            }
+        }
+
+        result.push(instruction);
+    }
+
+    result
+}
+
+/// Drops the instruction if it doesn't do anything and optimizes inner circuits of
+/// custom gates.
+fn simplify(instruction: &Instruction) -> Option<Instruction> {
+    match instruction {
+        Instruction::Identity(..) => None,
+        Instruction::RotationX { phase, .. }
+        | Instruction::RotationY { phase, .. }
+        | Instruction::RotationZ { phase, .. }
+            if is_zero_angle(*phase, 4f64 * PI) =>
+        {
+            None
+        }
+        Instruction::Phase { phase, .. } | Instruction::PhaseDagger { phase, .. }
+            if is_zero_angle(*phase, 2f64 * PI) =>
+        {
+            None
+        }
+        Instruction::Custom {
+            name,
+            circuit,
+            input_qubits,
+        } => Some(Instruction::Custom {
+            name: name.clone(),
+            circuit: optimize_circuit(circuit),
+            input_qubits: input_qubits.clone(),
+        }),
+        _ => Some(instruction.clone()),
+    }
+}
+
+/// Checks if `angle` is a multiple of the `period`.
+fn is_zero_angle(angle: f64, period: f64) -> bool {
+    let remainder = angle.rem_euclid(period);
+    remainder < ANGLE_TOLERANCE || period - remainder < ANGLE_TOLERANCE
+}
+
+/// Checks if two instructions applied one after another are the identity.
+fn cancels(first: &Instruction, second: &Instruction) -> bool {
+    match (first, second) {
+        (Instruction::Hadamard(a), Instruction::Hadamard(b))
+        | (Instruction::PauliX(a), Instruction::PauliX(b))
+        | (Instruction::PauliY(a), Instruction::PauliY(b))
+        | (Instruction::PauliZ(a), Instruction::PauliZ(b))
+        | (Instruction::T(a), Instruction::TDagger(b))
+        | (Instruction::TDagger(a), Instruction::T(b))
+        | (Instruction::S(a), Instruction::SDagger(b))
+        | (Instruction::SDagger(a), Instruction::S(b)) => a == b,
+        (
+            Instruction::Phase { qubit: a, phase: p },
+            Instruction::PhaseDagger { qubit: b, phase: q },
+        )
+        | (
+            Instruction::PhaseDagger { qubit: a, phase: p },
+            Instruction::Phase { qubit: b, phase: q },
+        ) => a == b && is_zero_angle(p - q, 2f64 * PI),
+        (Instruction::ControlledNot { .. }, Instruction::ControlledNot { .. }) => first == second,
+        (
+            Instruction::Toffoli {
+                control1: a1,
+                control2: a2,
+                target: a,
+            },
+            Instruction::Toffoli {
+                control1: b1,
+                control2: b2,
+                target: b,
+            },
+        ) => a == b && ((a1 == b1 && a2 == b2) || (a1 == b2 && a2 == b1)),
+        (Instruction::Swap(a1, a2), Instruction::Swap(b1, b2)) => {
+            (a1 == b1 && a2 == b2) || (a1 == b2 && a2 == b1)
+        }
+        _ => false,
+    }
+}
+
+/// Merges two rotations around the same axis into a single rotation.
+fn merge(first: &Instruction, second: &Instruction) -> Option<Instruction> {
+    match (first, second) {
+        (
+            Instruction::RotationX { qubit: a, phase: p },
+            Instruction::RotationX { qubit: b, phase: q },
+        ) if a == b => Some(Instruction::RotationX {
+            qubit: *a,
+            phase: p + q,
+        }),
+        (
+            Instruction::RotationY { qubit: a, phase: p },
+            Instruction::RotationY { qubit: b, phase: q },
+        ) if a == b => Some(Instruction::RotationY {
+            qubit: *a,
+            phase: p + q,
+        }),
+        (
+            Instruction::RotationZ { qubit: a, phase: p },
+            Instruction::RotationZ { qubit: b, phase: q },
+        ) if a == b => Some(Instruction::RotationZ {
+            qubit: *a,
+            phase: p + q,
+        }),
+        (Instruction::Phase { qubit: a, phase: p }, Instruction::Phase { qubit: b, phase: q })
+            if a == b =>
+        {
+            Some(Instruction::Phase {
+                qubit: *a,
+                phase: p + q,
+            })
+        }
+        (
+            Instruction::PhaseDagger { qubit: a, phase: p },
+            Instruction::PhaseDagger { qubit: b, phase: q },
+        ) if a == b => Some(Instruction::PhaseDagger {
+            qubit: *a,
+            phase: p + q,
+        }),
+        (
+            Instruction::Phase { qubit: a, phase: p },
+            Instruction::PhaseDagger { qubit: b, phase: q },
+        ) if a == b => Some(Instruction::Phase {
+            qubit: *a,
+            phase: p - q,
+        }),
+        (
+            Instruction::PhaseDagger { qubit: a, phase: p },
+            Instruction::Phase { qubit: b, phase: q },
+        ) if a == b => Some(Instruction::Phase {
+            qubit: *a,
+            phase: q - p,
+        }),
+        _ => None,
+    }
+}
+
+/// Checks if the gate matrix is diagonal in the computational basis.
+fn is_diagonal(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Identity(..)
+        | Instruction::PauliZ(..)
+        | Instruction::Phase { .. }
+        | Instruction::PhaseDagger { .. }
+        | Instruction::T(..)
+        | Instruction::TDagger(..)
+        | Instruction::S(..)
+        | Instruction::SDagger(..)
+        | Instruction::RotationZ { .. } => true,
+        Instruction::ControlledU { gate, .. } => is_diagonal(gate),
+        _ => false,
+    }
+}
+
+/// Checks if the gate is diagonal in the Hadamard basis, i.e. commutes with
+/// targets of controlled-NOT gates.
+fn is_x_rotation(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::PauliX(..) | Instruction::RotationX { .. }
+    )
+}
+
+/// Splits controlled-NOT gates into control qubits and the target qubit.
+fn controlled_not(instruction: &Instruction) -> Option<(Vec<usize>, usize)> {
+    match instruction {
+        Instruction::ControlledNot { control, target } => Some((vec![*control], *target)),
+        Instruction::Toffoli {
+            control1,
+            control2,
+            target,
+        } => Some((vec![*control1, *control2], *target)),
+        _ => None,
+    }
+}
+
+/// Checks if two instructions can be swapped without changing the circuit.
+fn commutes(first: &Instruction, second: &Instruction) -> bool {
+    let first_qubits = first.qubits();
+    let second_qubits = second.qubits();
+
+    let first_clbits = first.clbits();
+    let second_clbits = second.clbits();
+
+    if !first_qubits
+        .iter()
+        .any(|qubit| second_qubits.contains(qubit))
+        && !first_clbits
+            .iter()
+            .any(|clbit| second_clbits.contains(clbit))
+    {
+        return true;
+    }
+
+    if is_diagonal(first) && is_diagonal(second) {
+        return true;
+    }
+
+    commutes_with_controlled_not(first, second) || commutes_with_controlled_not(second, first)
+}
+
+/// Checks if the `instruction` commutes with the controlled-NOT (or Toffoli) gate
+/// `controlled_not`, given that they share qubits.
+fn commutes_with_controlled_not(instruction: &Instruction, controlled_not: &Instruction) -> bool {
+    let (controls, target) = match self::controlled_not(controlled_not) {
+        Some(gate) => gate,
+        None => return false,
+    };
+
+    if let Some((other_controls, other_target)) = self::controlled_not(instruction) {
+        return !controls.contains(&other_target) && !other_controls.contains(&target);
+    }
+
+    match instruction.qubits().as_slice() {
+        [qubit] if controls.contains(qubit) => is_diagonal(instruction),
+        [qubit] if *qubit == target => is_x_rotation(instruction),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        peephole_optimizer::optimize,
+        quantum_circuit::{Instruction, QuantumCircuit},
+    };
+    use std::f64::consts::PI;
+
+    fn circuit(qubits: usize, instructions: Vec<Instruction>) -> QuantumCircuit {
+        let mut circuit = QuantumCircuit::new(qubits);
+        for instruction in instructions {
+            circuit.add(instruction);
+        }
+        circuit
+    }
+
+    #[test]
+    fn cancels_inverse_pairs() {
+        let circuit = circuit(
+            2,
+            vec![
+                Instruction::Hadamard(0),
+                Instruction::ControlledNot {
+                    control: 0,
+                    target: 1,
+                },
+                Instruction::ControlledNot {
+                    control: 0,
+                    target: 1,
+                },
+                Instruction::Hadamard(0),
+                Instruction::Swap(0, 1),
+                Instruction::Swap(1, 0),
+                Instruction::S(1),
+                Instruction::SDagger(1),
+            ],
+        );
+
+        let (optimized, report) = optimize(&circuit);
+        assert!(optimized.instructions().is_empty());
+        assert_eq!(report.gates_before, 8);
+        assert_eq!(report.depth_before, 8);
+        assert_eq!(report.gates_after, 0);
+        assert_eq!(report.depth_after, 0);
+    }
+
+    #[test]
+    fn merges_rotations_and_drops_identities() {
+        let circuit = circuit(
+            1,
+            vec![
+                Instruction::RotationZ {
+                    qubit: 0,
+                    phase: PI / 4f64,
+                },
+                Instruction::Identity(0),
+                Instruction::RotationZ {
+                    qubit: 0,
+                    phase: PI / 2f64,
+                },
+                Instruction::RotationX {
+                    qubit: 0,
+                    phase: 0f64,
+                },
+                Instruction::Phase {
+                    qubit: 0,
+                    phase: 0.5,
+                },
+                Instruction::PhaseDagger {
+                    qubit: 0,
+                    phase: 0.25,
+                },
+            ],
+        );
+
+        let (optimized, _) = optimize(&circuit);
+        assert_eq!(
+            optimized.instructions(),
+            &vec![
+                Instruction::RotationZ {
+                    qubit: 0,
+                    phase: 3f64 * PI / 4f64,
+                },
+                Instruction::Phase {
+                    qubit: 0,
+                    phase: 0.25,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn commutes_through_diagonal_gates() {
+        let circuit = circuit(
+            2,
+            vec![
+                Instruction::T(0),
+                Instruction::ControlledNot {
+                    control: 0,
+                    target: 1,
+                },
+                Instruction::PauliZ(0),
+                Instruction::PauliX(1),
+                Instruction::TDagger(0),
+                Instruction::ControlledNot {
+                    control: 0,
+                    target: 1,
+                },
+                Instruction::PauliX(1),
+            ],
+        );
+
+        let (optimized, report) = optimize(&circuit);
+        assert_eq!(optimized.instructions(), &vec![Instruction::PauliZ(0)]);
+        assert_eq!(report.depth_after, 1);
+    }
+
+    #[test]
+    fn keeps_non_commuting_gates() {
+        let circuit = circuit(
+            2,
+            vec![
+                Instruction::Hadamard(1),
+                Instruction::ControlledNot {
+                    control: 0,
+                    target: 1,
+                },
+                Instruction::Hadamard(1),
+                Instruction::T(1),
+                Instruction::ControlledNot {
+                    control: 0,
+                    target: 1,
+                },
+                Instruction::TDagger(1),
+            ],
+        );
+
+        let (optimized, report) = optimize(&circuit);
+        assert_eq!(optimized, circuit);
+        assert_eq!(report.gates_before, report.gates_after);
+    }
+
+    #[test]
+    fn optimizes_custom_gate_circuits() {
+        let inner = circuit(1, vec![Instruction::PauliX(0), Instruction::PauliX(0)]);
+        let circuit = circuit(
+            1,
+            vec![Instruction::Custom {
+                name: "noop".to_owned(),
+                circuit: inner,
+                input_qubits: vec![0],
+            }],
+        );
+
+        let (optimized, _) = optimize(&circuit);
+        match &optimized.instructions()[0] {
+            Instruction::Custom { circuit, .. } => assert!(circuit.instructions().is_empty()),
+            instruction => panic!("unexpected instruction: {:?}", instruction),
+        }
+    }
+}
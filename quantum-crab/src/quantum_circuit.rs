@@ -1,18 +1,84 @@
+use crate::{
+    classical_register::ClassicalRegister,
+    complex::Complex,
+    layout::Layout,
+    register::{self, Register},
+    state_preparation::state_preparation,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
 /// Represents a set of instructions applied to a set of qubits.
+///
+/// With `serde` feature enabled, circuits are serialized together with the version
+/// of the schema (see [`QuantumCircuit::SCHEMA_VERSION`]):
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "qubits": 2,
+///   "clbits": 0,
+///   "instructions": [{ "Hadamard": 0 }, { "ControlledNot": { "control": 0, "target": 1 } }],
+///   "layout": null
+/// }
+/// ```
+///
+/// Registers declared with [`QuantumCircuit::add_qreg`] and
+/// [`QuantumCircuit::add_creg`] are stored in `qregs` and `cregs` fields, that are
+/// omitted when there are no registers.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "SerializedCircuit", try_from = "SerializedCircuit")
+)]
 pub struct QuantumCircuit {
     qubits: usize,
+    clbits: usize,
     instructions: Vec<Instruction>,
+    layout: Option<Layout>,
+    qregs: Vec<Register>,
+    cregs: Vec<Register>,
 }
 
 impl QuantumCircuit {
+    /// Version of the serialization schema, that is written alongside the circuit.
+    /// Deserializing circuits with other versions fails.
+    pub const SCHEMA_VERSION: u32 = 1;
+
     /// Constructs empty circuit with a concrete amount of qubits.
     #[inline]
     #[must_use]
     pub const fn new(qubits: usize) -> QuantumCircuit {
+        QuantumCircuit::with_clbits(qubits, 0)
+    }
+
+    /// Constructs empty circuit with a concrete amount of qubits and classical bits,
+    /// that measurement results are stored in.
+    #[inline]
+    #[must_use]
+    pub const fn with_clbits(qubits: usize, clbits: usize) -> QuantumCircuit {
         QuantumCircuit {
             qubits,
+            clbits,
             instructions: Vec::new(),
+            layout: None,
+            qregs: Vec::new(),
+            cregs: Vec::new(),
+        }
+    }
+
+    /// Constructs empty circuit with the same qubits, classical bits, registers and
+    /// layout as this one.
+    pub(crate) fn empty_copy(&self) -> QuantumCircuit {
+        QuantumCircuit {
+            qubits: self.qubits,
+            clbits: self.clbits,
+            instructions: Vec::new(),
+            layout: self.layout.clone(),
+            qregs: self.qregs.clone(),
+            cregs: self.cregs.clone(),
         }
     }
 
@@ -24,46 +90,162 @@ impl QuantumCircuit {
     }
 
     /// Validates instruction, before it is added into the circuit.
+    #[inline]
     fn validate_instruction(&self, instruction: &Instruction, custom_gate_circuit: Option<&str>) {
-        match instruction {
-            Instruction::Identity(qubit)
-            | Instruction::PauliX(qubit)
-            | Instruction::PauliY(qubit)
-            | Instruction::PauliZ(qubit)
-            | Instruction::Hadamard(qubit)
-            | Instruction::Phase { qubit, .. }
-            | Instruction::T(qubit)
-            | Instruction::RotationX { qubit, .. }
-            | Instruction::RotationY { qubit, .. }
-            | Instruction::RotationZ { qubit, .. } => {
-                self.validate_input_qubit(*qubit, instruction, custom_gate_circuit)
+        if let Err(message) = self.check_instruction(instruction, custom_gate_circuit) {
+            panic!("{}", message);
+        }
+    }
+
+    /// Checks if instruction can be added into the circuit, returning the reason
+    /// why it can't otherwise.
+    pub(crate) fn check_instruction(
+        &self,
+        instruction: &Instruction,
+        custom_gate_circuit: Option<&str>,
+    ) -> Result<(), String> {
+        if let Instruction::Initialize { qubits, amplitudes } = instruction {
+            let size = u32::try_from(qubits.len())
+                .ok()
+                .and_then(|qubits| 1usize.checked_shl(qubits));
+            if size != Some(amplitudes.len()) {
+                return Err(format!(
+                    "Amount of amplitudes doesn't match the amount of qubits in instruction: {:?}",
+                    instruction
+                ));
+            }
+
+            let norm = amplitudes
+                .iter()
+                .map(|amplitude| amplitude.norm().powi(2))
+                .sum::<f64>();
+            let normalized = (norm - 1f64).abs() <= 1e-9;
+            if !normalized {
+                return Err(format!(
+                    "Amplitudes are not normalized in instruction: {:?}",
+                    instruction
+                ));
+            }
+        }
+
+        if let Instruction::ControlledU { gate, .. } = instruction {
+            if !gate.is_single_qubit_gate() {
+                return Err(format!(
+                    "Controlled gate is not a single-qubit gate in instruction: {:?}",
+                    instruction
+                ));
             }
+        }
+
+        match instruction {
             Instruction::Custom {
                 name,
                 circuit,
                 input_qubits,
             } => {
-                for qubit in input_qubits {
-                    self.validate_input_qubit(*qubit, instruction, custom_gate_circuit);
+                if input_qubits.len() != circuit.qubits() {
+                    return Err(format!(
+                        "Amount of input qubits doesn't match the custom gate in instruction: {:?}",
+                        instruction
+                    ));
+                }
+
+                for (i, qubit) in input_qubits.iter().enumerate() {
+                    self.check_input_qubit(*qubit, instruction, custom_gate_circuit)?;
+
+                    if input_qubits[..i].contains(qubit) {
+                        return Err(format!(
+                            "Repeated input qubit in instruction: {:?}",
+                            instruction
+                        ));
+                    }
+                }
+
+                // Classical bits of the inner circuit are shared with the circuit.
+                if circuit.clbits() > self.clbits {
+                    return Err(format!(
+                        "Invalid classical bit in instruction: {:?}",
+                        instruction
+                    ));
+                }
+
+                // Inner instructions refer to qubits of the inner circuit.
+                for inner in circuit.instructions() {
+                    circuit.check_instruction(inner, Some(name))?;
+                }
+            }
+            Instruction::Measure { qubit, clbit } => {
+                self.check_input_qubit(*qubit, instruction, custom_gate_circuit)?;
+
+                if *clbit >= self.clbits {
+                    return Err(format!(
+                        "Invalid classical bit in instruction: {:?}",
+                        instruction
+                    ));
+                }
+            }
+            Instruction::If {
+                register,
+                value,
+                instruction: inner,
+            } => {
+                for (i, clbit) in register.iter().enumerate() {
+                    if *clbit >= self.clbits || register[..i].contains(clbit) {
+                        return Err(format!(
+                            "Invalid classical bit in instruction: {:?}",
+                            instruction
+                        ));
+                    }
                 }
 
-                for instruction in circuit.instructions() {
-                    self.validate_instruction(instruction, Some(name));
+                if register.len() < 128 && value >> register.len() != 0 {
+                    return Err(format!(
+                        "Value doesn't fit into the register in instruction: {:?}",
+                        instruction
+                    ));
+                }
+
+                if let Instruction::Barrier(..) = **inner {
+                    return Err(format!(
+                        "Barrier can't be conditioned in instruction: {:?}",
+                        instruction
+                    ));
+                }
+
+                self.check_instruction(inner, custom_gate_circuit)?;
+            }
+            _ => {
+                let qubits = instruction.qubits();
+
+                for (i, qubit) in qubits.iter().enumerate() {
+                    self.check_input_qubit(*qubit, instruction, custom_gate_circuit)?;
+
+                    if qubits[..i].contains(qubit) {
+                        return Err(format!(
+                            "Repeated input qubit in instruction: {:?}",
+                            instruction
+                        ));
+                    }
                 }
             }
-            _ => todo!(),
         }
+
+        Ok(())
     }
 
-    /// Validates input qubit used in the instruction, before it is added into
+    /// Checks input qubit used in the instruction, before it is added into
     /// the circuit.
     #[inline]
-    fn validate_input_qubit(
+    fn check_input_qubit(
         &self,
         qubit: usize,
         instruction: &Instruction,
         custom_gate_circuit: Option<&str>,
-    ) {
+    ) -> Result<(), String> {
+        if qubit < self.qubits {
+            return Ok(());
+        }
+
         let mut message = format!("Invalid input qubit in instruction: {:?}", instruction);
         if let Some(circuit_name) = custom_gate_circuit {
             message.push_str(&format!(
@@ -72,7 +254,7 @@ impl QuantumCircuit {
             ));
         }
 
-        assert!(qubit < self.qubits, "{}", message);
+        Err(message)
     }
 
     /// Amount of qubits used in the circuit.
@@ -81,11 +263,474 @@ impl QuantumCircuit {
         self.qubits
     }
 
+    /// Amount of classical bits used in the circuit.
+    #[inline]
+    pub const fn clbits(&self) -> usize {
+        self.clbits
+    }
+
     /// List of instructions inside the circuit.
     #[inline]
     pub const fn instructions(&self) -> &Vec<Instruction> {
         &self.instructions
     }
+
+    /// Amount of qubits and classical bits used in the circuit.
+    #[inline]
+    pub const fn width(&self) -> usize {
+        self.qubits + self.clbits
+    }
+
+    /// Declares quantum register of `size` new qubits, appended after existing
+    /// qubits of the circuit (see [`crate::register`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not valid or is already used by another register, or
+    /// if the circuit has a layout.
+    pub fn add_qreg(&mut self, name: &str, size: usize) -> Register {
+        if let Err(message) = self.check_register_name(name) {
+            panic!("{}", message);
+        }
+        assert!(
+            self.layout.is_none(),
+            "Qubits can't be added to the circuit with layout"
+        );
+
+        let register = Register::new(name, self.qubits, size);
+        self.qubits += size;
+        self.qregs.push(register.clone());
+
+        register
+    }
+
+    /// Declares classical register of `size` new classical bits, appended after
+    /// existing classical bits of the circuit (see [`crate::register`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not valid or is already used by another register.
+    pub fn add_creg(&mut self, name: &str, size: usize) -> Register {
+        if let Err(message) = self.check_register_name(name) {
+            panic!("{}", message);
+        }
+
+        let register = Register::new(name, self.clbits, size);
+        self.clbits += size;
+        self.cregs.push(register.clone());
+
+        register
+    }
+
+    /// Quantum registers of the circuit in order of declaration.
+    #[inline]
+    pub fn qregs(&self) -> &[Register] {
+        &self.qregs
+    }
+
+    /// Classical registers of the circuit in order of declaration.
+    #[inline]
+    pub fn cregs(&self) -> &[Register] {
+        &self.cregs
+    }
+
+    /// Quantum register with the name, if it is declared.
+    pub fn qreg(&self, name: &str) -> Option<&Register> {
+        self.qregs.iter().find(|register| register.name() == name)
+    }
+
+    /// Classical register with the name, if it is declared.
+    pub fn creg(&self, name: &str) -> Option<&Register> {
+        self.cregs.iter().find(|register| register.name() == name)
+    }
+
+    /// Splits classical bits of the circuit, like the output of
+    /// [`StabilizerBackend`](crate::stabilizer_backend::StabilizerBackend), into
+    /// values of classical registers by their names.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width of `clbits` doesn't match the circuit.
+    pub fn creg_values(&self, clbits: &ClassicalRegister) -> BTreeMap<String, ClassicalRegister> {
+        assert_eq!(
+            clbits.width(),
+            self.clbits,
+            "Register doesn't match the amount of classical bits in the circuit"
+        );
+
+        self.cregs
+            .iter()
+            .map(|register| {
+                let bits = clbits.bits()[register.indices()].to_vec();
+                (register.name().to_owned(), ClassicalRegister::new(bits))
+            })
+            .collect()
+    }
+
+    /// Formats classical bits as a bitstring, where the last classical bit is the
+    /// first one. Bits of different classical registers are separated by spaces.
+    pub(crate) fn format_clbits(&self, clbits: &[u8]) -> String {
+        let creg = |clbit: usize| {
+            self.cregs
+                .iter()
+                .position(|register| register.position(clbit).is_some())
+        };
+
+        let mut bitstring = String::with_capacity(2 * clbits.len());
+        for clbit in (0..clbits.len()).rev() {
+            if clbit + 1 < clbits.len() && creg(clbit) != creg(clbit + 1) {
+                bitstring.push(' ');
+            }
+
+            bitstring.push(if clbits[clbit] == 0 { '0' } else { '1' });
+        }
+
+        bitstring
+    }
+
+    /// Checks that the name can be used for a new register.
+    fn check_register_name(&self, name: &str) -> Result<(), String> {
+        check_register_name(name, self.qregs.iter().chain(&self.cregs))
+    }
+
+    /// Replaces registers of the circuit, checking that they have unique valid names
+    /// and consist of distinct existing qubits and classical bits.
+    pub(crate) fn set_registers(
+        &mut self,
+        qregs: Vec<Register>,
+        cregs: Vec<Register>,
+    ) -> Result<(), String> {
+        let registers = qregs.iter().chain(&cregs);
+        for (i, register) in registers.clone().enumerate() {
+            check_register_name(register.name(), registers.clone().take(i))?;
+        }
+
+        for (registers, bits) in [(&qregs, self.qubits), (&cregs, self.clbits)] {
+            let mut ranges = Vec::with_capacity(registers.len());
+
+            for register in registers {
+                match register.offset().checked_add(register.size()) {
+                    Some(end) if end <= bits => ranges.push((register.offset(), end)),
+                    _ => {
+                        return Err(format!(
+                            "Register {:?} is out of the circuit",
+                            register.name()
+                        ))
+                    }
+                }
+            }
+
+            ranges.sort_unstable();
+            if ranges.windows(2).any(|pair| pair[0].1 > pair[1].0) {
+                return Err("Registers of the circuit overlap".to_owned());
+            }
+        }
+
+        self.qregs = qregs;
+        self.cregs = cregs;
+
+        Ok(())
+    }
+
+    /// Amount of layers in the circuit, where every layer consists of instructions
+    /// acting on disjoint qubits and classical bits. Barriers are not counted.
+    ///
+    /// Custom gates are counted as a single layer. Use [`QuantumCircuit::flatten`]
+    /// to take their inner circuits into account.
+    ///
+    /// ```
+    /// use quantum_crab::quantum_circuit::{QuantumCircuit, Instruction};
+    ///
+    /// let mut circuit = QuantumCircuit::new(3);
+    /// circuit.add(Instruction::Hadamard(0));
+    /// circuit.add(Instruction::Hadamard(2));
+    /// circuit.add(Instruction::ControlledNot { control: 0, target: 1 });
+    /// assert_eq!(circuit.depth(), 2);
+    /// ```
+    pub fn depth(&self) -> usize {
+        self.critical_path().len()
+    }
+
+    /// Indices of instructions forming the longest chain of dependent instructions
+    /// (see [`QuantumCircuit::depth`]). Among equally long chains the one ending
+    /// first is returned.
+    ///
+    /// ```
+    /// use quantum_crab::quantum_circuit::{QuantumCircuit, Instruction};
+    ///
+    /// let mut circuit = QuantumCircuit::new(3);
+    /// circuit.add(Instruction::Hadamard(0));
+    /// circuit.add(Instruction::Hadamard(2));
+    /// circuit.add(Instruction::ControlledNot { control: 2, target: 1 });
+    /// circuit.add(Instruction::T(1));
+    /// assert_eq!(circuit.critical_path(), vec![1, 2, 3]);
+    /// ```
+    pub fn critical_path(&self) -> Vec<usize> {
+        // Last instruction on every qubit and classical bit.
+        let mut last_instructions: Vec<Option<usize>> = vec![None; self.width()];
+        let mut lengths = Vec::with_capacity(self.instructions.len());
+        let mut previous_instructions = Vec::with_capacity(self.instructions.len());
+
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            if let Instruction::Barrier(..) = instruction {
+                lengths.push(0);
+                previous_instructions.push(None);
+                continue;
+            }
+
+            let wires: Vec<usize> = instruction
+                .qubits()
+                .into_iter()
+                .chain(
+                    instruction
+                        .clbits()
+                        .into_iter()
+                        .map(|clbit| self.qubits + clbit),
+                )
+                .collect();
+
+            let previous = wires
+                .iter()
+                .filter_map(|wire| last_instructions[*wire])
+                .max_by_key(|previous| (lengths[*previous], std::cmp::Reverse(*previous)));
+
+            lengths.push(previous.map_or(1, |previous| lengths[previous] + 1));
+            previous_instructions.push(previous);
+
+            for wire in wires {
+                last_instructions[wire] = Some(index);
+            }
+        }
+
+        let mut path = Vec::new();
+        let mut current = (0..self.instructions.len())
+            .filter(|index| lengths[*index] > 0)
+            .max_by_key(|index| (lengths[*index], std::cmp::Reverse(*index)));
+
+        while let Some(index) = current {
+            path.push(index);
+            current = previous_instructions[index];
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// Amount of instructions of every kind (see [`Instruction::name`]).
+    ///
+    /// Custom gates are counted by their names as `Custom(name)`, so that they are
+    /// not mixed with built-in instructions. Use [`QuantumCircuit::flatten`] to count
+    /// instructions of their inner circuits instead.
+    ///
+    /// ```
+    /// use quantum_crab::quantum_circuit::{QuantumCircuit, Instruction};
+    ///
+    /// let mut circuit = QuantumCircuit::new(2);
+    /// circuit.add(Instruction::Hadamard(0));
+    /// circuit.add(Instruction::Hadamard(1));
+    /// circuit.add(Instruction::ControlledNot { control: 0, target: 1 });
+    ///
+    /// let counts = circuit.count_ops();
+    /// assert_eq!(counts["Hadamard"], 2);
+    /// assert_eq!(counts["ControlledNot"], 1);
+    /// ```
+    pub fn count_ops(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+
+        for instruction in &self.instructions {
+            let name = match instruction {
+                Instruction::Custom { name, .. } => format!("Custom({})", name),
+                _ => instruction.name().to_owned(),
+            };
+            *counts.entry(name).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    /// Amount of primitive two-qubit gates: [`Instruction::ControlledNot`],
+    /// [`Instruction::ControlledU`] and [`Instruction::Swap`], including conditioned
+    /// ones. Custom gates and state preparation are not counted, even if they act on
+    /// two qubits, use [`QuantumCircuit::flatten`] to count gates inside of them.
+    pub fn two_qubit_gate_count(&self) -> usize {
+        self.instructions
+            .iter()
+            .filter(|instruction| instruction.is_two_qubit_gate())
+            .count()
+    }
+
+    /// Amount of [`Instruction::T`] and [`Instruction::TDagger`] gates, including
+    /// ones inside custom gates, conditioned and controlled instructions.
+    pub fn t_count(&self) -> usize {
+        self.instructions.iter().map(Instruction::t_count).sum()
+    }
+
+    /// Layout chosen for the circuit: logical qubit `i` of the circuit is to be
+    /// placed on physical qubit `layout.physical(i)` of the device.
+    ///
+    /// See [`crate::layout`] for layout selection passes.
+    #[inline]
+    pub const fn layout(&self) -> Option<&Layout> {
+        self.layout.as_ref()
+    }
+
+    /// Records the layout chosen for the circuit (see [`QuantumCircuit::layout`]).
+    pub fn set_layout(&mut self, layout: Layout) {
+        assert_eq!(
+            layout.logical_qubits(),
+            self.qubits,
+            "Layout doesn't match the amount of qubits in the circuit"
+        );
+
+        self.layout = Some(layout);
+    }
+
+    /// Returns the circuit with all custom gates replaced by instructions of their
+    /// inner circuits (recursively). Qubit `i` of the inner circuit is mapped to the
+    /// `i`-th input qubit of the custom gate. Conditioned custom gates are replaced by
+    /// their conditioned instructions.
+    ///
+    /// ```
+    /// use quantum_crab::quantum_circuit::{QuantumCircuit, Instruction};
+    ///
+    /// let mut bell = QuantumCircuit::new(2);
+    /// bell.add(Instruction::Hadamard(0));
+    /// bell.add(Instruction::ControlledNot { control: 0, target: 1 });
+    ///
+    /// let mut circuit = QuantumCircuit::new(3);
+    /// circuit.add(Instruction::Custom {
+    ///     name: "bell".to_owned(),
+    ///     circuit: bell,
+    ///     input_qubits: vec![2, 1],
+    /// });
+    ///
+    /// assert_eq!(
+    ///     circuit.flatten().instructions(),
+    ///     &vec![
+    ///         Instruction::Hadamard(2),
+    ///         Instruction::ControlledNot { control: 2, target: 1 },
+    ///     ]
+    /// );
+    /// ```
+    pub fn flatten(&self) -> QuantumCircuit {
+        let mut circuit = self.empty_copy();
+
+        for instruction in &self.instructions {
+            match instruction {
+                Instruction::Custom {
+                    circuit: inner,
+                    input_qubits,
+                    ..
+                } => {
+                    for inner_instruction in inner.flatten().instructions() {
+                        circuit.add(inner_instruction.map_qubits(|qubit| input_qubits[qubit]));
+                    }
+                }
+                Instruction::Initialize { qubits, amplitudes } => {
+                    for inner_instruction in state_preparation(amplitudes).instructions() {
+                        circuit.add(inner_instruction.map_qubits(|qubit| qubits[qubit]));
+                    }
+                }
+                Instruction::If {
+                    register,
+                    value,
+                    instruction: inner,
+                } => {
+                    let mut conditioned = QuantumCircuit::with_clbits(self.qubits, self.clbits);
+                    conditioned.instructions.push((**inner).clone());
+
+                    for inner_instruction in conditioned.flatten().instructions {
+                        circuit.add(Instruction::If {
+                            register: register.clone(),
+                            value: *value,
+                            instruction: Box::new(inner_instruction),
+                        });
+                    }
+                }
+                _ => circuit.add(instruction.clone()),
+            }
+        }
+
+        circuit
+    }
+}
+
+/// Checks that the name is valid and is not used by any of the registers.
+fn check_register_name<'a>(
+    name: &str,
+    mut registers: impl Iterator<Item = &'a Register>,
+) -> Result<(), String> {
+    if !register::is_valid_name(name) {
+        return Err(format!("Invalid register name {:?}", name));
+    }
+
+    if registers.any(|register| register.name() == name) {
+        return Err(format!("Register {:?} is already declared", name));
+    }
+
+    Ok(())
+}
+
+/// Serialized form of the circuit.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SerializedCircuit {
+    version: u32,
+    qubits: usize,
+    clbits: usize,
+    instructions: Vec<Instruction>,
+    layout: Option<Layout>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    qregs: Vec<Register>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cregs: Vec<Register>,
+}
+
+#[cfg(feature = "serde")]
+impl From<QuantumCircuit> for SerializedCircuit {
+    fn from(circuit: QuantumCircuit) -> SerializedCircuit {
+        SerializedCircuit {
+            version: QuantumCircuit::SCHEMA_VERSION,
+            qubits: circuit.qubits,
+            clbits: circuit.clbits,
+            instructions: circuit.instructions,
+            layout: circuit.layout,
+            qregs: circuit.qregs,
+            cregs: circuit.cregs,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SerializedCircuit> for QuantumCircuit {
+    type Error = String;
+
+    fn try_from(serialized: SerializedCircuit) -> Result<QuantumCircuit, String> {
+        if serialized.version != QuantumCircuit::SCHEMA_VERSION {
+            return Err(format!(
+                "Unsupported circuit schema version {} (expected {})",
+                serialized.version,
+                QuantumCircuit::SCHEMA_VERSION
+            ));
+        }
+
+        let mut circuit = QuantumCircuit::with_clbits(serialized.qubits, serialized.clbits);
+        for instruction in serialized.instructions {
+            circuit.check_instruction(&instruction, None)?;
+            circuit.instructions.push(instruction);
+        }
+
+        if let Some(layout) = serialized.layout {
+            if layout.logical_qubits() != circuit.qubits {
+                return Err("Layout doesn't match the amount of qubits in the circuit".to_owned());
+            }
+
+            circuit.layout = Some(layout);
+        }
+
+        circuit.set_registers(serialized.qregs, serialized.cregs)?;
+        Ok(circuit)
+    }
 }
 
 /// The trait used to visualize quantum circuits in different formats.
@@ -95,6 +740,7 @@ pub trait CircuitVisualizer {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Instruction {
     /// The Identity gate.
     ///
@@ -107,7 +753,6 @@ pub enum Instruction {
     /// # Example
     /// ```
     /// use quantum_crab::{
-    ///   matrix_real,
     ///   backend::Backend,
     ///   statevector_backend::StateVectorBackend,
     ///   quantum_circuit::{QuantumCircuit, Instruction}
@@ -116,7 +761,7 @@ pub enum Instruction {
     /// let mut circuit = QuantumCircuit::new(1);
     /// circuit.add(Instruction::Identity(0));
     /// let state_vector = StateVectorBackend::execute(circuit);
-    /// assert_eq!(state_vector, matrix_real![[1], [0]]);
+    /// assert_eq!(state_vector.to_string(), "1.000|0>");
     /// ```
     Identity(usize),
 
@@ -140,13 +785,12 @@ pub enum Instruction {
     ///   backend::Backend,
     ///   statevector_backend::StateVectorBackend,
     ///   quantum_circuit::{QuantumCircuit, Instruction},
-    ///   matrix_real
     /// };
     ///
     /// let mut circuit = QuantumCircuit::new(1);
     /// circuit.add(Instruction::PauliX(0));
     /// let state_vector = StateVectorBackend::execute(circuit);
-    /// assert_eq!(state_vector, matrix_real![[0], [1]]);
+    /// assert_eq!(state_vector.to_string(), "1.000|1>");
     /// ```
     ///
     /// See [Wikipedia](https://en.wikipedia.org/wiki/Quantum_gate#Pauli-X_gate)
@@ -467,6 +1111,143 @@ pub enum Instruction {
         phase: f64,
     },
 
+    /// The measurement in the computational basis.
+    ///
+    /// The instruction collapses the qubit state into `|0>` or `|1>` and writes
+    /// the outcome into the classical bit:
+    ///
+    /// ```txt
+    /// a|0> + b|1> --> M --> |0> (c = 0) with probability |a|^2
+    ///                   --> |1> (c = 1) with probability |b|^2
+    /// ```
+    Measure {
+        /// The measured qubit.
+        ///
+        /// See [`Instruction::Measure`] for more information.
+        qubit: usize,
+
+        /// The classical bit, that the outcome is written into.
+        ///
+        /// See [`Instruction::Measure`] for more information.
+        clbit: usize,
+    },
+
+    /// The reset.
+    ///
+    /// The instruction returns the qubit into `|0>` state, no matter what state it is
+    /// in. It is equivalent to measuring the qubit and flipping it if the outcome is
+    /// `|1>`, so that the qubit can be reused:
+    ///
+    /// ```txt
+    /// a|0> + b|1> --> R --> |0>
+    /// ```
+    ///
+    /// # Example
+    /// ```
+    /// use quantum_crab::{
+    ///   backend::Backend,
+    ///   stabilizer_backend::StabilizerBackend,
+    ///   quantum_circuit::{QuantumCircuit, Instruction},
+    /// };
+    ///
+    /// let mut circuit = QuantumCircuit::with_clbits(1, 1);
+    /// circuit.add(Instruction::Hadamard(0));
+    /// circuit.add(Instruction::Reset(0));
+    /// circuit.add(Instruction::Measure { qubit: 0, clbit: 0 });
+    /// assert_eq!(StabilizerBackend::execute(circuit).bits(), &[0]);
+    /// ```
+    Reset(usize),
+
+    /// The classically conditioned instruction.
+    ///
+    /// The inner instruction is executed only if classical bits of the register hold
+    /// the value, where `register[i]` is bit `i` of the value (see
+    /// [`ClassicalRegister::value`]). Conditions are evaluated in every shot
+    /// separately, using outcomes of measurements executed so far:
+    ///
+    /// ```txt
+    /// c == value --> U
+    /// ```
+    ///
+    /// # Example
+    /// ```
+    /// use quantum_crab::{
+    ///   backend::Backend,
+    ///   stabilizer_backend::StabilizerBackend,
+    ///   quantum_circuit::{QuantumCircuit, Instruction},
+    /// };
+    ///
+    /// let mut circuit = QuantumCircuit::with_clbits(2, 2);
+    /// circuit.add(Instruction::PauliX(0));
+    /// circuit.add(Instruction::Measure { qubit: 0, clbit: 0 });
+    /// circuit.add(Instruction::If {
+    ///     register: vec![0],
+    ///     value: 1,
+    ///     instruction: Box::new(Instruction::PauliX(1)),
+    /// });
+    /// circuit.add(Instruction::Measure { qubit: 1, clbit: 1 });
+    /// assert_eq!(StabilizerBackend::execute(circuit).bits(), &[1, 1]);
+    /// ```
+    If {
+        /// Classical bits forming the register, least significant bit first.
+        ///
+        /// See [`Instruction::If`] for more information.
+        register: Vec<usize>,
+
+        /// The value the register is compared with.
+        ///
+        /// See [`Instruction::If`] for more information.
+        value: u128,
+
+        /// The conditioned instruction.
+        ///
+        /// See [`Instruction::If`] for more information.
+        instruction: Box<Instruction>,
+    },
+
+    /// The barrier.
+    ///
+    /// The instruction doesn't change the state of qubits, but it separates parts of
+    /// the circuit: optimization passes don't move or merge instructions across it.
+    Barrier(Vec<usize>),
+
+    /// The state preparation.
+    ///
+    /// The instruction turns the qubits from `|0...0>` into the state with the
+    /// normalized amplitudes, indexed by basis states where `qubits[0]` is the least
+    /// significant bit. It is executed as the circuit synthesized by
+    /// [`state_preparation`](crate::state_preparation::state_preparation), which
+    /// [`QuantumCircuit::flatten`] expands it into.
+    ///
+    /// ```
+    /// use quantum_crab::{
+    ///     backend::Backend,
+    ///     complex::Complex,
+    ///     quantum_circuit::{Instruction, QuantumCircuit},
+    ///     statevector_backend::StateVectorBackend,
+    /// };
+    ///
+    /// let mut circuit = QuantumCircuit::new(2);
+    /// circuit.add(Instruction::Initialize {
+    ///     qubits: vec![1],
+    ///     amplitudes: vec![Complex::from(0.6), Complex::from(-0.8)],
+    /// });
+    ///
+    /// let state_vector = StateVectorBackend::execute(circuit);
+    /// assert_eq!(state_vector.to_string(), "0.600|00> - 0.800|10>");
+    /// ```
+    Initialize {
+        /// The prepared qubits.
+        ///
+        /// See [`Instruction::Initialize`] for more information.
+        qubits: Vec<usize>,
+
+        /// Amplitudes of the prepared state.
+        ///
+        /// See [`Instruction::Initialize`] for more information.
+        amplitudes: Vec<Complex>,
+    },
+
     /// Represents custom gate.
     Custom {
         /// Name of the custom gate.
@@ -479,3 +1260,815 @@ pub enum Instruction {
         input_qubits: Vec<usize>,
     },
 }
+
+impl Instruction {
+    /// Name of the instruction kind: name of the enum variant, or name of the gate for
+    /// custom gates.
+    pub fn name(&self) -> &str {
+        match self {
+            Instruction::Identity(..) => "Identity",
+            Instruction::PauliX(..) => "PauliX",
+            Instruction::PauliY(..) => "PauliY",
+            Instruction::PauliZ(..) => "PauliZ",
+            Instruction::Hadamard(..) => "Hadamard",
+            Instruction::Phase { .. } => "Phase",
+            Instruction::PhaseDagger { .. } => "PhaseDagger",
+            Instruction::T(..) => "T",
+            Instruction::TDagger(..) => "TDagger",
+            Instruction::S(..) => "S",
+            Instruction::SDagger(..) => "SDagger",
+            Instruction::ControlledNot { .. } => "ControlledNot",
+            Instruction::ControlledU { .. } => "ControlledU",
+            Instruction::Toffoli { .. } => "Toffoli",
+            Instruction::Swap(..) => "Swap",
+            Instruction::RotationX { .. } => "RotationX",
+            Instruction::RotationY { .. } => "RotationY",
+            Instruction::RotationZ { .. } => "RotationZ",
+            Instruction::Measure { .. } => "Measure",
+            Instruction::Reset(..) => "Reset",
+            Instruction::If { .. } => "If",
+            Instruction::Barrier(..) => "Barrier",
+            Instruction::Initialize { .. } => "Initialize",
+            Instruction::Custom { name, .. } => name,
+        }
+    }
+
+    /// Amount of [`Instruction::T`] and [`Instruction::TDagger`] gates in the
+    /// instruction (see [`QuantumCircuit::t_count`]).
+    fn t_count(&self) -> usize {
+        match self {
+            Instruction::T(..) | Instruction::TDagger(..) => 1,
+            Instruction::ControlledU { gate, .. } => gate.t_count(),
+            Instruction::If { instruction, .. } => instruction.t_count(),
+            Instruction::Custom { circuit, .. } => circuit.t_count(),
+            _ => 0,
+        }
+    }
+
+    /// Checks if the instruction is a primitive two-qubit gate (see
+    /// [`QuantumCircuit::two_qubit_gate_count`]).
+    fn is_two_qubit_gate(&self) -> bool {
+        match self {
+            Instruction::ControlledNot { .. }
+            | Instruction::ControlledU { .. }
+            | Instruction::Swap(..) => true,
+            Instruction::If { instruction, .. } => instruction.is_two_qubit_gate(),
+            _ => false,
+        }
+    }
+
+    /// Qubits the instruction acts on.
+    ///
+    /// For controlled gates control qubits come first and the target qubit
+    /// comes last.
+    ///
+    /// ```
+    /// use quantum_crab::quantum_circuit::Instruction;
+    ///
+    /// let instruction = Instruction::Toffoli { control1: 2, control2: 0, target: 1 };
+    /// assert_eq!(instruction.qubits(), vec![2, 0, 1]);
+    /// ```
+    pub fn qubits(&self) -> Vec<usize> {
+        match self {
+            Instruction::Identity(qubit)
+            | Instruction::PauliX(qubit)
+            | Instruction::PauliY(qubit)
+            | Instruction::PauliZ(qubit)
+            | Instruction::Hadamard(qubit)
+            | Instruction::Phase { qubit, .. }
+            | Instruction::PhaseDagger { qubit, .. }
+            | Instruction::T(qubit)
+            | Instruction::TDagger(qubit)
+            | Instruction::S(qubit)
+            | Instruction::SDagger(qubit)
+            | Instruction::RotationX { qubit, .. }
+            | Instruction::RotationY { qubit, .. }
+            | Instruction::RotationZ { qubit, .. }
+            | Instruction::Measure { qubit, .. }
+            | Instruction::Reset(qubit) => vec![*qubit],
+            Instruction::ControlledNot { control, target }
+            | Instruction::ControlledU {
+                control, target, ..
+            } => vec![*control, *target],
+            Instruction::Toffoli {
+                control1,
+                control2,
+                target,
+            } => vec![*control1, *control2, *target],
+            Instruction::Swap(qubit1, qubit2) => vec![*qubit1, *qubit2],
+            Instruction::Barrier(qubits) | Instruction::Initialize { qubits, .. } => qubits.clone(),
+            Instruction::If { instruction, .. } => instruction.qubits(),
+            Instruction::Custom { input_qubits, .. } => input_qubits.clone(),
+        }
+    }
+
+    /// Returns the same instruction, with every qubit `q` it acts on replaced by
+    /// `mapping(q)`. Inner circuits of custom gates are left untouched, as they have
+    /// their own qubit indices.
+    pub fn map_qubits<F>(&self, mapping: F) -> Instruction
+    where
+        F: Fn(usize) -> usize,
+    {
+        self.map_qubits_with(&mapping)
+    }
+
+    /// Non-generic implementation of [`Instruction::map_qubits`], so that it can
+    /// recurse into controlled gates.
+    fn map_qubits_with(&self, mapping: &dyn Fn(usize) -> usize) -> Instruction {
+        match self {
+            Instruction::Identity(qubit) => Instruction::Identity(mapping(*qubit)),
+            Instruction::PauliX(qubit) => Instruction::PauliX(mapping(*qubit)),
+            Instruction::PauliY(qubit) => Instruction::PauliY(mapping(*qubit)),
+            Instruction::PauliZ(qubit) => Instruction::PauliZ(mapping(*qubit)),
+            Instruction::Hadamard(qubit) => Instruction::Hadamard(mapping(*qubit)),
+            Instruction::Phase { qubit, phase } => Instruction::Phase {
+                qubit: mapping(*qubit),
+                phase: *phase,
+            },
+            Instruction::PhaseDagger { qubit, phase } => Instruction::PhaseDagger {
+                qubit: mapping(*qubit),
+                phase: *phase,
+            },
+            Instruction::T(qubit) => Instruction::T(mapping(*qubit)),
+            Instruction::TDagger(qubit) => Instruction::TDagger(mapping(*qubit)),
+            Instruction::S(qubit) => Instruction::S(mapping(*qubit)),
+            Instruction::SDagger(qubit) => Instruction::SDagger(mapping(*qubit)),
+            Instruction::ControlledNot { control, target } => Instruction::ControlledNot {
+                control: mapping(*control),
+                target: mapping(*target),
+            },
+            Instruction::ControlledU {
+                gate,
+                control,
+                target,
+            } => Instruction::ControlledU {
+                gate: Box::new(gate.map_qubits_with(mapping)),
+                control: mapping(*control),
+                target: mapping(*target),
+            },
+            Instruction::Toffoli {
+                control1,
+                control2,
+                target,
+            } => Instruction::Toffoli {
+                control1: mapping(*control1),
+                control2: mapping(*control2),
+                target: mapping(*target),
+            },
+            Instruction::Swap(qubit1, qubit2) => {
+                Instruction::Swap(mapping(*qubit1), mapping(*qubit2))
+            }
+            Instruction::RotationX { qubit, phase } => Instruction::RotationX {
+                qubit: mapping(*qubit),
+                phase: *phase,
+            },
+            Instruction::RotationY { qubit, phase } => Instruction::RotationY {
+                qubit: mapping(*qubit),
+                phase: *phase,
+            },
+            Instruction::RotationZ { qubit, phase } => Instruction::RotationZ {
+                qubit: mapping(*qubit),
+                phase: *phase,
+            },
+            Instruction::Measure { qubit, clbit } => Instruction::Measure {
+                qubit: mapping(*qubit),
+                clbit: *clbit,
+            },
+            Instruction::Reset(qubit) => Instruction::Reset(mapping(*qubit)),
+            Instruction::If {
+                register,
+                value,
+                instruction,
+            } => Instruction::If {
+                register: register.clone(),
+                value: *value,
+                instruction: Box::new(instruction.map_qubits_with(mapping)),
+            },
+            Instruction::Barrier(qubits) => {
+                Instruction::Barrier(qubits.iter().map(|qubit| mapping(*qubit)).collect())
+            }
+            Instruction::Initialize { qubits, amplitudes } => Instruction::Initialize {
+                qubits: qubits.iter().map(|qubit| mapping(*qubit)).collect(),
+                amplitudes: amplitudes.clone(),
+            },
+            Instruction::Custom {
+                name,
+                circuit,
+                input_qubits,
+            } => Instruction::Custom {
+                name: name.clone(),
+                circuit: circuit.clone(),
+                input_qubits: input_qubits.iter().map(|qubit| mapping(*qubit)).collect(),
+            },
+        }
+    }
+
+    /// Checks if the instruction is a unitary gate acting on a single qubit, that can
+    /// be controlled by [`Instruction::ControlledU`]. Custom gates qualify, if their
+    /// inner circuit consists of such gates on a single qubit.
+    pub(crate) fn is_single_qubit_gate(&self) -> bool {
+        if let Instruction::Custom { circuit, .. } = self {
+            return circuit.qubits() == 1
+                && circuit
+                    .instructions()
+                    .iter()
+                    .all(Instruction::is_single_qubit_gate);
+        }
+
+        matches!(
+            self,
+            Instruction::Identity(..)
+                | Instruction::PauliX(..)
+                | Instruction::PauliY(..)
+                | Instruction::PauliZ(..)
+                | Instruction::Hadamard(..)
+                | Instruction::Phase { .. }
+                | Instruction::PhaseDagger { .. }
+                | Instruction::T(..)
+                | Instruction::TDagger(..)
+                | Instruction::S(..)
+                | Instruction::SDagger(..)
+                | Instruction::RotationX { .. }
+                | Instruction::RotationY { .. }
+                | Instruction::RotationZ { .. }
+        )
+    }
+
+    /// Checks if the instruction is a measurement, reset or conditioned instruction,
+    /// that can only be executed shot by shot rather than applied as a gate.
+    pub(crate) fn is_dynamic(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Measure { .. } | Instruction::Reset(..) | Instruction::If { .. }
+        )
+    }
+
+    /// Decomposes Toffoli gate, conditioned or not, into an equivalent sequence of
+    /// one- and two-qubit gates (conditioned in the same way). Returns `None` for other
+    /// instructions.
+    pub(crate) fn decompose_toffoli(&self) -> Option<Vec<Instruction>> {
+        match self {
+            &Instruction::Toffoli {
+                control1,
+                control2,
+                target,
+            } => {
+                let cnot = |control, target| Instruction::ControlledNot { control, target };
+
+                Some(vec![
+                    Instruction::Hadamard(target),
+                    cnot(control2, target),
+                    Instruction::TDagger(target),
+                    cnot(control1, target),
+                    Instruction::T(target),
+                    cnot(control2, target),
+                    Instruction::TDagger(target),
+                    cnot(control1, target),
+                    Instruction::T(control2),
+                    Instruction::T(target),
+                    Instruction::Hadamard(target),
+                    cnot(control1, control2),
+                    Instruction::T(control1),
+                    Instruction::TDagger(control2),
+                    cnot(control1, control2),
+                ])
+            }
+            Instruction::If {
+                register,
+                value,
+                instruction,
+            } => Some(
+                instruction
+                    .decompose_toffoli()?
+                    .into_iter()
+                    .map(|gate| Instruction::If {
+                        register: register.clone(),
+                        value: *value,
+                        instruction: Box::new(gate),
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Classical bits the instruction reads or writes, including the ones used by
+    /// instructions of custom gates.
+    pub fn clbits(&self) -> Vec<usize> {
+        match self {
+            Instruction::Measure { clbit, .. } => vec![*clbit],
+            Instruction::Custom { circuit, .. } => {
+                // Classical bits of the inner circuit are shared with the circuit.
+                let mut clbits = Vec::new();
+                for clbit in circuit.instructions().iter().flat_map(Instruction::clbits) {
+                    if !clbits.contains(&clbit) {
+                        clbits.push(clbit);
+                    }
+                }
+                clbits
+            }
+            Instruction::If {
+                register,
+                instruction,
+                ..
+            } => {
+                let mut clbits = register.clone();
+                clbits.extend(
+                    instruction
+                        .clbits()
+                        .into_iter()
+                        .filter(|clbit| !register.contains(clbit)),
+                );
+                clbits
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Checks if classical bits of the `register` hold the `value` (see
+/// [`Instruction::If`]).
+pub(crate) fn condition_holds(register: &[usize], value: u128, clbits: &[u8]) -> bool {
+    let register = ClassicalRegister::new(register.iter().map(|clbit| clbits[*clbit]).collect());
+    register.checked_value() == Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        classical_register::ClassicalRegister,
+        complex::Complex,
+        quantum_circuit::{condition_holds, Instruction, QuantumCircuit},
+    };
+
+    fn circuit_with_custom_gate() -> QuantumCircuit {
+        let mut inner = QuantumCircuit::new(2);
+        inner.add(Instruction::T(0));
+        inner.add(Instruction::ControlledNot {
+            control: 0,
+            target: 1,
+        });
+        inner.add(Instruction::TDagger(1));
+
+        let mut circuit = QuantumCircuit::with_clbits(3, 1);
+        circuit.add(Instruction::Hadamard(0));
+        circuit.add(Instruction::Custom {
+            name: "tct".to_owned(),
+            circuit: inner,
+            input_qubits: vec![0, 2],
+        });
+        circuit.add(Instruction::T(1));
+        circuit.add(Instruction::Measure { qubit: 1, clbit: 0 });
+        circuit.add(Instruction::Measure { qubit: 2, clbit: 0 });
+        circuit
+    }
+
+    #[test]
+    fn metrics_treat_custom_gates_as_opaque() {
+        let circuit = circuit_with_custom_gate();
+
+        assert_eq!(circuit.width(), 4);
+        assert_eq!(circuit.depth(), 3);
+        assert_eq!(circuit.critical_path(), vec![0, 1, 4]);
+        assert_eq!(circuit.two_qubit_gate_count(), 0);
+        assert_eq!(circuit.t_count(), 3);
+        assert_eq!(
+            circuit.count_ops().into_iter().collect::<Vec<_>>(),
+            vec![
+                ("Custom(tct)".to_owned(), 1),
+                ("Hadamard".to_owned(), 1),
+                ("Measure".to_owned(), 2),
+                ("T".to_owned(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn metrics_look_into_nested_instructions() {
+        let mut t = QuantumCircuit::new(1);
+        t.h(0);
+
+        let mut circuit = QuantumCircuit::with_clbits(2, 1);
+        circuit
+            .t(0)
+            .custom("T", t, [1])
+            .cu(Instruction::TDagger(0), 0, 1)
+            .c_if(0, 1, Instruction::T(1));
+
+        assert_eq!(circuit.t_count(), 3);
+        assert_eq!(
+            circuit.count_ops().into_iter().collect::<Vec<_>>(),
+            vec![
+                ("ControlledU".to_owned(), 1),
+                ("Custom(T)".to_owned(), 1),
+                ("If".to_owned(), 1),
+                ("T".to_owned(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn metrics_of_flattened_circuit() {
+        let circuit = circuit_with_custom_gate().flatten();
+
+        assert_eq!(circuit.depth(), 5);
+        assert_eq!(circuit.critical_path(), vec![0, 1, 2, 3, 6]);
+        assert_eq!(circuit.two_qubit_gate_count(), 1);
+        assert_eq!(circuit.t_count(), 3);
+        assert_eq!(circuit.count_ops()["ControlledNot"], 1);
+        assert_eq!(circuit.count_ops()["TDagger"], 1);
+    }
+
+    #[test]
+    fn metrics_of_custom_gates_with_classical_bits() {
+        let mut inner = QuantumCircuit::with_clbits(1, 1);
+        inner.measure(0, 0);
+
+        let mut circuit = QuantumCircuit::with_clbits(2, 1);
+        circuit.custom("measure", inner.clone(), 0).measure(1, 0);
+        assert_eq!(circuit.instructions()[0].clbits(), vec![0]);
+        assert_eq!(circuit.depth(), 2);
+        assert_eq!(circuit.critical_path(), vec![0, 1]);
+
+        let mut circuit = QuantumCircuit::with_clbits(2, 2);
+        circuit
+            .c_if(
+                1,
+                1,
+                Instruction::Custom {
+                    name: "measure".to_owned(),
+                    circuit: inner,
+                    input_qubits: vec![0],
+                },
+            )
+            .measure(1, 0);
+        assert_eq!(circuit.instructions()[0].clbits(), vec![1, 0]);
+        assert_eq!(circuit.depth(), 2);
+    }
+
+    #[test]
+    fn counts_primitive_two_qubit_gates() {
+        let amplitudes = vec![Complex::from(0.5); 4];
+        let mut circuit = QuantumCircuit::with_clbits(3, 1);
+        circuit
+            .initialize([0, 1], amplitudes)
+            .cx(0, 1)
+            .cu(
+                Instruction::RotationY {
+                    qubit: 2,
+                    phase: 0.5,
+                },
+                1,
+                2,
+            )
+            .swap(0, 2)
+            .ccx(0, 1, 2)
+            .measure(0, 0)
+            .c_if(
+                0,
+                1,
+                Instruction::ControlledNot {
+                    control: 1,
+                    target: 2,
+                },
+            )
+            .c_if(0, 1, Instruction::Reset(1));
+
+        assert_eq!(circuit.two_qubit_gate_count(), 4);
+    }
+
+    #[test]
+    fn empty_circuit_metrics() {
+        let circuit = QuantumCircuit::new(2);
+
+        assert_eq!(circuit.depth(), 0);
+        assert!(circuit.critical_path().is_empty());
+        assert!(circuit.count_ops().is_empty());
+    }
+
+    #[test]
+    fn validates_conditioned_instructions() {
+        let circuit = circuit_with_custom_gate();
+        let condition = |register: Vec<usize>, value, instruction| Instruction::If {
+            register,
+            value,
+            instruction: Box::new(instruction),
+        };
+
+        assert!(circuit
+            .check_instruction(&condition(vec![0], 1, Instruction::Reset(2)), None)
+            .is_ok());
+        for (instruction, message) in [
+            (
+                condition(vec![1], 0, Instruction::PauliX(0)),
+                "Invalid classical bit",
+            ),
+            (
+                condition(vec![0, 0], 0, Instruction::PauliX(0)),
+                "Invalid classical bit",
+            ),
+            (condition(vec![0], 2, Instruction::PauliX(0)), "doesn't fit"),
+            (
+                condition(vec![0], 0, Instruction::PauliX(3)),
+                "Invalid input qubit",
+            ),
+            (
+                condition(vec![0], 0, Instruction::Barrier(vec![0])),
+                "can't be conditioned",
+            ),
+        ] {
+            let error = circuit.check_instruction(&instruction, None).unwrap_err();
+            assert!(error.contains(message), "{}", error);
+        }
+
+        // Registers can be wider than the value.
+        let wide = QuantumCircuit::with_clbits(1, 130);
+        let register: Vec<usize> = (0..130).collect();
+        assert!(wide
+            .check_instruction(
+                &condition(register.clone(), u128::MAX, Instruction::PauliX(0)),
+                None
+            )
+            .is_ok());
+        assert!(wide
+            .check_instruction(
+                &condition((0..100).collect(), 1 << 100, Instruction::PauliX(0)),
+                None
+            )
+            .is_err());
+
+        let mut clbits = vec![1; 130];
+        clbits[128] = 0;
+        clbits[129] = 0;
+        assert!(condition_holds(&register, u128::MAX, &clbits));
+        clbits[129] = 1;
+        assert!(!condition_holds(&register, u128::MAX, &clbits));
+
+        let Instruction::Custom { circuit: inner, .. } = circuit.instructions()[1].clone() else {
+            unreachable!();
+        };
+        let mut conditioned = QuantumCircuit::with_clbits(2, 1);
+        conditioned.c_if(
+            0,
+            1,
+            Instruction::Custom {
+                name: "tct".to_owned(),
+                circuit: inner,
+                input_qubits: vec![1, 0],
+            },
+        );
+
+        let flattened = conditioned.flatten();
+        assert_eq!(flattened.instructions().len(), 3);
+        assert_eq!(
+            flattened.instructions()[1],
+            condition(
+                vec![0],
+                1,
+                Instruction::ControlledNot {
+                    control: 1,
+                    target: 0
+                }
+            )
+        );
+        assert_eq!(flattened.depth(), 3);
+    }
+
+    #[test]
+    fn validates_custom_and_controlled_gates() {
+        let circuit = QuantumCircuit::new(3);
+        let mut bell = QuantumCircuit::new(2);
+        bell.h(0).cx(0, 1);
+        let custom = |circuit: &QuantumCircuit, input_qubits: Vec<usize>| Instruction::Custom {
+            name: "bell".to_owned(),
+            circuit: circuit.clone(),
+            input_qubits,
+        };
+        let controlled = |gate, control, target| Instruction::ControlledU {
+            gate: Box::new(gate),
+            control,
+            target,
+        };
+
+        // Inner instructions are checked against the inner circuit, not the outer one.
+        let invalid_inner = QuantumCircuit {
+            instructions: vec![Instruction::ControlledNot {
+                control: 0,
+                target: 2,
+            }],
+            ..QuantumCircuit::new(2)
+        };
+        let mut single_qubit = QuantumCircuit::new(1);
+        single_qubit.h(0).t(0);
+
+        assert!(circuit
+            .check_instruction(&custom(&bell, vec![2, 0]), None)
+            .is_ok());
+        assert!(circuit
+            .check_instruction(&controlled(custom(&single_qubit, vec![1]), 0, 1), None)
+            .is_ok());
+        for (instruction, message) in [
+            (custom(&bell, vec![0]), "doesn't match"),
+            (custom(&bell, vec![0, 1, 2]), "doesn't match"),
+            (custom(&bell, vec![1, 1]), "Repeated input qubit"),
+            (custom(&bell, vec![0, 3]), "Invalid input qubit"),
+            (
+                custom(&invalid_inner, vec![0, 1]),
+                "inside custom gate inner circuit",
+            ),
+            (
+                custom(&QuantumCircuit::with_clbits(0, 1), vec![]),
+                "Invalid classical bit",
+            ),
+            (
+                controlled(
+                    Instruction::Toffoli {
+                        control1: 0,
+                        control2: 1,
+                        target: 2,
+                    },
+                    0,
+                    2,
+                ),
+                "not a single-qubit gate",
+            ),
+            (
+                controlled(Instruction::Measure { qubit: 1, clbit: 0 }, 0, 1),
+                "not a single-qubit gate",
+            ),
+            (
+                controlled(custom(&bell, vec![1, 2]), 0, 1),
+                "not a single-qubit gate",
+            ),
+            (
+                controlled(Instruction::Hadamard(1), 1, 1),
+                "Repeated input qubit",
+            ),
+            (
+                controlled(Instruction::Hadamard(1), 0, 3),
+                "Invalid input qubit",
+            ),
+        ] {
+            let error = circuit.check_instruction(&instruction, None).unwrap_err();
+            assert!(error.contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn validates_initialize_instructions() {
+        let circuit = QuantumCircuit::new(64);
+        let initialize =
+            |qubits: Vec<usize>, amplitudes| Instruction::Initialize { qubits, amplitudes };
+
+        assert!(circuit
+            .check_instruction(
+                &initialize(vec![3], vec![Complex::from(0), Complex::from(1)]),
+                None
+            )
+            .is_ok());
+        for (instruction, message) in [
+            (
+                initialize(vec![3], vec![Complex::from(1)]),
+                "Amount of amplitudes",
+            ),
+            (
+                initialize((0..64).collect(), vec![Complex::from(1)]),
+                "Amount of amplitudes",
+            ),
+            (
+                initialize(vec![3], vec![Complex::from(1), Complex::from(1)]),
+                "not normalized",
+            ),
+        ] {
+            let error = circuit.check_instruction(&instruction, None).unwrap_err();
+            assert!(error.contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn declares_registers() {
+        let mut circuit = QuantumCircuit::with_clbits(1, 1);
+        let data = circuit.add_qreg("data", 3);
+        let anc = circuit.add_qreg("anc", 2);
+        let syndrome = circuit.add_creg("syndrome", 2);
+
+        assert_eq!((circuit.qubits(), circuit.clbits()), (6, 3));
+        assert_eq!(data.indices(), 1..4);
+        assert_eq!((anc.at(1), syndrome.at(0)), (5, 1));
+        assert_eq!(anc.position(4), Some(0));
+        assert_eq!(circuit.qreg("anc"), Some(&anc));
+        assert_eq!(circuit.creg("data"), None);
+
+        circuit.measure(&anc, &syndrome);
+        assert_eq!(
+            circuit.instructions()[1],
+            Instruction::Measure { qubit: 5, clbit: 2 }
+        );
+
+        let values = circuit.creg_values(&ClassicalRegister::new(vec![1, 0, 1]));
+        assert_eq!(values.len(), 1);
+        assert_eq!(values["syndrome"].bits(), &[0, 1]);
+        assert_eq!(circuit.format_clbits(&[1, 0, 1]), "10 1");
+
+        for name in ["anc", "1q", "Data", "a-b", ""] {
+            let result = std::panic::catch_unwind(|| circuit.clone().add_creg(name, 1));
+            assert!(result.is_err(), "{:?} is accepted", name);
+        }
+
+        let flattened = circuit.flatten();
+        assert_eq!(flattened.qregs(), circuit.qregs());
+        assert_eq!(flattened.cregs(), circuit.cregs());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_circuits_to_json() {
+        use crate::layout::Layout;
+
+        let mut circuit = circuit_with_custom_gate();
+        circuit.add(Instruction::ControlledU {
+            gate: Box::new(Instruction::RotationY {
+                qubit: 1,
+                phase: 0.5,
+            }),
+            control: 0,
+            target: 1,
+        });
+        circuit.set_layout(Layout::new(vec![2, 0, 1], 4));
+
+        let json = serde_json::to_string(&circuit).unwrap();
+        assert!(json.starts_with(r#"{"version":1,"qubits":3"#));
+        assert!(!json.contains("regs"));
+        assert_eq!(
+            serde_json::from_str::<QuantumCircuit>(&json).unwrap(),
+            circuit
+        );
+
+        let mut circuit = QuantumCircuit::new(0);
+        circuit.add_qreg("q", 2);
+        circuit.add_creg("c", 2);
+        let json = serde_json::to_string(&circuit).unwrap();
+        assert!(json.ends_with(r#""cregs":[{"name":"c","offset":0,"size":2}]}"#));
+        assert_eq!(
+            serde_json::from_str::<QuantumCircuit>(&json).unwrap(),
+            circuit
+        );
+
+        let mut circuit = QuantumCircuit::with_clbits(1, 128);
+        circuit.c_if(0..128, u128::MAX, Instruction::PauliX(0));
+        let json = serde_json::to_string(&circuit).unwrap();
+        assert!(json.contains(&u128::MAX.to_string()));
+        assert_eq!(
+            serde_json::from_str::<QuantumCircuit>(&json).unwrap(),
+            circuit
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rejects_invalid_json_circuits() {
+        let error = serde_json::from_str::<QuantumCircuit>(
+            r#"{"version":2,"qubits":1,"clbits":0,"instructions":[],"layout":null}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("schema version 2"));
+
+        let error = serde_json::from_str::<QuantumCircuit>(
+            r#"{"version":1,"qubits":1,"clbits":0,"instructions":[{"Hadamard":1}],"layout":null}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("Invalid input qubit"));
+
+        let qubits: Vec<_> = (0..64).map(|qubit| qubit.to_string()).collect();
+        let error = serde_json::from_str::<QuantumCircuit>(&format!(
+            r#"{{"version":1,"qubits":64,"clbits":0,"layout":null,"instructions":[
+                {{"Initialize":{{"qubits":[{}],"amplitudes":[{{"real":1.0,"imag":0.0}}]}}}}]}}"#,
+            qubits.join(",")
+        ))
+        .unwrap_err();
+        assert!(error.to_string().contains("Amount of amplitudes"));
+
+        let error = serde_json::from_str::<QuantumCircuit>(
+            r#"{"version":1,"qubits":2,"clbits":0,"instructions":[],
+                "layout":{"logical_to_physical":[1,1],"physical_qubits":2}}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("used twice"));
+
+        let error = serde_json::from_str::<QuantumCircuit>(
+            r#"{"version":1,"qubits":1,"clbits":0,"instructions":[],
+                "layout":{"logical_to_physical":[0],"physical_qubits":18446744073709551615}}"#,
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("more than 1048576 physical qubits"));
+
+        let error = serde_json::from_str::<QuantumCircuit>(
+            r#"{"version":1,"qubits":2,"clbits":0,"instructions":[],"layout":null,
+                "qregs":[{"name":"a","offset":0,"size":2},{"name":"b","offset":1,"size":1}]}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("overlap"));
+    }
+}
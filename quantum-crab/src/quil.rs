@@ -0,0 +1,1853 @@
+//! Implements conversion of quantum circuits from and to [Quil](https://quil-lang.github.io/)
+//! programs.
+//!
+//! Supported subset of Quil consists of standard gates `I`, `X`, `Y`, `Z`, `H`, `S`,
+//! `T`, `PHASE`, `RX`, `RY`, `RZ`, `CNOT`, `CCNOT` and `SWAP` (with `DAGGER` and
+//! `CONTROLLED` modifiers, where the result is representable as an [`Instruction`]),
+//! `DECLARE` of `BIT` memory regions, `MEASURE` into them, `RESET` of a single qubit
+//! and `FENCE`, that corresponds to [`Instruction::Barrier`]. Single-qubit custom
+//! gates are exported as `DEFGATE` with the unitary matrix of the gate.
+//!
+//! Classical registers of the circuit (see [`crate::register`]) are declared as
+//! memory regions with the same names, while classical bits outside of registers are
+//! stored in the `ro` region. Imported regions take consecutive classical bits in order
+//! of declaration, and every region except `ro` becomes a classical register.
+//!
+//! [`Instruction::If`] is exported as `JUMP-WHEN` and `JUMP-UNLESS` jumps over the
+//! conditioned instruction to the `LABEL` after it. The same pattern is imported back
+//! as conditioned instructions, while other control flow is not supported.
+//!
+//! ```
+//! use quantum_crab::{circuit, quil::{from_quil, to_quil}};
+//!
+//! let circuit = circuit!(2; h 0; cx 0 1; rx(pi/2) 0; measure 0 -> 0);
+//! let program = to_quil(&circuit).unwrap();
+//!
+//! assert_eq!(
+//!     program,
+//!     "DECLARE ro BIT[1]\nH 0\nCNOT 0 1\nRX(pi/2) 0\nMEASURE 0 ro[0]\n"
+//! );
+//! assert_eq!(from_quil(&program), Ok(circuit));
+//! ```
+//!
+//! As Quil programs don't declare the amount of qubits, imported circuit has one
+//! qubit more than the highest qubit used in the program.
+use crate::{
+    complex::Complex,
+    matrix::Matrix,
+    quantum_circuit::{Instruction, QuantumCircuit},
+    register::Register,
+    state_preparation::state_preparation,
+    statevector_backend::unitary,
+};
+use num::Zero;
+use std::{
+    f64::consts::PI,
+    fmt::{self, Display},
+};
+
+/// Name of the memory region measurements are stored into by [`to_quil`].
+const MEMORY_REGION: &str = "ro";
+
+/// Names, that can't be used for custom gates.
+const RESERVED_NAMES: &[&str] = &[
+    "I",
+    "X",
+    "Y",
+    "Z",
+    "H",
+    "S",
+    "T",
+    "PHASE",
+    "RX",
+    "RY",
+    "RZ",
+    "CNOT",
+    "CCNOT",
+    "SWAP",
+    "CZ",
+    "CPHASE",
+    "CPHASE00",
+    "CPHASE01",
+    "CPHASE10",
+    "ISWAP",
+    "PSWAP",
+    "XY",
+    "CSWAP",
+    "DAGGER",
+    "CONTROLLED",
+    "FORKED",
+    "DECLARE",
+    "DEFGATE",
+    "DEFCIRCUIT",
+    "MEASURE",
+    "FENCE",
+    "RESET",
+    "HALT",
+    "WAIT",
+    "NOP",
+    "LABEL",
+    "JUMP",
+    "PRAGMA",
+];
+
+/// Quil instructions, that can't be represented in quantum circuits.
+const UNSUPPORTED_INSTRUCTIONS: &[&str] = &[
+    "HALT",
+    "WAIT",
+    "NOP",
+    "JUMP",
+    "DEFCIRCUIT",
+    "DEFCAL",
+    "DEFFRAME",
+    "DEFWAVEFORM",
+    "INCLUDE",
+    "MOVE",
+    "EXCHANGE",
+    "CONVERT",
+    "LOAD",
+    "STORE",
+    "NEG",
+    "NOT",
+    "AND",
+    "IOR",
+    "XOR",
+    "ADD",
+    "SUB",
+    "MUL",
+    "DIV",
+    "EQ",
+    "GT",
+    "GE",
+    "LT",
+    "LE",
+    "PULSE",
+    "CAPTURE",
+    "RAW-CAPTURE",
+    "DELAY",
+    "SET-FREQUENCY",
+    "SHIFT-FREQUENCY",
+    "SET-PHASE",
+    "SHIFT-PHASE",
+    "SWAP-PHASES",
+    "SET-SCALE",
+];
+
+/// Error returned when circuit can't be converted from or to Quil.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuilError {
+    /// Line of the program can't be parsed.
+    Syntax { line: usize, message: String },
+    /// Line of the program uses Quil feature, that can't be represented in quantum
+    /// circuits.
+    Unsupported { line: usize, feature: String },
+    /// Instruction on the line of the program is not valid.
+    InvalidInstruction { line: usize, message: String },
+    /// Instruction of the circuit can't be represented in Quil.
+    NotExportable(String),
+}
+
+impl Display for QuilError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuilError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            QuilError::Unsupported { line, feature } => {
+                write!(f, "line {}: {} is not supported", line, feature)
+            }
+            QuilError::InvalidInstruction { line, message } => {
+                write!(f, "line {}: {}", line, message)
+            }
+            QuilError::NotExportable(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for QuilError {}
+
+/// Converts the circuit into Quil program.
+///
+/// Measurements are stored into memory regions of classical registers, or into `ro`
+/// memory region for classical bits outside of registers. Fails if the circuit contains
+/// custom gates acting on more than one qubit (as [`from_quil`] can't import such
+/// `DEFGATE`), custom gates with measurements, resets or conditioned instructions (that
+/// don't have unitary matrix) or controlled gates acting on more than one qubit.
+pub fn to_quil(circuit: &QuantumCircuit) -> Result<String, QuilError> {
+    let mut definitions = Vec::new();
+    for instruction in circuit.instructions() {
+        collect_definitions(instruction, &mut definitions)?;
+    }
+
+    let mut program = String::new();
+    write_declarations(&mut program, circuit)?;
+
+    for definition in &definitions {
+        program.push_str(&format!("DEFGATE {}:\n", definition.quil_name));
+
+        let matrix = unitary(definition.circuit);
+        let size = matrix.rows();
+        // Quil matrices treat the first qubit of the gate as the most significant one.
+        let index = |i: usize| {
+            i.reverse_bits()
+                .checked_shr(usize::BITS - definition.circuit.qubits() as u32)
+                .unwrap_or(0)
+        };
+
+        for row in 0..size {
+            let entries: Vec<_> = (0..size)
+                .map(|col| format_complex(matrix.get(index(row), index(col))))
+                .collect();
+            program.push_str(&format!("    {}\n", entries.join(", ")));
+        }
+    }
+
+    let mut labels = 0;
+    for instruction in circuit.instructions() {
+        write_instruction(
+            &mut program,
+            circuit,
+            instruction,
+            &definitions,
+            &mut labels,
+        )?;
+    }
+
+    Ok(program)
+}
+
+/// Appends `DECLARE` of memory regions in order of their first classical bits.
+fn write_declarations(program: &mut String, circuit: &QuantumCircuit) -> Result<(), QuilError> {
+    let registered: usize = circuit.cregs().iter().map(|register| register.size()).sum();
+    let unregistered = circuit.clbits() - registered;
+
+    if unregistered > 0 && circuit.creg(MEMORY_REGION).is_some() {
+        return Err(QuilError::NotExportable(format!(
+            "Classical register {} clashes with memory region of classical bits outside \
+             of registers",
+            MEMORY_REGION
+        )));
+    }
+
+    let mut declared = Vec::new();
+    for clbit in 0..circuit.clbits() {
+        let (name, size) = match circuit
+            .cregs()
+            .iter()
+            .find(|register| register.position(clbit).is_some())
+        {
+            Some(register) => (register.name(), register.size()),
+            None => (MEMORY_REGION, unregistered),
+        };
+
+        if !declared.contains(&name) {
+            program.push_str(&format!("DECLARE {} BIT[{}]\n", name, size));
+            declared.push(name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reference to the classical bit of the circuit in the memory region, that it is
+/// stored in (see [`to_quil`]).
+fn memory_reference(circuit: &QuantumCircuit, clbit: usize) -> String {
+    let mut unregistered = clbit;
+
+    for register in circuit.cregs() {
+        if let Some(index) = register.position(clbit) {
+            return format!("{}[{}]", register.name(), index);
+        }
+
+        if register.offset() < clbit {
+            unregistered -= register.size();
+        }
+    }
+
+    format!("{}[{}]", MEMORY_REGION, unregistered)
+}
+
+/// Appends lines of the instruction to the program. Conditioned instructions are
+/// skipped by jumps to the numbered label after them, if any classical bit of the
+/// register differs from the value.
+fn write_instruction(
+    program: &mut String,
+    circuit: &QuantumCircuit,
+    instruction: &Instruction,
+    definitions: &[Definition],
+    labels: &mut usize,
+) -> Result<(), QuilError> {
+    let line = match instruction {
+        Instruction::Measure { qubit, clbit } => {
+            format!("MEASURE {} {}", qubit, memory_reference(circuit, *clbit))
+        }
+        Instruction::Reset(qubit) => format!("RESET {}", qubit),
+        Instruction::Barrier(qubits) if qubits.is_empty() => return Ok(()),
+        Instruction::Barrier(qubits) => format!("FENCE {}", join_qubits(qubits)),
+        Instruction::Initialize { qubits, amplitudes } => {
+            // State preparation is exported as the synthesized gates.
+            for gate in state_preparation(amplitudes).instructions() {
+                let gate = gate.map_qubits(|qubit| qubits[qubit]);
+                program.push_str(&format!(
+                    "{} {}\n",
+                    operator(&gate, definitions)?,
+                    join_qubits(&gate.qubits())
+                ));
+            }
+
+            return Ok(());
+        }
+        Instruction::If {
+            register,
+            value,
+            instruction,
+        } => {
+            let label = format!("@skip{}", labels);
+            *labels += 1;
+
+            for (i, clbit) in register.iter().enumerate() {
+                let jump = if i < 128 && value >> i & 1 == 1 {
+                    "JUMP-UNLESS"
+                } else {
+                    "JUMP-WHEN"
+                };
+                program.push_str(&format!(
+                    "{} {} {}\n",
+                    jump,
+                    label,
+                    memory_reference(circuit, *clbit)
+                ));
+            }
+
+            write_instruction(program, circuit, instruction, definitions, labels)?;
+            format!("LABEL {}", label)
+        }
+        _ => format!(
+            "{} {}",
+            operator(instruction, definitions)?,
+            join_qubits(&instruction.qubits())
+        ),
+    };
+
+    program.push_str(&line);
+    program.push('\n');
+    Ok(())
+}
+
+/// Custom gate exported as `DEFGATE`.
+struct Definition<'a> {
+    name: &'a str,
+    circuit: &'a QuantumCircuit,
+    quil_name: String,
+}
+
+fn collect_definitions<'a>(
+    instruction: &'a Instruction,
+    definitions: &mut Vec<Definition<'a>>,
+) -> Result<(), QuilError> {
+    match instruction {
+        Instruction::ControlledU { gate, .. } => collect_definitions(gate, definitions),
+        Instruction::If { instruction, .. } => collect_definitions(instruction, definitions),
+        Instruction::Custom { name, circuit, .. } => {
+            if definitions
+                .iter()
+                .any(|definition| definition.name == name && definition.circuit == circuit)
+            {
+                return Ok(());
+            }
+
+            if circuit.qubits() != 1 {
+                return Err(QuilError::NotExportable(format!(
+                    "Custom gate {:?} doesn't act on a single qubit and can't be exported \
+                     as DEFGATE",
+                    name
+                )));
+            }
+
+            if !is_unitary(circuit) {
+                return Err(QuilError::NotExportable(format!(
+                    "Custom gate {:?} contains measurements and can't be exported as DEFGATE",
+                    name
+                )));
+            }
+
+            let mut quil_name: String = name
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            if !quil_name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                quil_name.insert(0, '_');
+            }
+
+            let base_name = quil_name.clone();
+            let mut suffix = 1;
+            while RESERVED_NAMES.contains(&quil_name.as_str())
+                || definitions
+                    .iter()
+                    .any(|definition| definition.quil_name == quil_name)
+            {
+                suffix += 1;
+                quil_name = format!("{}_{}", base_name, suffix);
+            }
+
+            definitions.push(Definition {
+                name,
+                circuit,
+                quil_name,
+            });
+
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks if the circuit doesn't contain measurements, resets and conditioned
+/// instructions.
+fn is_unitary(circuit: &QuantumCircuit) -> bool {
+    circuit
+        .instructions()
+        .iter()
+        .all(|instruction| match instruction {
+            Instruction::Measure { .. } | Instruction::Reset(..) | Instruction::If { .. } => false,
+            Instruction::Custom { circuit, .. } => is_unitary(circuit),
+            _ => true,
+        })
+}
+
+/// Quil gate with modifiers and parameters, but without qubits.
+fn operator(instruction: &Instruction, definitions: &[Definition]) -> Result<String, QuilError> {
+    Ok(match instruction {
+        Instruction::Identity(..) => "I".to_owned(),
+        Instruction::PauliX(..) => "X".to_owned(),
+        Instruction::PauliY(..) => "Y".to_owned(),
+        Instruction::PauliZ(..) => "Z".to_owned(),
+        Instruction::Hadamard(..) => "H".to_owned(),
+        Instruction::Phase { phase, .. } => format!("PHASE({})", format_angle(*phase)),
+        Instruction::PhaseDagger { phase, .. } => {
+            format!("DAGGER PHASE({})", format_angle(*phase))
+        }
+        Instruction::T(..) => "T".to_owned(),
+        Instruction::TDagger(..) => "DAGGER T".to_owned(),
+        Instruction::S(..) => "S".to_owned(),
+        Instruction::SDagger(..) => "DAGGER S".to_owned(),
+        Instruction::ControlledNot { .. } => "CNOT".to_owned(),
+        Instruction::ControlledU { gate, .. } => {
+            if gate.qubits().len() != 1 {
+                return Err(QuilError::NotExportable(format!(
+                    "Controlled gate {:?} doesn't act on a single qubit",
+                    gate
+                )));
+            }
+
+            format!("CONTROLLED {}", operator(gate, definitions)?)
+        }
+        Instruction::Toffoli { .. } => "CCNOT".to_owned(),
+        Instruction::Swap(..) => "SWAP".to_owned(),
+        Instruction::RotationX { phase, .. } => format!("RX({})", format_angle(*phase)),
+        Instruction::RotationY { phase, .. } => format!("RY({})", format_angle(*phase)),
+        Instruction::RotationZ { phase, .. } => format!("RZ({})", format_angle(*phase)),
+        Instruction::Custom { name, circuit, .. } => definitions
+            .iter()
+            .find(|definition| definition.name == name && definition.circuit == circuit)
+            .unwrap()
+            .quil_name
+            .clone(),
+        Instruction::Measure { .. }
+        | Instruction::Reset(..)
+        | Instruction::If { .. }
+        | Instruction::Barrier(..)
+        | Instruction::Initialize { .. } => {
+            return Err(QuilError::NotExportable(format!(
+                "{:?} can't be used as a gate",
+                instruction
+            )))
+        }
+    })
+}
+
+fn join_qubits(qubits: &[usize]) -> String {
+    qubits
+        .iter()
+        .map(|qubit| qubit.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats the angle as a multiple of `pi`, if it can be parsed back exactly.
+fn format_angle(angle: f64) -> String {
+    for denominator in [1, 2, 3, 4, 6, 8, 12, 16] {
+        let numerator = (angle / PI * denominator as f64).round();
+
+        if numerator == 0f64 || numerator.abs() > 64f64 {
+            continue;
+        }
+
+        if numerator * PI / denominator as f64 == angle {
+            let numerator = match numerator as i64 {
+                1 => "pi".to_owned(),
+                -1 => "-pi".to_owned(),
+                numerator => format!("{}*pi", numerator),
+            };
+
+            return if denominator == 1 {
+                numerator
+            } else {
+                format!("{}/{}", numerator, denominator)
+            };
+        }
+    }
+
+    angle.to_string()
+}
+
+fn format_complex(number: Complex) -> String {
+    let round = |x: f64| if x.abs() < 1e-14 { 0f64 } else { x };
+    let (real, imag) = (round(number.real), round(number.imag));
+
+    if imag == 0f64 {
+        real.to_string()
+    } else if real == 0f64 {
+        format!("{}i", imag)
+    } else {
+        format!("{}{:+}i", real, imag)
+    }
+}
+
+/// Parses Quil program into the quantum circuit.
+///
+/// Fails with [`QuilError::Unsupported`] if the program uses Quil features, that can't
+/// be represented in quantum circuits: classical control flow, parametric gates,
+/// `DEFCIRCUIT`, `DEFGATE` acting on more than one qubit and others. `PRAGMA`
+/// directives are ignored.
+///
+/// `JUMP-WHEN` and `JUMP-UNLESS` to the same label, followed by instructions and the
+/// `LABEL`, are imported as [`Instruction::If`] (the pattern written by [`to_quil`]).
+/// Every skipped instruction is conditioned on the classical bits of the jumps, so
+/// only the last one of them may overwrite these bits.
+///
+/// Single-qubit `DEFGATE` is turned into [`Instruction::Custom`] gate, made of
+/// `RZ`, `RY` and `RZ` rotations followed by the correction of the global phase, so
+/// that the gate matches the matrix exactly, even with the `CONTROLLED` modifier.
+pub fn from_quil(program: &str) -> Result<QuantumCircuit, QuilError> {
+    let mut parser = Parser {
+        memory: Vec::new(),
+        definitions: Vec::new(),
+        instructions: Vec::new(),
+        labels: Vec::new(),
+        blocks: Vec::new(),
+    };
+
+    let lines: Vec<(usize, &str)> = program
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap()))
+        .collect();
+
+    let mut i = 0;
+    while i < lines.len() {
+        let (line, text) = lines[i];
+        i += 1;
+
+        if text.trim_start().starts_with("DEFGATE") {
+            let mut rows = Vec::new();
+            while i < lines.len()
+                && lines[i].1.starts_with(char::is_whitespace)
+                && !lines[i].1.trim().is_empty()
+            {
+                rows.push(lines[i]);
+                i += 1;
+            }
+
+            parser.parse_definition(line, text.trim(), &rows)?;
+            continue;
+        }
+
+        for statement in text.split(';') {
+            let statement = statement.trim();
+
+            if !statement.is_empty() {
+                parser.parse_statement(line, statement)?;
+            }
+        }
+    }
+
+    if let Some(block) = parser.blocks.first() {
+        return Err(QuilError::Unsupported {
+            line: block.line,
+            feature: CONTROL_FLOW.to_owned(),
+        });
+    }
+
+    let mut qubits = 0;
+    for (line, instruction) in &parser.instructions {
+        for qubit in instruction.qubits() {
+            let count = qubit
+                .checked_add(1)
+                .ok_or_else(|| QuilError::InvalidInstruction {
+                    line: *line,
+                    message: format!("Qubit {} is out of range", qubit),
+                })?;
+            qubits = qubits.max(count);
+        }
+    }
+    // Total size of memory regions is checked, when they are declared.
+    let clbits = parser.memory.iter().map(|(_, _, size)| size).sum();
+
+    let mut circuit = QuantumCircuit::with_clbits(qubits, clbits);
+    let mut cregs = Vec::new();
+    let mut offset = 0;
+    for (line, name, size) in parser.memory {
+        if name != MEMORY_REGION {
+            cregs.push(Register::new(&name, offset, size));
+            circuit
+                .set_registers(Vec::new(), cregs.clone())
+                .map_err(|message| QuilError::InvalidInstruction { line, message })?;
+        }
+
+        offset += size;
+    }
+
+    for (line, instruction) in parser.instructions {
+        let instruction = match instruction {
+            Instruction::Barrier(qubits) if qubits.is_empty() => {
+                Instruction::Barrier((0..circuit.qubits()).collect())
+            }
+            instruction => instruction,
+        };
+
+        circuit
+            .check_instruction(&instruction, None)
+            .map_err(|message| QuilError::InvalidInstruction { line, message })?;
+        circuit.add(instruction);
+    }
+
+    Ok(circuit)
+}
+
+struct Parser {
+    /// Line, name and size of every declared `BIT` memory region.
+    memory: Vec<(usize, String, usize)>,
+    /// Custom gates defined with `DEFGATE`.
+    definitions: Vec<(String, QuantumCircuit)>,
+    /// Parsed instructions with their line numbers. `FENCE` without qubits is stored
+    /// as empty barrier, as the amount of qubits is not known yet.
+    instructions: Vec<(usize, Instruction)>,
+    /// Labels of all jumps in the program.
+    labels: Vec<String>,
+    /// Nested blocks of skipped instructions, that are not closed by their labels yet.
+    blocks: Vec<Block>,
+}
+
+/// Description of the control flow, that can't be imported.
+const CONTROL_FLOW: &str = "Control flow other than jumps over instructions to the label";
+
+/// Instructions skipped by `JUMP-WHEN` and `JUMP-UNLESS` to the label, unless the
+/// classical bits of the register match the value.
+struct Block {
+    /// Line of the first jump.
+    line: usize,
+    label: String,
+    register: Vec<usize>,
+    value: u128,
+    /// Whether the block has instructions or nested blocks, so that it takes no more
+    /// jumps.
+    started: bool,
+    /// Whether an instruction of the block has overwritten classical bits of the
+    /// register, so that the following instructions can't be conditioned on them.
+    overwritten: bool,
+}
+
+impl Parser {
+    /// Classical bit of the circuit referenced as `name[index]`. Memory regions take
+    /// consecutive classical bits in order of declaration.
+    fn clbit(&self, line: usize, name: &str, index: usize) -> Result<usize, QuilError> {
+        let mut offset = 0;
+
+        for (_, memory, size) in &self.memory {
+            if memory == name {
+                if index >= *size {
+                    return Err(QuilError::InvalidInstruction {
+                        line,
+                        message: format!("Invalid memory reference {}[{}]", name, index),
+                    });
+                }
+
+                return Ok(offset + index);
+            }
+
+            offset += size;
+        }
+
+        Err(QuilError::InvalidInstruction {
+            line,
+            message: format!("Memory region {} is not declared", name),
+        })
+    }
+
+    /// Classical bit referenced as `name[index]`, or as `name` for the first bit of the
+    /// memory region.
+    fn parse_clbit(&self, line: usize, target: &str) -> Result<usize, QuilError> {
+        let (name, index) = match target.split_once('[') {
+            Some((name, index)) => (
+                name,
+                index
+                    .strip_suffix(']')
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .ok_or_else(|| QuilError::Syntax {
+                        line,
+                        message: format!("Invalid memory reference {}", target),
+                    })?,
+            ),
+            None => (target, 0),
+        };
+
+        self.clbit(line, name, index)
+    }
+
+    /// Adds the instruction, conditioned on every open block of skipped instructions.
+    fn push(&mut self, line: usize, mut instruction: Instruction) -> Result<(), QuilError> {
+        for block in self.blocks.iter_mut().rev() {
+            if block.overwritten {
+                return Err(QuilError::Unsupported {
+                    line,
+                    feature: "Instructions skipped after overwriting the condition".to_owned(),
+                });
+            }
+
+            block.started = true;
+            block.overwritten = instruction
+                .clbits()
+                .iter()
+                .any(|clbit| block.register.contains(clbit));
+            instruction = Instruction::If {
+                register: block.register.clone(),
+                value: block.value,
+                instruction: Box::new(instruction),
+            };
+        }
+
+        self.instructions.push((line, instruction));
+        Ok(())
+    }
+
+    fn parse_definition(
+        &mut self,
+        line: usize,
+        header: &str,
+        rows: &[(usize, &str)],
+    ) -> Result<(), QuilError> {
+        let syntax = |message: &str| QuilError::Syntax {
+            line,
+            message: message.to_owned(),
+        };
+        let unsupported = |feature: &str| QuilError::Unsupported {
+            line,
+            feature: feature.to_owned(),
+        };
+
+        let header = header
+            .strip_prefix("DEFGATE")
+            .and_then(|header| header.strip_suffix(':'))
+            .ok_or_else(|| syntax("Expected `DEFGATE name:`"))?;
+        let mut words = header.split_whitespace();
+        let name = words.next().ok_or_else(|| syntax("Missing gate name"))?;
+
+        match (words.next(), words.next(), words.next()) {
+            (None, ..) | (Some("AS"), Some("MATRIX"), None) => {}
+            (Some("AS"), Some(kind), None) => {
+                return Err(unsupported(&format!("DEFGATE AS {}", kind)))
+            }
+            _ => return Err(syntax("Invalid DEFGATE header")),
+        }
+
+        if name.contains('(') {
+            return Err(unsupported("DEFGATE with parameters"));
+        }
+
+        if !is_identifier(name) {
+            return Err(syntax(&format!("Invalid gate name {:?}", name)));
+        }
+
+        if RESERVED_NAMES.contains(&name) || self.definition(name).is_some() {
+            return Err(syntax(&format!("Gate {} is already defined", name)));
+        }
+
+        let mut entries = Vec::new();
+        for (line, row) in rows {
+            let row = split_top_level(row.trim(), ',')
+                .into_iter()
+                .map(|entry| evaluate(*line, entry))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if row.len() != rows.len() {
+                return Err(QuilError::Syntax {
+                    line: *line,
+                    message: format!("Expected {} entries in the row", rows.len()),
+                });
+            }
+
+            entries.extend(row);
+        }
+
+        match rows.len() {
+            2 => {}
+            size if size.is_power_of_two() && size > 2 => {
+                return Err(unsupported("DEFGATE acting on more than one qubit"))
+            }
+            _ => return Err(syntax("DEFGATE matrix size must be a power of two")),
+        }
+
+        let matrix = Matrix::new(2, 2, entries);
+        if !is_unitary_matrix(&matrix) {
+            return Err(QuilError::InvalidInstruction {
+                line,
+                message: format!("Matrix of gate {} is not unitary", name),
+            });
+        }
+
+        let mut circuit = QuantumCircuit::new(1);
+        let (theta, phi, lambda) = zyz_angles(&matrix);
+        for (phase, gate) in [(lambda, 'z'), (theta, 'y'), (phi, 'z')] {
+            if phase.abs() > 1e-12 {
+                match gate {
+                    'y' => circuit.ry(0, phase),
+                    _ => circuit.rz(0, phase),
+                };
+            }
+        }
+
+        // Global phase of the matrix turns into a relative one, when the gate is
+        // controlled, so it is restored with `e^(i * phase) = X P(phase) X P(phase)`.
+        let rotations = unitary(&circuit);
+        let row = if matrix.get(0, 0).norm() >= matrix.get(1, 0).norm() {
+            0
+        } else {
+            1
+        };
+        let ratio = matrix.get(row, 0) * rotations.get(row, 0).conjugate();
+        let phase = ratio.imag.atan2(ratio.real);
+        if phase.abs() > 1e-12 {
+            circuit.p(0, phase).x(0).p(0, phase).x(0);
+        }
+
+        self.definitions.push((name.to_owned(), circuit));
+        Ok(())
+    }
+
+    fn definition(&self, name: &str) -> Option<&QuantumCircuit> {
+        self.definitions
+            .iter()
+            .find(|(definition, _)| definition == name)
+            .map(|(_, circuit)| circuit)
+    }
+
+    fn parse_statement(&mut self, line: usize, statement: &str) -> Result<(), QuilError> {
+        let syntax = |message: String| QuilError::Syntax { line, message };
+        let unsupported = |feature: String| QuilError::Unsupported { line, feature };
+
+        let keyword = statement
+            .split(|c: char| c.is_whitespace() || c == '(')
+            .next()
+            .unwrap();
+        let arguments: Vec<&str> = statement[keyword.len()..].split_whitespace().collect();
+
+        match keyword {
+            "PRAGMA" => Ok(()),
+            "DECLARE" => {
+                let (name, size) = match arguments.as_slice() {
+                    [name, kind] => (name, parse_memory_type(kind)),
+                    [_, _, "SHARING", ..] => {
+                        return Err(unsupported("DECLARE with SHARING".to_owned()))
+                    }
+                    _ => return Err(syntax("Expected `DECLARE name BIT[size]`".to_owned())),
+                };
+                let size =
+                    size.ok_or_else(|| unsupported(format!("Memory of type {}", arguments[1])))?;
+
+                if self.memory.iter().any(|(_, memory, _)| memory == name) {
+                    return Err(QuilError::InvalidInstruction {
+                        line,
+                        message: format!("Memory region {} is already declared", name),
+                    });
+                }
+
+                self.memory
+                    .iter()
+                    .try_fold(size, |total, (_, _, size)| total.checked_add(*size))
+                    .ok_or_else(|| QuilError::InvalidInstruction {
+                        line,
+                        message: "Memory regions are too large".to_owned(),
+                    })?;
+
+                self.memory.push((line, name.to_string(), size));
+                Ok(())
+            }
+            "MEASURE" => {
+                let (qubit, target) = match arguments.as_slice() {
+                    [qubit, target] => (parse_qubit(line, qubit)?, *target),
+                    [_] => return Err(unsupported("MEASURE without memory reference".to_owned())),
+                    _ => return Err(syntax("Expected `MEASURE qubit memory[index]`".to_owned())),
+                };
+
+                let clbit = self.parse_clbit(line, target)?;
+                self.push(line, Instruction::Measure { qubit, clbit })
+            }
+            "RESET" => {
+                let qubit = match arguments.as_slice() {
+                    [qubit] => parse_qubit(line, qubit)?,
+                    [] => return Err(unsupported("RESET of all qubits".to_owned())),
+                    _ => return Err(syntax("Expected `RESET qubit`".to_owned())),
+                };
+
+                self.push(line, Instruction::Reset(qubit))
+            }
+            "FENCE" => {
+                let qubits = arguments
+                    .iter()
+                    .map(|qubit| parse_qubit(line, qubit))
+                    .collect::<Result<_, _>>()?;
+
+                self.push(line, Instruction::Barrier(qubits))
+            }
+            "JUMP-WHEN" | "JUMP-UNLESS" => {
+                let (label, target) = match arguments.as_slice() {
+                    [label, target] if is_label(label) => (*label, *target),
+                    _ => {
+                        return Err(syntax(format!(
+                            "Expected `{} @label memory[index]`",
+                            keyword
+                        )))
+                    }
+                };
+                let clbit = self.parse_clbit(line, target)?;
+
+                let continued = matches!(
+                    self.blocks.last(),
+                    Some(block) if block.label == label && !block.started
+                );
+                if !continued {
+                    if self.labels.iter().any(|used| used == label) {
+                        return Err(unsupported(CONTROL_FLOW.to_owned()));
+                    }
+
+                    for block in &mut self.blocks {
+                        block.started = true;
+                    }
+                    self.labels.push(label.to_owned());
+                    self.blocks.push(Block {
+                        line,
+                        label: label.to_owned(),
+                        register: Vec::new(),
+                        value: 0,
+                        started: false,
+                        overwritten: false,
+                    });
+                }
+
+                let block = self.blocks.last_mut().unwrap();
+                // `JUMP-UNLESS` skips the instructions, when the bit is zero.
+                if keyword == "JUMP-UNLESS" {
+                    if block.register.len() >= 128 {
+                        return Err(QuilError::InvalidInstruction {
+                            line,
+                            message: "Condition value doesn't fit into 128 bits".to_owned(),
+                        });
+                    }
+
+                    block.value |= 1 << block.register.len();
+                }
+                block.register.push(clbit);
+                Ok(())
+            }
+            "LABEL" => {
+                let label = match arguments.as_slice() {
+                    [label] if is_label(label) => *label,
+                    _ => return Err(syntax("Expected `LABEL @label`".to_owned())),
+                };
+
+                match self.blocks.last() {
+                    Some(block) if block.label == label => {
+                        self.blocks.pop();
+                        Ok(())
+                    }
+                    _ => Err(unsupported(CONTROL_FLOW.to_owned())),
+                }
+            }
+            keyword if UNSUPPORTED_INSTRUCTIONS.contains(&keyword) => {
+                Err(unsupported(format!("{} instruction", keyword)))
+            }
+            _ => {
+                let instruction = self.parse_gate(line, statement)?;
+                self.push(line, instruction)
+            }
+        }
+    }
+
+    fn parse_gate(&self, line: usize, statement: &str) -> Result<Instruction, QuilError> {
+        let syntax = |message: String| QuilError::Syntax { line, message };
+        let unsupported = |feature: String| QuilError::Unsupported { line, feature };
+
+        let mut rest = statement;
+        let mut modifiers = Vec::new();
+        loop {
+            let word = rest.split_whitespace().next().unwrap_or("");
+
+            match word {
+                "DAGGER" | "CONTROLLED" => modifiers.push(word),
+                "FORKED" => return Err(unsupported("FORKED modifier".to_owned())),
+                _ => break,
+            }
+
+            rest = rest.trim_start()[word.len()..].trim_start();
+        }
+
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '(')
+            .unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        rest = &rest[name_end..];
+
+        let mut parameters = Vec::new();
+        if rest.starts_with('(') {
+            let end = matching_parenthesis(rest)
+                .ok_or_else(|| syntax("Unbalanced parentheses".to_owned()))?;
+
+            for parameter in split_top_level(&rest[1..end], ',') {
+                let value = evaluate(line, parameter)?;
+                if value.imag.abs() > 1e-12 {
+                    return Err(syntax(format!(
+                        "Parameter {} is not real",
+                        parameter.trim()
+                    )));
+                }
+
+                parameters.push(value.real);
+            }
+
+            rest = &rest[end + 1..];
+        }
+
+        let qubits = rest
+            .split_whitespace()
+            .map(|qubit| parse_qubit(line, qubit))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let controls = modifiers
+            .iter()
+            .filter(|modifier| **modifier == "CONTROLLED")
+            .count();
+        if qubits.len() < controls {
+            return Err(syntax(
+                "Not enough qubits for CONTROLLED modifiers".to_owned(),
+            ));
+        }
+
+        let (control_qubits, gate_qubits) = qubits.split_at(controls);
+        let mut instruction = self.gate(line, name, &parameters, gate_qubits)?;
+
+        let mut control_qubits = control_qubits.iter().rev();
+        for modifier in modifiers.iter().rev() {
+            instruction = if *modifier == "DAGGER" {
+                dagger(instruction)
+                    .ok_or_else(|| unsupported("DAGGER modifier on custom gates".to_owned()))?
+            } else {
+                let control = *control_qubits.next().unwrap();
+
+                match instruction {
+                    Instruction::ControlledU {
+                        gate,
+                        control: control2,
+                        target,
+                    } if *gate == Instruction::PauliX(target) => Instruction::Toffoli {
+                        control1: control,
+                        control2,
+                        target,
+                    },
+                    Instruction::ControlledNot {
+                        control: control2,
+                        target,
+                    } => Instruction::Toffoli {
+                        control1: control,
+                        control2,
+                        target,
+                    },
+                    instruction if instruction.qubits().len() == 1 => Instruction::ControlledU {
+                        target: instruction.qubits()[0],
+                        gate: Box::new(instruction),
+                        control,
+                    },
+                    _ => {
+                        return Err(unsupported(
+                            "CONTROLLED modifier on multi-qubit gates".to_owned(),
+                        ))
+                    }
+                }
+            };
+        }
+
+        Ok(instruction)
+    }
+
+    fn gate(
+        &self,
+        line: usize,
+        name: &str,
+        parameters: &[f64],
+        qubits: &[usize],
+    ) -> Result<Instruction, QuilError> {
+        let expected = match name {
+            "I" | "X" | "Y" | "Z" | "H" | "S" | "T" => (0, 1),
+            "PHASE" | "RX" | "RY" | "RZ" => (1, 1),
+            "CNOT" | "SWAP" => (0, 2),
+            "CCNOT" => (0, 3),
+            name => match self.definition(name) {
+                Some(circuit) => (0, circuit.qubits()),
+                None if RESERVED_NAMES.contains(&name) => {
+                    return Err(QuilError::Unsupported {
+                        line,
+                        feature: format!("{} gate", name),
+                    })
+                }
+                None => {
+                    return Err(QuilError::Syntax {
+                        line,
+                        message: format!("Unknown gate {:?}", name),
+                    })
+                }
+            },
+        };
+
+        if (parameters.len(), qubits.len()) != expected {
+            return Err(QuilError::Syntax {
+                line,
+                message: format!(
+                    "Gate {} expects {} parameters and {} qubits",
+                    name, expected.0, expected.1
+                ),
+            });
+        }
+
+        Ok(match name {
+            "I" => Instruction::Identity(qubits[0]),
+            "X" => Instruction::PauliX(qubits[0]),
+            "Y" => Instruction::PauliY(qubits[0]),
+            "Z" => Instruction::PauliZ(qubits[0]),
+            "H" => Instruction::Hadamard(qubits[0]),
+            "S" => Instruction::S(qubits[0]),
+            "T" => Instruction::T(qubits[0]),
+            "PHASE" => Instruction::Phase {
+                qubit: qubits[0],
+                phase: parameters[0],
+            },
+            "RX" => Instruction::RotationX {
+                qubit: qubits[0],
+                phase: parameters[0],
+            },
+            "RY" => Instruction::RotationY {
+                qubit: qubits[0],
+                phase: parameters[0],
+            },
+            "RZ" => Instruction::RotationZ {
+                qubit: qubits[0],
+                phase: parameters[0],
+            },
+            "CNOT" => Instruction::ControlledNot {
+                control: qubits[0],
+                target: qubits[1],
+            },
+            "SWAP" => Instruction::Swap(qubits[0], qubits[1]),
+            "CCNOT" => Instruction::Toffoli {
+                control1: qubits[0],
+                control2: qubits[1],
+                target: qubits[2],
+            },
+            name => Instruction::Custom {
+                name: name.to_owned(),
+                circuit: self.definition(name).unwrap().clone(),
+                input_qubits: qubits.to_vec(),
+            },
+        })
+    }
+}
+
+/// Returns the inverse of the gate, if it can be represented as an instruction.
+fn dagger(instruction: Instruction) -> Option<Instruction> {
+    Some(match instruction {
+        Instruction::S(qubit) => Instruction::SDagger(qubit),
+        Instruction::SDagger(qubit) => Instruction::S(qubit),
+        Instruction::T(qubit) => Instruction::TDagger(qubit),
+        Instruction::TDagger(qubit) => Instruction::T(qubit),
+        Instruction::Phase { qubit, phase } => Instruction::PhaseDagger { qubit, phase },
+        Instruction::PhaseDagger { qubit, phase } => Instruction::Phase { qubit, phase },
+        Instruction::RotationX { qubit, phase } => Instruction::RotationX {
+            qubit,
+            phase: -phase,
+        },
+        Instruction::RotationY { qubit, phase } => Instruction::RotationY {
+            qubit,
+            phase: -phase,
+        },
+        Instruction::RotationZ { qubit, phase } => Instruction::RotationZ {
+            qubit,
+            phase: -phase,
+        },
+        Instruction::ControlledU {
+            gate,
+            control,
+            target,
+        } => Instruction::ControlledU {
+            gate: Box::new(dagger(*gate)?),
+            control,
+            target,
+        },
+        Instruction::Custom { .. } | Instruction::Measure { .. } | Instruction::Barrier(..) => {
+            return None
+        }
+        // The rest of the gates are inverses of themselves.
+        instruction => instruction,
+    })
+}
+
+/// Size of `BIT` or `BIT[size]` memory region, `None` for other types of memory.
+fn parse_memory_type(kind: &str) -> Option<usize> {
+    match kind {
+        "BIT" => Some(1),
+        kind => kind.strip_prefix("BIT[")?.strip_suffix(']')?.parse().ok(),
+    }
+}
+
+fn parse_qubit(line: usize, qubit: &str) -> Result<usize, QuilError> {
+    qubit.parse().map_err(|_| QuilError::Syntax {
+        line,
+        message: format!("Invalid qubit {:?}", qubit),
+    })
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn is_label(label: &str) -> bool {
+    label.strip_prefix('@').is_some_and(is_identifier)
+}
+
+/// Index of the parenthesis closing the one `text` starts with.
+fn matching_parenthesis(text: &str) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Splits the text by the separator, that is not inside of parentheses.
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0i32, 0);
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&text[start..]);
+    parts
+}
+
+fn is_unitary_matrix(matrix: &Matrix<Complex>) -> bool {
+    let product = matrix.dot_product(&matrix.hermitian_transpose());
+
+    (0..matrix.rows()).all(|row| {
+        (0..matrix.cols()).all(|col| {
+            let expected = if row == col { 1f64 } else { 0f64 };
+            let entry = product.get(row, col);
+
+            (entry.real - expected).abs() < 1e-9 && entry.imag.abs() < 1e-9
+        })
+    })
+}
+
+/// Angles `(theta, phi, lambda)`, such that the matrix is equal to
+/// `RZ(phi) * RY(theta) * RZ(lambda)` up to the global phase.
+fn zyz_angles(matrix: &Matrix<Complex>) -> (f64, f64, f64) {
+    let (a, b) = (matrix.get(0, 0), matrix.get(1, 0));
+    let (c, d) = (matrix.get(0, 1), matrix.get(1, 1));
+
+    // The product is [[e^(-i(phi+lambda)/2) cos, -e^(-i(phi-lambda)/2) sin],
+    // [e^(i(phi-lambda)/2) sin, e^(i(phi+lambda)/2) cos]] (where cos and sin are of
+    // theta/2), so only phases of the entries relative to each other matter.
+    let theta = 2f64 * b.norm().atan2(a.norm());
+    let argument = |z: Complex| z.imag.atan2(z.real);
+
+    let (sum, difference) = if a.norm() < 1e-12 {
+        (0f64, argument(b) - argument(-c))
+    } else if b.norm() < 1e-12 {
+        (argument(d) - argument(a), 0f64)
+    } else {
+        (argument(d) - argument(a), argument(b) - argument(-c))
+    };
+
+    (theta, (sum + difference) / 2f64, (sum - difference) / 2f64)
+}
+
+/// Evaluates arithmetic expression used as gate parameter or matrix entry.
+fn evaluate(line: usize, expression: &str) -> Result<Complex, QuilError> {
+    let mut evaluator = Evaluator {
+        line,
+        chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+        position: 0,
+    };
+
+    let value = evaluator.expression()?;
+    if evaluator.position != evaluator.chars.len() {
+        return Err(evaluator.error(format!("Invalid expression {:?}", expression.trim())));
+    }
+
+    Ok(value)
+}
+
+struct Evaluator {
+    line: usize,
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Evaluator {
+    fn error(&self, message: String) -> QuilError {
+        QuilError::Syntax {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn expression(&mut self) -> Result<Complex, QuilError> {
+        let mut value = self.term()?;
+
+        while let Some(operator @ ('+' | '-')) = self.peek() {
+            self.position += 1;
+            let rhs = self.term()?;
+
+            value = if operator == '+' {
+                value + rhs
+            } else {
+                value - rhs
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<Complex, QuilError> {
+        let mut value = self.unary()?;
+
+        while let Some(operator @ ('*' | '/')) = self.peek() {
+            self.position += 1;
+            let rhs = self.unary()?;
+
+            value = if operator == '*' {
+                value * rhs
+            } else {
+                divide(value, rhs).ok_or_else(|| self.error("Division by zero".to_owned()))?
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<Complex, QuilError> {
+        match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                Ok(-self.unary()?)
+            }
+            Some('+') => {
+                self.position += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Complex, QuilError> {
+        let base = self.atom()?;
+
+        if self.peek() != Some('^') {
+            return Ok(base);
+        }
+
+        self.position += 1;
+        let exponent = self.unary()?;
+        if base.imag != 0f64 || exponent.imag != 0f64 {
+            return Err(self.error("Only real numbers can be raised to a power".to_owned()));
+        }
+
+        Ok(base.real.powf(exponent.real).into())
+    }
+
+    fn atom(&mut self) -> Result<Complex, QuilError> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let value = self.expression()?;
+
+                if self.peek() != Some(')') {
+                    return Err(self.error("Unbalanced parentheses".to_owned()));
+                }
+
+                self.position += 1;
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.identifier(),
+            Some('%') => Err(QuilError::Unsupported {
+                line: self.line,
+                feature: "Gate parameters".to_owned(),
+            }),
+            _ => Err(self.error("Expected a number".to_owned())),
+        }
+    }
+
+    fn number(&mut self) -> Result<Complex, QuilError> {
+        let start = self.position;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || c == '.') {
+            self.position += 1;
+        }
+
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let exponent = match self.chars.get(self.position + 1) {
+                Some('+' | '-') => self.position + 2,
+                _ => self.position + 1,
+            };
+
+            if matches!(self.chars.get(exponent), Some(c) if c.is_ascii_digit()) {
+                self.position = exponent;
+                while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+                    self.position += 1;
+                }
+            }
+        }
+
+        let text: String = self.chars[start..self.position].iter().collect();
+        let value: f64 = text
+            .parse()
+            .map_err(|_| self.error(format!("Invalid number {:?}", text)))?;
+
+        if self.peek() == Some('i') {
+            self.position += 1;
+            return Ok(Complex::new(0, value));
+        }
+
+        Ok(value.into())
+    }
+
+    fn identifier(&mut self) -> Result<Complex, QuilError> {
+        let start = self.position;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_') {
+            self.position += 1;
+        }
+
+        let name: String = self.chars[start..self.position].iter().collect();
+        match name.as_str() {
+            "pi" => return Ok(PI.into()),
+            "i" => return Ok(Complex::i()),
+            _ => {}
+        }
+
+        if self.peek() == Some('[') {
+            return Err(QuilError::Unsupported {
+                line: self.line,
+                feature: "Memory references in expressions".to_owned(),
+            });
+        }
+
+        if self.peek() != Some('(') {
+            return Err(self.error(format!("Unknown identifier {:?}", name)));
+        }
+
+        let argument = self.atom()?;
+        match name.as_str() {
+            "exp" => Ok(Complex::new_from_polar(argument.real.exp(), argument.imag)),
+            "sqrt" => Ok(Complex::new_from_polar(
+                argument.norm().sqrt(),
+                argument.imag.atan2(argument.real) / 2f64,
+            )),
+            "sin" | "cos" | "cis" if argument.imag != 0f64 => {
+                Err(self.error(format!("Argument of {} must be real", name)))
+            }
+            "sin" => Ok(argument.real.sin().into()),
+            "cos" => Ok(argument.real.cos().into()),
+            "cis" => Ok(Complex::new_from_polar(1, argument.real)),
+            _ => Err(self.error(format!("Unknown function {:?}", name))),
+        }
+    }
+}
+
+fn divide(lhs: Complex, rhs: Complex) -> Option<Complex> {
+    if rhs.is_zero() {
+        return None;
+    }
+
+    if rhs.imag == 0f64 {
+        return Some(Complex::new(lhs.real / rhs.real, lhs.imag / rhs.real));
+    }
+
+    let denominator = rhs.real * rhs.real + rhs.imag * rhs.imag;
+    Some(lhs * rhs.conjugate() * Complex::new(1f64 / denominator, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        complex::Complex,
+        quantum_circuit::{Instruction, QuantumCircuit},
+        quil::{from_quil, to_quil, QuilError},
+        statevector_backend::unitary,
+    };
+    use std::f64::consts::PI;
+
+    fn assert_same_unitary(actual: &QuantumCircuit, expected: &QuantumCircuit) {
+        let (actual, expected) = (unitary(actual), unitary(expected));
+
+        for row in 0..expected.rows() {
+            for col in 0..expected.cols() {
+                let difference = actual.get(row, col) - expected.get(row, col);
+                assert!(difference.norm() < 1e-12, "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_standard_gates() {
+        let mut circuit = circuit!(4, 3;
+            id 0; x 1; y 2; z 3; h 0; s 1; sdg 2; t 3; tdg 0;
+            p(pi/4) 1; pdg(0.125) 2; rx(-pi/2) 3; ry(3*pi/4) 0; rz(1e-3) 1;
+            cx 0 1; ccx 0 1 2; swap 2 3;
+            barrier; barrier 0 2;
+            measure 0 -> 2; measure 3 -> 0; reset 1;
+        );
+        circuit.cu(Instruction::SDagger(3), 1, 3);
+
+        let program = to_quil(&circuit).unwrap();
+        assert!(program.contains("\nDAGGER PHASE(0.125) 2\nRX(-pi/2) 3\nRY(3*pi/4) 0\n"));
+        assert!(program.contains("\nFENCE 0 1 2 3\nFENCE 0 2\n"));
+        assert!(program.contains("\nMEASURE 3 ro[0]\nRESET 1\n"));
+        assert!(program.ends_with("\nCONTROLLED DAGGER S 1 3\n"));
+        assert_eq!(from_quil(&program), Ok(circuit));
+    }
+
+    #[test]
+    fn round_trips_classical_registers() {
+        let mut circuit = QuantumCircuit::with_clbits(2, 1);
+        let syndrome = circuit.add_creg("syndrome", 2);
+        circuit.measure(0, 0).measure(0..2, &syndrome);
+
+        let program = to_quil(&circuit).unwrap();
+        assert!(program.starts_with("DECLARE ro BIT[1]\nDECLARE syndrome BIT[2]\n"));
+        assert!(program.ends_with("\nMEASURE 1 syndrome[1]\n"));
+        assert_eq!(from_quil(&program), Ok(circuit.clone()));
+
+        circuit.c_if(syndrome.at(1), 1, Instruction::PauliX(0));
+        let program = to_quil(&circuit).unwrap();
+        assert!(program.contains("\nJUMP-UNLESS @skip0 syndrome[1]\n"));
+        assert_eq!(from_quil(&program), Ok(circuit));
+
+        let circuit = from_quil("DECLARE b BIT[2]\nDECLARE a BIT\nMEASURE 0 a").unwrap();
+        assert_eq!(
+            circuit.instructions()[0],
+            Instruction::Measure { qubit: 0, clbit: 2 }
+        );
+        assert_eq!(circuit.creg("a").map(|register| register.offset()), Some(2));
+
+        let mut clashing = QuantumCircuit::with_clbits(1, 1);
+        clashing.add_creg("ro", 1);
+        assert!(matches!(
+            to_quil(&clashing),
+            Err(QuilError::NotExportable(..))
+        ));
+    }
+
+    #[test]
+    fn exports_custom_gates_as_defgate() {
+        let mut cnot = QuantumCircuit::new(2);
+        cnot.cx(0, 1);
+
+        let mut circuit = QuantumCircuit::new(3);
+        circuit
+            .custom("H", circuit!(1; h 0), [1])
+            .custom("my h", circuit!(1; h 0), [2])
+            .custom("H", circuit!(1; h 0), [0]);
+
+        let program = to_quil(&circuit).unwrap();
+        assert_eq!(
+            program,
+            "DEFGATE H_2:\n\
+            \x20   0.7071067811865475, 0.7071067811865475\n\
+            \x20   0.7071067811865475, -0.7071067811865475\n\
+            DEFGATE my_h:\n\
+            \x20   0.7071067811865475, 0.7071067811865475\n\
+            \x20   0.7071067811865475, -0.7071067811865475\n\
+            H_2 1\n\
+            my_h 2\n\
+            H_2 0\n"
+        );
+        assert_same_unitary(&from_quil(&program).unwrap(), &circuit);
+
+        let mut circuit = QuantumCircuit::new(3);
+        circuit.custom("my cnot", cnot, [2, 0]);
+        assert!(matches!(
+            to_quil(&circuit),
+            Err(QuilError::NotExportable(..))
+        ));
+
+        let mut measured = QuantumCircuit::with_clbits(1, 1);
+        measured.measure(0, 0);
+        let mut circuit = QuantumCircuit::with_clbits(1, 1);
+        circuit.custom("measure", measured, 0);
+        assert!(matches!(
+            to_quil(&circuit),
+            Err(QuilError::NotExportable(..))
+        ));
+    }
+
+    #[test]
+    fn exports_conditioned_instructions() {
+        let mut circuit = QuantumCircuit::with_clbits(2, 2);
+        circuit
+            .measure(0..2, 0..2)
+            .c_if(0..2, 1, Instruction::PauliX(1))
+            .c_if(1, 0, Instruction::Measure { qubit: 0, clbit: 1 });
+
+        assert_eq!(
+            to_quil(&circuit).unwrap(),
+            "DECLARE ro BIT[2]\n\
+            MEASURE 0 ro[0]\n\
+            MEASURE 1 ro[1]\n\
+            JUMP-UNLESS @skip0 ro[0]\n\
+            JUMP-WHEN @skip0 ro[1]\n\
+            X 1\n\
+            LABEL @skip0\n\
+            JUMP-WHEN @skip1 ro[1]\n\
+            MEASURE 0 ro[1]\n\
+            LABEL @skip1\n"
+        );
+        assert_eq!(from_quil(&to_quil(&circuit).unwrap()), Ok(circuit));
+    }
+
+    #[test]
+    fn imports_jumps_over_instructions() {
+        let circuit = from_quil(
+            "DECLARE ro BIT[2]\n\
+            JUMP-UNLESS @outer ro[1]\n\
+            H 0; MEASURE 0 ro\n\
+            JUMP-WHEN @inner ro[0]; JUMP-UNLESS @inner ro[1]\n\
+            LABEL @inner\n\
+            LABEL @outer\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            circuit.instructions(),
+            &vec![
+                Instruction::If {
+                    register: vec![1],
+                    value: 1,
+                    instruction: Box::new(Instruction::Hadamard(0)),
+                },
+                Instruction::If {
+                    register: vec![1],
+                    value: 1,
+                    instruction: Box::new(Instruction::Measure { qubit: 0, clbit: 0 }),
+                },
+            ]
+        );
+
+        let circuit = from_quil(
+            "DECLARE ro BIT[2]\n\
+            JUMP-WHEN @a ro[0]\n\
+            JUMP-UNLESS @b ro[1]\n\
+            X 0\n\
+            LABEL @b\n\
+            LABEL @a\n",
+        )
+        .unwrap();
+        assert_eq!(
+            circuit.instructions(),
+            &vec![Instruction::If {
+                register: vec![0],
+                value: 0,
+                instruction: Box::new(Instruction::If {
+                    register: vec![1],
+                    value: 1,
+                    instruction: Box::new(Instruction::PauliX(0)),
+                }),
+            }]
+        );
+    }
+
+    #[test]
+    fn imports_single_qubit_defgate() {
+        let circuit = from_quil(
+            "DEFGATE U AS MATRIX:\n\
+            \x20   1/sqrt(2), -i/sqrt(2)\n\
+            \x20   -i/sqrt(2), 1/sqrt(2)\n\
+            \n\
+            X 0; U 0 # comment\n\
+            CONTROLLED U 1 0\n",
+        )
+        .unwrap();
+
+        let Instruction::Custom { circuit: inner, .. } = &circuit.instructions()[1] else {
+            panic!("U is not imported as custom gate");
+        };
+        assert_same_unitary(inner, &circuit!(1; rx(pi/2) 0));
+
+        assert!(matches!(
+            &circuit.instructions()[2],
+            Instruction::ControlledU { gate, control: 1, target: 0 }
+                if matches!(**gate, Instruction::Custom { .. })
+        ));
+    }
+
+    #[test]
+    fn keeps_global_phase_of_controlled_defgate() {
+        let circuit = from_quil(
+            "DEFGATE MYS:\n\
+            \x20   1, 0\n\
+            \x20   0, i\n\
+            H 0; H 1; CONTROLLED MYS 0 1\n",
+        )
+        .unwrap();
+
+        let mut expected = QuantumCircuit::new(2);
+        expected.h(0).h(1).cu(Instruction::S(1), 0, 1);
+        assert_same_unitary(&circuit, &expected);
+    }
+
+    #[test]
+    fn parses_modifiers_and_expressions() {
+        let circuit = from_quil(
+            "CONTROLLED CONTROLLED X 2 0 1\n\
+            CONTROLLED CNOT 0 1 2\n\
+            DAGGER DAGGER T 0\n\
+            DAGGER RZ(-(pi - 1) * 2^2 / 4) 1\n\
+            PHASE(cis(pi) + 1) 2\n\
+            FENCE\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            circuit.instructions(),
+            &vec![
+                Instruction::Toffoli {
+                    control1: 2,
+                    control2: 0,
+                    target: 1
+                },
+                Instruction::Toffoli {
+                    control1: 0,
+                    control2: 1,
+                    target: 2
+                },
+                Instruction::T(0),
+                Instruction::RotationZ {
+                    qubit: 1,
+                    phase: PI - 1f64
+                },
+                Instruction::Phase {
+                    qubit: 2,
+                    phase: (Complex::new_from_polar(1, PI) + Complex::from(1)).real
+                },
+                Instruction::Barrier(vec![0, 1, 2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unsupported_features() {
+        let unsupported = |program: &str| match from_quil(program) {
+            Err(QuilError::Unsupported { line, .. }) => line,
+            result => panic!("{:?} is not an unsupported feature error", result),
+        };
+
+        assert_eq!(unsupported("H 0\nRESET"), 2);
+        assert_eq!(unsupported("DECLARE theta REAL[1]"), 1);
+        assert_eq!(unsupported("CZ 0 1"), 1);
+        assert_eq!(unsupported("X 0\n\nRX(%theta) 0"), 3);
+        assert_eq!(unsupported("DECLARE ro BIT\nMEASURE 0"), 2);
+        assert_eq!(unsupported("DEFGATE G(%a):\n    cos(%a), 0\n    0, 1"), 1);
+        assert_eq!(unsupported("X 0\nJUMP @end\nX 0\nLABEL @end"), 2);
+        assert_eq!(unsupported("LABEL @start\nX 0"), 1);
+        assert_eq!(unsupported("DECLARE ro BIT\nJUMP-WHEN @end ro\nX 0"), 2);
+        assert_eq!(
+            unsupported("DECLARE ro BIT\nJUMP-WHEN @end ro\nMEASURE 0 ro\nX 0\nLABEL @end"),
+            4
+        );
+        assert_eq!(
+            unsupported(
+                "DECLARE ro BIT\nJUMP-WHEN @end ro\nX 0\nLABEL @end\nJUMP-WHEN @end ro\nX 0"
+            ),
+            5
+        );
+        assert_eq!(
+            unsupported(
+                "DEFGATE G:\n    1, 0, 0, 0\n    0, 1, 0, 0\n    0, 0, 0, 1\n    0, 0, 1, 0"
+            ),
+            1
+        );
+
+        assert!(matches!(
+            from_quil("FOO 0"),
+            Err(QuilError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            from_quil("H 0\nRX(pi 0"),
+            Err(QuilError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            from_quil("CNOT 1 1"),
+            Err(QuilError::InvalidInstruction { line: 1, .. })
+        ));
+        assert!(matches!(
+            from_quil("DECLARE ro BIT[1]\nMEASURE 0 ro[1]"),
+            Err(QuilError::InvalidInstruction { line: 2, .. })
+        ));
+        assert!(matches!(
+            from_quil("X 18446744073709551615"),
+            Err(QuilError::InvalidInstruction { line: 1, .. })
+        ));
+        assert!(matches!(
+            from_quil("DECLARE a BIT[18446744073709551615]\nDECLARE b BIT[1]"),
+            Err(QuilError::InvalidInstruction { line: 2, .. })
+        ));
+    }
+}
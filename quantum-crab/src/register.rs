@@ -0,0 +1,110 @@
+//! Implements named registers of qubits and classical bits.
+//!
+//! Registers are declared with [`QuantumCircuit::add_qreg`](crate::quantum_circuit::QuantumCircuit::add_qreg) and
+//! [`QuantumCircuit::add_creg`](crate::quantum_circuit::QuantumCircuit::add_creg), just like `qreg` and `creg` declarations of
+//! OpenQASM. Every register is a named range of consecutive qubits (or classical
+//! bits) appended to the circuit, so instructions keep referring to flat indices,
+//! which are obtained from the register:
+//!
+//! ```
+//! use quantum_crab::{
+//!     quantum_circuit::QuantumCircuit, stabilizer_backend::StabilizerBackend,
+//!     backend::{Backend, RunOptions},
+//! };
+//!
+//! let mut circuit = QuantumCircuit::new(0);
+//! let data = circuit.add_qreg("data", 3);
+//! let syndrome = circuit.add_creg("syndrome", 2);
+//! let result = circuit.add_creg("result", 3);
+//!
+//! circuit.x(data.at(1));
+//! circuit.measure(&data, &result);
+//! circuit.cx(data.at(0), data.at(1)).measure(data.at(1), syndrome.at(0));
+//!
+//! let clbits = StabilizerBackend.run(&circuit, &RunOptions::default()).unwrap();
+//! let registers = circuit.creg_values(&clbits);
+//! assert_eq!(registers["result"].value(), 2);
+//! assert_eq!(registers["syndrome"].value(), 1);
+//! ```
+//!
+//! Register names follow OpenQASM identifiers: they start with a lowercase letter,
+//! followed by letters, digits and underscores, and are unique within the circuit.
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Named range of qubits or classical bits of the circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Register {
+    name: String,
+    offset: usize,
+    size: usize,
+}
+
+impl Register {
+    /// Constructs register of `size` bits, starting from the bit `offset` of
+    /// the circuit.
+    pub(crate) fn new(name: &str, offset: usize, size: usize) -> Register {
+        Register {
+            name: name.to_owned(),
+            offset,
+            size,
+        }
+    }
+
+    /// Name of the register.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Index of the first qubit (or classical bit) of the register in the circuit.
+    #[inline]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Amount of qubits (or classical bits) in the register.
+    #[inline]
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Index of the qubit (or classical bit) `index` of the register in the circuit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of the register.
+    pub fn at(&self, index: usize) -> usize {
+        assert!(
+            index < self.size,
+            "Index {} is out of register {}[{}]",
+            index,
+            self.name,
+            self.size
+        );
+
+        self.offset + index
+    }
+
+    /// Indices of all qubits (or classical bits) of the register in the circuit.
+    #[inline]
+    pub const fn indices(&self) -> Range<usize> {
+        self.offset..self.offset + self.size
+    }
+
+    /// Returns index within the register of the qubit (or classical bit) `index`
+    /// of the circuit, if the register contains it.
+    pub fn position(&self, index: usize) -> Option<usize> {
+        self.indices().contains(&index).then(|| index - self.offset)
+    }
+}
+
+/// Checks that the name is a valid OpenQASM identifier.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
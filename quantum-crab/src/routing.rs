@@ -0,0 +1,300 @@
+//! Implements routing pass, that makes circuits executable on devices with limited
+//! connectivity.
+//!
+//! Routing maps logical qubits of the circuit into physical qubits of the device and
+//! inserts [`Instruction::Swap`] gates, so that every two-qubit gate acts on qubits
+//! coupled in the [`CouplingMap`]. As swaps move logical qubits around, the layout at
+//! the end of the circuit generally differs from the initial one.
+use crate::{
+    circuit_dag::CircuitDag,
+    coupling_map::CouplingMap,
+    layout::Layout,
+    quantum_circuit::{Instruction, QuantumCircuit},
+};
+
+/// Result of the routing pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutedCircuit {
+    /// Circuit acting on physical qubits of the device.
+    pub circuit: QuantumCircuit,
+    /// Mapping of logical qubits into physical ones at the beginning of the circuit.
+    pub initial_layout: Layout,
+    /// Mapping of logical qubits into physical ones at the end of the circuit.
+    pub final_layout: Layout,
+    /// Amount of inserted swap gates.
+    pub swaps: usize,
+}
+
+/// Routes the circuit, starting from the layout recorded on the circuit (see
+/// [`QuantumCircuit::layout`]). If there is no layout, logical qubit `i` is mapped
+/// into physical qubit `i` at the beginning.
+///
+/// ```
+/// use quantum_crab::{
+///   coupling_map::CouplingMap,
+///   routing::route,
+///   quantum_circuit::{QuantumCircuit, Instruction}
+/// };
+///
+/// let mut circuit = QuantumCircuit::new(3);
+/// circuit.add(Instruction::ControlledNot { control: 0, target: 2 });
+///
+/// let routed = route(&circuit, &CouplingMap::line(3));
+/// assert_eq!(
+///     routed.circuit.instructions(),
+///     &vec![
+///         Instruction::Swap(0, 1),
+///         Instruction::ControlledNot { control: 1, target: 2 },
+///     ]
+/// );
+/// assert_eq!(routed.final_layout.as_slice(), &[1, 0, 2]);
+/// ```
+pub fn route(circuit: &QuantumCircuit, coupling_map: &CouplingMap) -> RoutedCircuit {
+    match circuit.layout() {
+        Some(layout) => route_with_layout(circuit, coupling_map, layout),
+        None => route_with_layout(
+            circuit,
+            coupling_map,
+            &Layout::trivial(circuit.qubits(), coupling_map.qubits()),
+        ),
+    }
+}
+
+/// Routes the circuit, starting from the given layout.
+///
+/// Custom gates are flattened and Toffoli gates (including conditioned ones) are
+/// decomposed into one- and two-qubit gates before routing. Whenever none of the instructions that can be executed next
+/// acts on coupled qubits, swaps are inserted along the shortest path between the
+/// closest pair of qubits.
+pub fn route_with_layout(
+    circuit: &QuantumCircuit,
+    coupling_map: &CouplingMap,
+    initial_layout: &Layout,
+) -> RoutedCircuit {
+    assert_eq!(
+        initial_layout.logical_qubits(),
+        circuit.qubits(),
+        "Layout doesn't match the amount of qubits in the circuit"
+    );
+    assert_eq!(
+        initial_layout.physical_qubits(),
+        coupling_map.qubits(),
+        "Layout doesn't match the amount of qubits in the coupling map"
+    );
+
+    let mut dag = CircuitDag::from_circuit(&decompose_toffoli_gates(&circuit.flatten()));
+    let mut layout = initial_layout.clone();
+    let mut routed = QuantumCircuit::with_clbits(coupling_map.qubits(), circuit.clbits());
+    // Quantum registers don't survive mapping onto physical qubits.
+    routed
+        .set_registers(Vec::new(), circuit.cregs().to_vec())
+        .unwrap();
+    let mut swaps = 0;
+
+    loop {
+        let front_layer = dag.front_layer();
+        if front_layer.is_empty() {
+            break;
+        }
+
+        let mut executed = false;
+        for index in &front_layer {
+            let instruction = dag.node(*index).unwrap().instruction();
+
+            if is_executable(instruction, &layout, coupling_map) {
+                routed.add(instruction.map_qubits(|qubit| layout.physical(qubit)));
+                dag.remove_node(*index);
+                executed = true;
+            }
+        }
+
+        if executed {
+            continue;
+        }
+
+        let path = front_layer
+            .iter()
+            .map(|index| {
+                let qubits = dag.node(*index).unwrap().instruction().qubits();
+                let (physical1, physical2) =
+                    (layout.physical(qubits[0]), layout.physical(qubits[1]));
+
+                coupling_map
+                    .shortest_path(physical1, physical2)
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "Physical qubits {} and {} are not connected in the coupling map",
+                            physical1, physical2
+                        )
+                    })
+            })
+            .min_by_key(|path| path.len())
+            .unwrap();
+
+        for pair in path[..path.len() - 1].windows(2) {
+            routed.add(Instruction::Swap(pair[0], pair[1]));
+            layout.swap_physical(pair[0], pair[1]);
+            swaps += 1;
+        }
+    }
+
+    RoutedCircuit {
+        circuit: routed,
+        initial_layout: initial_layout.clone(),
+        final_layout: layout,
+        swaps,
+    }
+}
+
+/// Checks if the instruction acts on coupled physical qubits (or on a single qubit).
+fn is_executable(instruction: &Instruction, layout: &Layout, coupling_map: &CouplingMap) -> bool {
+    if let Instruction::Barrier(..) = instruction {
+        return true;
+    }
+
+    match instruction.qubits().as_slice() {
+        [] | [_] => true,
+        [qubit1, qubit2] => {
+            coupling_map.are_coupled(layout.physical(*qubit1), layout.physical(*qubit2))
+        }
+        // Such instructions are decomposed before routing.
+        _ => panic!(
+            "Instruction acting on more than two qubits can't be routed: {:?}",
+            instruction
+        ),
+    }
+}
+
+/// Replaces every Toffoli gate in the circuit (conditioned or not) with an equivalent
+/// sequence of one- and two-qubit gates.
+fn decompose_toffoli_gates(circuit: &QuantumCircuit) -> QuantumCircuit {
+    let mut decomposed = QuantumCircuit::with_clbits(circuit.qubits(), circuit.clbits());
+
+    for instruction in circuit.instructions() {
+        match instruction.decompose_toffoli() {
+            Some(gates) => {
+                for gate in gates {
+                    decomposed.add(gate);
+                }
+            }
+            None => decomposed.add(instruction.clone()),
+        }
+    }
+
+    decomposed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        coupling_map::CouplingMap,
+        layout::Layout,
+        quantum_circuit::{Instruction, QuantumCircuit},
+        routing::{route, route_with_layout},
+    };
+
+    fn assert_coupled(circuit: &QuantumCircuit, coupling_map: &CouplingMap) {
+        for instruction in circuit.instructions() {
+            if let [qubit1, qubit2] = instruction.qubits().as_slice() {
+                assert!(
+                    coupling_map.are_coupled(*qubit1, *qubit2),
+                    "{:?} acts on uncoupled qubits",
+                    instruction
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn routes_on_ring() {
+        let mut circuit = QuantumCircuit::with_clbits(6, 1);
+        circuit.add(Instruction::Hadamard(0));
+        circuit.add(Instruction::ControlledNot {
+            control: 0,
+            target: 3,
+        });
+        circuit.add(Instruction::ControlledNot {
+            control: 5,
+            target: 0,
+        });
+        circuit.add(Instruction::Measure { qubit: 3, clbit: 0 });
+
+        let coupling_map = CouplingMap::ring(6);
+        let routed = route(&circuit, &coupling_map);
+
+        assert_coupled(&routed.circuit, &coupling_map);
+        assert_eq!(routed.swaps, 4);
+        assert_eq!(routed.circuit.instructions().len(), 8);
+        assert!(routed
+            .circuit
+            .instructions()
+            .contains(&Instruction::Measure { qubit: 3, clbit: 0 }));
+        assert_eq!(routed.final_layout.as_slice(), &[2, 5, 0, 3, 4, 1]);
+    }
+
+    #[test]
+    fn routes_toffoli_and_custom_gates_on_grid() {
+        let mut inner = QuantumCircuit::new(3);
+        inner.add(Instruction::Toffoli {
+            control1: 0,
+            control2: 1,
+            target: 2,
+        });
+
+        let mut circuit = QuantumCircuit::new(4);
+        circuit.add(Instruction::Custom {
+            name: "ccx".to_owned(),
+            circuit: inner,
+            input_qubits: vec![0, 3, 1],
+        });
+        circuit.add(Instruction::Swap(0, 3));
+
+        let coupling_map = CouplingMap::grid(2, 3);
+        let routed = route_with_layout(&circuit, &coupling_map, &Layout::new(vec![0, 2, 4, 5], 6));
+
+        assert_coupled(&routed.circuit, &coupling_map);
+        assert_eq!(routed.initial_layout.as_slice(), &[0, 2, 4, 5]);
+        assert!(routed
+            .circuit
+            .instructions()
+            .iter()
+            .all(|instruction| instruction.qubits().len() <= 2));
+    }
+
+    #[test]
+    fn routes_conditioned_toffoli_gates() {
+        let mut circuit = QuantumCircuit::with_clbits(3, 1);
+        circuit.measure(1, 0).c_if(
+            0,
+            1,
+            Instruction::Toffoli {
+                control1: 0,
+                control2: 2,
+                target: 1,
+            },
+        );
+
+        let coupling_map = CouplingMap::line(3);
+        let routed = route(&circuit, &coupling_map);
+
+        assert_coupled(&routed.circuit, &coupling_map);
+        assert!(routed.swaps > 0);
+        assert!(routed.circuit.instructions()[1..]
+            .iter()
+            .all(|instruction| {
+                matches!(instruction, Instruction::If { .. } | Instruction::Swap(..))
+            }));
+    }
+
+    #[test]
+    #[should_panic(expected = "not connected")]
+    fn panics_on_disconnected_coupling_map() {
+        let mut circuit = QuantumCircuit::new(2);
+        circuit.add(Instruction::ControlledNot {
+            control: 0,
+            target: 1,
+        });
+
+        route(&circuit, &CouplingMap::new(2));
+    }
+}
@@ -0,0 +1,339 @@
+//! Implements sparse state vector backend, that stores only nonzero amplitudes.
+//!
+//! Amplitudes are kept in a hash map keyed by the index of the basis state (where
+//! qubit 0 is the least significant bit), and amplitudes, that become negligible,
+//! are pruned. This makes it possible to simulate wide circuits (up to 128 qubits),
+//! which keep only a few basis states, for example reversible classical circuits
+//! built from [`Instruction::PauliX`], [`Instruction::ControlledNot`],
+//! [`Instruction::Toffoli`] and [`Instruction::Swap`] gates.
+//!
+//! ```
+//! use quantum_crab::{
+//!     backend::Backend,
+//!     quantum_circuit::QuantumCircuit,
+//!     sparse_backend::SparseBackend,
+//! };
+//!
+//! let mut circuit = QuantumCircuit::new(100);
+//! circuit.x(0).h(1).cx(0, 99).ccx(0, 99, 50);
+//!
+//! let state = SparseBackend::execute(circuit);
+//! assert_eq!(state.len(), 2);
+//! assert!((state.amplitude(1 | 1 << 50 | 1 << 99).real - 0.5f64.sqrt()).abs() < 1e-12);
+//! ```
+use crate::{
+    backend::{check_instructions, Backend, BackendError, CheckedInitialState, RunOptions},
+    complex::Complex,
+    matrix::Matrix,
+    quantum_circuit::{Instruction, QuantumCircuit},
+    state_preparation::state_preparation,
+    statevector_backend::single_qubit_gate_matrix,
+};
+use num::{One, Zero};
+use std::collections::{HashMap, HashSet};
+
+/// Sparse state vector backend with pruning settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseBackend {
+    /// Amplitudes with smaller norm are removed from the state.
+    pub prune_threshold: f64,
+}
+
+impl Default for SparseBackend {
+    fn default() -> SparseBackend {
+        SparseBackend {
+            prune_threshold: 1e-12,
+        }
+    }
+}
+
+impl Backend for SparseBackend {
+    type Output = SparseStateVector;
+
+    /// Executes the circuit with the backend settings. Circuits with more than 128
+    /// qubits, measurements, resets and conditioned instructions are not supported.
+    fn run(
+        &self,
+        circuit: &QuantumCircuit,
+        options: &RunOptions,
+    ) -> Result<SparseStateVector, BackendError> {
+        if circuit.qubits() > 128 {
+            return Err(BackendError::TooManyQubits {
+                qubits: circuit.qubits(),
+                max_qubits: 128,
+            });
+        }
+        check_instructions(circuit, |instruction| !instruction.is_dynamic())?;
+
+        let amplitudes = match options.initial_state(circuit.qubits())? {
+            CheckedInitialState::Basis(bits) => {
+                let index = bits
+                    .iter()
+                    .rev()
+                    .fold(0, |index, bit| index << 1 | *bit as u128);
+                HashMap::from([(index, Complex::one())])
+            }
+            CheckedInitialState::StateVector(state) => (0..)
+                .zip(state.amplitudes())
+                .filter(|(_, amplitude)| amplitude.norm() > self.prune_threshold)
+                .map(|(index, amplitude)| (index, *amplitude))
+                .collect(),
+        };
+        let mut state = SparseStateVector {
+            qubits: circuit.qubits(),
+            amplitudes,
+        };
+
+        for instruction in circuit.instructions() {
+            options.check_cancelled()?;
+            state.apply_instruction(instruction, 0, self.prune_threshold);
+        }
+
+        Ok(state)
+    }
+}
+
+/// State vector, that stores only nonzero amplitudes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseStateVector {
+    qubits: usize,
+    amplitudes: HashMap<u128, Complex>,
+}
+
+impl SparseStateVector {
+    /// Constructs state, where all qubits are `|0>`.
+    pub fn new(qubits: usize) -> SparseStateVector {
+        SparseStateVector {
+            qubits,
+            amplitudes: HashMap::from([(0, Complex::one())]),
+        }
+    }
+
+    /// Amount of qubits.
+    #[inline]
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+
+    /// Amount of stored (nonzero) amplitudes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.amplitudes.len()
+    }
+
+    /// Checks if there are no stored amplitudes.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.amplitudes.is_empty()
+    }
+
+    /// Returns amplitude of the basis state with the index.
+    pub fn amplitude(&self, index: u128) -> Complex {
+        self.amplitudes
+            .get(&index)
+            .copied()
+            .unwrap_or_else(Complex::zero)
+    }
+
+    /// Returns stored amplitudes sorted by the index of the basis state.
+    pub fn nonzero_amplitudes(&self) -> Vec<(u128, Complex)> {
+        let mut amplitudes: Vec<_> = self
+            .amplitudes
+            .iter()
+            .map(|(index, amplitude)| (*index, *amplitude))
+            .collect();
+        amplitudes.sort_by_key(|(index, _)| *index);
+        amplitudes
+    }
+
+    /// Applies the instruction for basis states, where all qubits in the `controls`
+    /// bit mask are `|1>`.
+    fn apply_instruction(&mut self, instruction: &Instruction, controls: u128, threshold: f64) {
+        let not = single_qubit_gate_matrix(&Instruction::PauliX(0));
+
+        match instruction {
+            Instruction::Identity(..) | Instruction::Barrier(..) => {}
+            Instruction::ControlledNot { control, target } => {
+                self.apply_single_qubit_gate(&not, controls | 1 << control, *target, threshold)
+            }
+            Instruction::ControlledU {
+                gate,
+                control,
+                target,
+            } => self.apply_instruction(
+                &gate.map_qubits(|_| *target),
+                controls | 1 << control,
+                threshold,
+            ),
+            Instruction::Toffoli {
+                control1,
+                control2,
+                target,
+            } => self.apply_single_qubit_gate(
+                &not,
+                controls | 1 << control1 | 1 << control2,
+                *target,
+                threshold,
+            ),
+            Instruction::Swap(qubit1, qubit2) => {
+                for (control, target) in [(qubit1, qubit2), (qubit2, qubit1), (qubit1, qubit2)] {
+                    self.apply_single_qubit_gate(&not, controls | 1 << control, *target, threshold);
+                }
+            }
+            Instruction::Custom {
+                circuit,
+                input_qubits,
+                ..
+            } => {
+                for instruction in circuit.instructions() {
+                    self.apply_instruction(
+                        &instruction.map_qubits(|qubit| input_qubits[qubit]),
+                        controls,
+                        threshold,
+                    );
+                }
+            }
+            Instruction::Initialize { qubits, amplitudes } => {
+                for instruction in state_preparation(amplitudes).instructions() {
+                    self.apply_instruction(
+                        &instruction.map_qubits(|qubit| qubits[qubit]),
+                        controls,
+                        threshold,
+                    );
+                }
+            }
+            Instruction::Measure { .. } | Instruction::Reset(..) | Instruction::If { .. } => {
+                unreachable!("Measurements are rejected by the backend")
+            }
+            _ => self.apply_single_qubit_gate(
+                &single_qubit_gate_matrix(instruction),
+                controls,
+                instruction.qubits()[0],
+                threshold,
+            ),
+        }
+    }
+
+    fn apply_single_qubit_gate(
+        &mut self,
+        gate_matrix: &Matrix<Complex>,
+        controls: u128,
+        target: usize,
+        threshold: f64,
+    ) {
+        let target_mask = 1u128 << target;
+        let (a, b, c, d) = (
+            gate_matrix.get(0, 0),
+            gate_matrix.get(0, 1),
+            gate_matrix.get(1, 0),
+            gate_matrix.get(1, 1),
+        );
+
+        // Basis states with the target qubit `|0>`, paired with ones where it is `|1>`.
+        let pairs: HashSet<u128> = self
+            .amplitudes
+            .keys()
+            .filter(|index| *index & controls == controls)
+            .map(|index| index & !target_mask)
+            .collect();
+
+        for i in pairs {
+            let j = i | target_mask;
+            let (amplitude0, amplitude1) = (self.amplitude(i), self.amplitude(j));
+
+            for (index, amplitude) in [
+                (i, a * amplitude0 + b * amplitude1),
+                (j, c * amplitude0 + d * amplitude1),
+            ] {
+                if amplitude.norm() > threshold {
+                    self.amplitudes.insert(index, amplitude);
+                } else {
+                    self.amplitudes.remove(&index);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{Backend, BackendError, RunOptions},
+        complex::Complex,
+        quantum_circuit::{Instruction, QuantumCircuit},
+        sparse_backend::{SparseBackend, SparseStateVector},
+        statevector_backend::StateVectorBackend,
+    };
+    use std::f64::consts::PI;
+
+    #[test]
+    fn matches_statevector_backend() {
+        let mut inner = QuantumCircuit::new(2);
+        inner.h(0).cx(0, 1);
+
+        let mut circuit = QuantumCircuit::new(4);
+        circuit
+            .h(0)
+            .ry(1, 0.7)
+            .cu(Instruction::PauliY(0), 0, 2)
+            .custom("bell", inner, [3, 1])
+            .ccx(0, 3, 2)
+            .swap(1, 2)
+            .p(3, PI / 5f64);
+
+        let state = SparseBackend::execute(circuit.clone());
+        let expected = StateVectorBackend::execute(circuit);
+
+        for index in 0..16 {
+            let (actual, expected) = (
+                state.amplitude(index),
+                expected.amplitudes()[index as usize],
+            );
+            assert!((actual.real - expected.real).abs() < 1e-12);
+            assert!((actual.imag - expected.imag).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn simulates_wide_reversible_circuits() {
+        let mut circuit = QuantumCircuit::new(128);
+        circuit.x(0).x(5).x(63).x(100);
+        for qubit in 0..64 {
+            circuit.cx(qubit, 64 + qubit);
+        }
+        circuit.ccx(0, 5, 10).swap(100, 101).ccx(63, 127, 100).h(20);
+
+        let state = SparseBackend::execute(circuit);
+        let index: u128 = [0, 5, 10, 63, 64, 69, 100, 101, 127]
+            .iter()
+            .map(|qubit| 1 << qubit)
+            .sum();
+
+        assert_eq!(state.len(), 2);
+        for index in [index, index | 1 << 20] {
+            assert!((state.amplitude(index).real - 0.5f64.sqrt()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn prunes_negligible_amplitudes() {
+        let mut circuit = QuantumCircuit::new(70);
+        circuit.h(69).h(3).h(69);
+
+        let state = SparseBackend::execute(circuit);
+        assert_eq!(state.len(), 2);
+
+        let state = SparseStateVector::new(3);
+        assert_eq!(state.nonzero_amplitudes(), vec![(0, Complex::from(1))]);
+
+        let result =
+            SparseBackend::default().run(&QuantumCircuit::new(129), &RunOptions::default());
+        assert_eq!(
+            result,
+            Err(BackendError::TooManyQubits {
+                qubits: 129,
+                max_qubits: 128
+            })
+        );
+    }
+}
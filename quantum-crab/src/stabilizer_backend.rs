@@ -0,0 +1,685 @@
+//! Implements stabilizer backend, that efficiently simulates Clifford circuits.
+//!
+//! The state of `n` qubits is stored as a tableau of `n` stabilizer and `n`
+//! destabilizer Pauli operators (see [Aaronson and Gottesman](https://arxiv.org/abs/quant-ph/0406196)),
+//! so that gates take `O(n)` time and measurements take `O(n^2)` time, which makes it
+//! possible to simulate circuits with thousands of qubits.
+//!
+//! Only Clifford instructions are supported: [`Instruction::Hadamard`], [`Instruction::S`],
+//! [`Instruction::SDagger`], Pauli gates, [`Instruction::ControlledNot`],
+//! [`Instruction::Swap`], [`Instruction::Measure`] and [`Instruction::Reset`], as well
+//! as phase gates and rotations by multiples of `pi/2`, controlled Pauli gates and
+//! conditioned Clifford instructions.
+//!
+//! ```
+//! use quantum_crab::{
+//!     backend::Backend,
+//!     quantum_circuit::QuantumCircuit,
+//!     stabilizer_backend::StabilizerBackend,
+//! };
+//!
+//! let mut circuit = QuantumCircuit::with_clbits(1000, 1000);
+//! circuit.h(0);
+//! for qubit in 1..1000 {
+//!     circuit.cx(qubit - 1, qubit);
+//! }
+//! circuit.measure(0..1000, 0..1000);
+//!
+//! let register = StabilizerBackend::execute(circuit);
+//! assert!(register.bits().iter().all(|bit| *bit == register.bits()[0]));
+//! ```
+use crate::{
+    backend::{check_instructions, Backend, BackendError, CheckedInitialState, RunOptions},
+    classical_register::ClassicalRegister,
+    quantum_circuit::{condition_holds, Instruction, QuantumCircuit},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f64::consts::FRAC_PI_2;
+
+#[derive(Debug, Clone, Default)]
+pub struct StabilizerBackend;
+
+impl Backend for StabilizerBackend {
+    type Output = ClassicalRegister;
+
+    /// Executes the circuit, returning values of classical bits at the end. Every
+    /// execution is a single shot, so conditioned instructions depend on outcomes of
+    /// measurements in it.
+    fn run(
+        &self,
+        circuit: &QuantumCircuit,
+        options: &RunOptions,
+    ) -> Result<ClassicalRegister, BackendError> {
+        check_instructions(circuit, is_clifford)?;
+
+        let mut rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let circuit = circuit.flatten();
+        let mut state = StabilizerState::new(circuit.qubits());
+        match options.initial_state(circuit.qubits())? {
+            CheckedInitialState::Basis(bits) => {
+                for (qubit, bit) in bits.iter().enumerate() {
+                    if *bit == 1 {
+                        state.apply(&Instruction::PauliX(qubit))?;
+                    }
+                }
+            }
+            CheckedInitialState::StateVector(..) => {
+                return Err(BackendError::UnsupportedInitialState)
+            }
+        }
+        let mut clbits = vec![0; circuit.clbits()];
+
+        for instruction in circuit.instructions() {
+            options.check_cancelled()?;
+            execute(instruction, &mut state, &mut clbits, &mut rng)?;
+        }
+
+        Ok(ClassicalRegister::new(clbits))
+    }
+}
+
+/// Executes the instruction, that can read and write classical bits.
+fn execute<R: Rng>(
+    instruction: &Instruction,
+    state: &mut StabilizerState,
+    clbits: &mut [u8],
+    rng: &mut R,
+) -> Result<(), BackendError> {
+    match instruction {
+        Instruction::Measure { qubit, clbit } => clbits[*clbit] = state.measure(*qubit, rng),
+        Instruction::Reset(qubit) => state.reset(*qubit, rng),
+        Instruction::If {
+            register,
+            value,
+            instruction,
+        } => {
+            if condition_holds(register, *value, clbits) {
+                execute(instruction, state, clbits, rng)?;
+            }
+        }
+        instruction => state.apply(instruction)?,
+    }
+
+    Ok(())
+}
+
+/// Amount of quarter turns (`pi/2` rotations) in the angle, if it is their multiple.
+fn quarter_turns(angle: f64) -> Option<u8> {
+    let turns = angle / FRAC_PI_2;
+
+    if (turns - turns.round()).abs() < 1e-9 {
+        Some(turns.round().rem_euclid(4f64) as u8)
+    } else {
+        None
+    }
+}
+
+/// Checks if the instruction can be simulated with stabilizer tableau.
+fn is_clifford(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Identity(..)
+        | Instruction::PauliX(..)
+        | Instruction::PauliY(..)
+        | Instruction::PauliZ(..)
+        | Instruction::Hadamard(..)
+        | Instruction::S(..)
+        | Instruction::SDagger(..)
+        | Instruction::ControlledNot { .. }
+        | Instruction::Swap(..)
+        | Instruction::Measure { .. }
+        | Instruction::Reset(..)
+        | Instruction::Barrier(..) => true,
+        Instruction::If { instruction, .. } => is_clifford(instruction),
+        Instruction::Phase { phase, .. }
+        | Instruction::PhaseDagger { phase, .. }
+        | Instruction::RotationX { phase, .. }
+        | Instruction::RotationY { phase, .. }
+        | Instruction::RotationZ { phase, .. } => quarter_turns(*phase).is_some(),
+        Instruction::ControlledU { gate, .. } => matches!(
+            **gate,
+            Instruction::PauliX(..) | Instruction::PauliY(..) | Instruction::PauliZ(..)
+        ),
+        Instruction::T(..)
+        | Instruction::TDagger(..)
+        | Instruction::Toffoli { .. }
+        | Instruction::Initialize { .. }
+        | Instruction::Custom { .. } => false,
+    }
+}
+
+/// Stabilizer state of qubits, represented by the tableau of Pauli operators.
+///
+/// ```
+/// use quantum_crab::stabilizer_backend::StabilizerState;
+/// use quantum_crab::quantum_circuit::Instruction;
+///
+/// let mut state = StabilizerState::new(2);
+/// state.apply(&Instruction::Hadamard(0)).unwrap();
+/// state.apply(&Instruction::ControlledNot { control: 0, target: 1 }).unwrap();
+///
+/// assert_eq!(state.stabilizers(), vec!["+XX", "+ZZ"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StabilizerState {
+    qubits: usize,
+    /// Amount of 64-bit words in a row of the tableau.
+    words: usize,
+    /// X bits of Pauli operators: `n` destabilizers, `n` stabilizers and a scratch row.
+    x: Vec<u64>,
+    /// Z bits of Pauli operators, in the same order as X bits.
+    z: Vec<u64>,
+    /// Signs of Pauli operators (`true` for `-1`).
+    signs: Vec<bool>,
+}
+
+impl StabilizerState {
+    /// Constructs state, where all qubits are `|0>`.
+    pub fn new(qubits: usize) -> StabilizerState {
+        let words = qubits.div_ceil(64);
+        let rows = 2 * qubits + 1;
+
+        let mut state = StabilizerState {
+            qubits,
+            words,
+            x: vec![0; rows * words],
+            z: vec![0; rows * words],
+            signs: vec![false; rows],
+        };
+
+        // Destabilizers are X_i and stabilizers are Z_i.
+        for qubit in 0..qubits {
+            state.set_x(qubit, qubit, true);
+            state.set_z(qubits + qubit, qubit, true);
+        }
+
+        state
+    }
+
+    /// Amount of qubits.
+    #[inline]
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+
+    #[inline]
+    fn index(&self, row: usize, qubit: usize) -> (usize, u64) {
+        (row * self.words + qubit / 64, 1 << (qubit % 64))
+    }
+
+    #[inline]
+    fn x(&self, row: usize, qubit: usize) -> bool {
+        let (word, mask) = self.index(row, qubit);
+        self.x[word] & mask != 0
+    }
+
+    #[inline]
+    fn z(&self, row: usize, qubit: usize) -> bool {
+        let (word, mask) = self.index(row, qubit);
+        self.z[word] & mask != 0
+    }
+
+    #[inline]
+    fn set_x(&mut self, row: usize, qubit: usize, value: bool) {
+        let (word, mask) = self.index(row, qubit);
+        self.x[word] = if value {
+            self.x[word] | mask
+        } else {
+            self.x[word] & !mask
+        };
+    }
+
+    #[inline]
+    fn set_z(&mut self, row: usize, qubit: usize, value: bool) {
+        let (word, mask) = self.index(row, qubit);
+        self.z[word] = if value {
+            self.z[word] | mask
+        } else {
+            self.z[word] & !mask
+        };
+    }
+
+    fn hadamard(&mut self, qubit: usize) {
+        for row in 0..2 * self.qubits {
+            let (x, z) = (self.x(row, qubit), self.z(row, qubit));
+            self.signs[row] ^= x && z;
+            self.set_x(row, qubit, z);
+            self.set_z(row, qubit, x);
+        }
+    }
+
+    fn phase(&mut self, qubit: usize) {
+        for row in 0..2 * self.qubits {
+            let (x, z) = (self.x(row, qubit), self.z(row, qubit));
+            self.signs[row] ^= x && z;
+            self.set_z(row, qubit, x ^ z);
+        }
+    }
+
+    fn phase_dagger(&mut self, qubit: usize) {
+        for row in 0..2 * self.qubits {
+            let (x, z) = (self.x(row, qubit), self.z(row, qubit));
+            self.signs[row] ^= x && !z;
+            self.set_z(row, qubit, x ^ z);
+        }
+    }
+
+    /// Applies the Pauli gate with given X and Z components.
+    fn pauli(&mut self, qubit: usize, x: bool, z: bool) {
+        for row in 0..2 * self.qubits {
+            self.signs[row] ^= (z && self.x(row, qubit)) ^ (x && self.z(row, qubit));
+        }
+    }
+
+    fn controlled_not(&mut self, control: usize, target: usize) {
+        for row in 0..2 * self.qubits {
+            let (x_control, z_control) = (self.x(row, control), self.z(row, control));
+            let (x_target, z_target) = (self.x(row, target), self.z(row, target));
+
+            self.signs[row] ^= x_control && z_target && !(x_target ^ z_control);
+            self.set_x(row, target, x_target ^ x_control);
+            self.set_z(row, control, z_control ^ z_target);
+        }
+    }
+
+    fn swap(&mut self, qubit1: usize, qubit2: usize) {
+        for row in 0..2 * self.qubits {
+            let (x1, z1) = (self.x(row, qubit1), self.z(row, qubit1));
+            let (x2, z2) = (self.x(row, qubit2), self.z(row, qubit2));
+
+            self.set_x(row, qubit1, x2);
+            self.set_z(row, qubit1, z2);
+            self.set_x(row, qubit2, x1);
+            self.set_z(row, qubit2, z1);
+        }
+    }
+
+    /// Applies `S` gate `turns` times.
+    fn phase_turns(&mut self, qubit: usize, turns: u8) {
+        match turns {
+            1 => self.phase(qubit),
+            2 => self.pauli(qubit, false, true),
+            3 => self.phase_dagger(qubit),
+            _ => {}
+        }
+    }
+
+    /// Applies Clifford gate to the state. Fails if the instruction is not a Clifford
+    /// gate, measurements, resets, conditioned instructions and custom gates are not
+    /// supported either.
+    pub fn apply(&mut self, instruction: &Instruction) -> Result<(), BackendError> {
+        if matches!(
+            instruction,
+            Instruction::Measure { .. }
+                | Instruction::Reset(..)
+                | Instruction::If { .. }
+                | Instruction::Custom { .. }
+        ) || !is_clifford(instruction)
+        {
+            return Err(BackendError::UnsupportedInstructions(vec![
+                instruction.clone()
+            ]));
+        }
+
+        for qubit in instruction.qubits() {
+            assert!(
+                qubit < self.qubits,
+                "Invalid input qubit in instruction: {:?}",
+                instruction
+            );
+        }
+
+        match *instruction {
+            Instruction::PauliX(qubit) => self.pauli(qubit, true, false),
+            Instruction::PauliY(qubit) => self.pauli(qubit, true, true),
+            Instruction::PauliZ(qubit) => self.pauli(qubit, false, true),
+            Instruction::Hadamard(qubit) => self.hadamard(qubit),
+            Instruction::S(qubit) => self.phase(qubit),
+            Instruction::SDagger(qubit) => self.phase_dagger(qubit),
+            Instruction::ControlledNot { control, target } => self.controlled_not(control, target),
+            Instruction::Swap(qubit1, qubit2) => self.swap(qubit1, qubit2),
+            // Global phase doesn't change stabilizers, so rotations around Z axis
+            // are equivalent to phase gates.
+            Instruction::Phase { qubit, phase } | Instruction::RotationZ { qubit, phase } => {
+                self.phase_turns(qubit, quarter_turns(phase).unwrap())
+            }
+            Instruction::PhaseDagger { qubit, phase } => {
+                self.phase_turns(qubit, (4 - quarter_turns(phase).unwrap()) % 4)
+            }
+            // RX = H RZ H and RY = S RX S^dagger.
+            Instruction::RotationX { qubit, phase } => {
+                self.hadamard(qubit);
+                self.phase_turns(qubit, quarter_turns(phase).unwrap());
+                self.hadamard(qubit);
+            }
+            Instruction::RotationY { qubit, phase } => {
+                self.phase_dagger(qubit);
+                self.hadamard(qubit);
+                self.phase_turns(qubit, quarter_turns(phase).unwrap());
+                self.hadamard(qubit);
+                self.phase(qubit);
+            }
+            Instruction::ControlledU {
+                ref gate,
+                control,
+                target,
+            } => match **gate {
+                Instruction::PauliX(..) => self.controlled_not(control, target),
+                Instruction::PauliY(..) => {
+                    self.phase_dagger(target);
+                    self.controlled_not(control, target);
+                    self.phase(target);
+                }
+                _ => {
+                    self.hadamard(target);
+                    self.controlled_not(control, target);
+                    self.hadamard(target);
+                }
+            },
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Sets row `target` to the product of Pauli operators in rows `target` and
+    /// `source`.
+    fn multiply_rows(&mut self, target: usize, source: usize) {
+        // Phase of the product is i^sum, where sum is always even.
+        let mut sum: i64 = 2 * (self.signs[target] as i64 + self.signs[source] as i64);
+
+        for word in 0..self.words {
+            let (x1, z1) = (
+                self.x[source * self.words + word],
+                self.z[source * self.words + word],
+            );
+            let (x2, z2) = (
+                self.x[target * self.words + word],
+                self.z[target * self.words + word],
+            );
+
+            let plus = (x1 & z1 & z2 & !x2) | (x1 & !z1 & z2 & x2) | (!x1 & z1 & x2 & !z2);
+            let minus = (x1 & z1 & x2 & !z2) | (x1 & !z1 & z2 & !x2) | (!x1 & z1 & x2 & z2);
+            sum += plus.count_ones() as i64 - minus.count_ones() as i64;
+
+            self.x[target * self.words + word] ^= x1;
+            self.z[target * self.words + word] ^= z1;
+        }
+
+        self.signs[target] = sum.rem_euclid(4) == 2;
+    }
+
+    /// Measures the qubit in the computational basis, returning the outcome.
+    pub fn measure<R: Rng>(&mut self, qubit: usize, rng: &mut R) -> u8 {
+        assert!(qubit < self.qubits, "Invalid qubit {}", qubit);
+        let n = self.qubits;
+
+        // The outcome is random if any stabilizer anticommutes with Z on the qubit.
+        if let Some(pivot) = (n..2 * n).find(|row| self.x(*row, qubit)) {
+            for row in 0..2 * n {
+                if row != pivot && self.x(row, qubit) {
+                    self.multiply_rows(row, pivot);
+                }
+            }
+
+            let (from, to) = (pivot * self.words, (pivot - n) * self.words);
+            self.x.copy_within(from..from + self.words, to);
+            self.z.copy_within(from..from + self.words, to);
+            self.signs[pivot - n] = self.signs[pivot];
+
+            self.x[from..from + self.words].fill(0);
+            self.z[from..from + self.words].fill(0);
+            self.set_z(pivot, qubit, true);
+            self.signs[pivot] = rng.gen();
+
+            return self.signs[pivot] as u8;
+        }
+
+        let scratch = 2 * n;
+        self.x[scratch * self.words..].fill(0);
+        self.z[scratch * self.words..].fill(0);
+        self.signs[scratch] = false;
+
+        for row in 0..n {
+            if self.x(row, qubit) {
+                self.multiply_rows(scratch, row + n);
+            }
+        }
+
+        self.signs[scratch] as u8
+    }
+
+    /// Resets the qubit to `|0>`, by measuring it and flipping it if the outcome is
+    /// `|1>`.
+    pub fn reset<R: Rng>(&mut self, qubit: usize, rng: &mut R) {
+        if self.measure(qubit, rng) == 1 {
+            self.pauli(qubit, true, false);
+        }
+    }
+
+    /// Stabilizers of the state as Pauli strings, where the first character is the
+    /// sign and qubit `i` corresponds to character `i + 1`.
+    pub fn stabilizers(&self) -> Vec<String> {
+        (self.qubits..2 * self.qubits)
+            .map(|row| {
+                let sign = if self.signs[row] { '-' } else { '+' };
+                let paulis =
+                    (0..self.qubits).map(|qubit| match (self.x(row, qubit), self.z(row, qubit)) {
+                        (false, false) => 'I',
+                        (true, false) => 'X',
+                        (false, true) => 'Z',
+                        (true, true) => 'Y',
+                    });
+
+                std::iter::once(sign).chain(paulis).collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{Backend, BackendError, InitialState, RunOptions},
+        classical_register::ClassicalRegister,
+        complex::Complex,
+        quantum_circuit::{Instruction, QuantumCircuit},
+        stabilizer_backend::{StabilizerBackend, StabilizerState},
+        statevector::StateVector,
+    };
+    use std::f64::consts::PI;
+
+    #[test]
+    fn measures_entangled_qubits() {
+        let mut circuit = QuantumCircuit::with_clbits(200, 200);
+        circuit.h(0);
+        for qubit in 1..200 {
+            circuit.cx(0, qubit);
+        }
+        circuit.swap(3, 150).measure(0..200, 0..200);
+
+        let mut outcomes = Vec::new();
+        for seed in 0..16 {
+            let options = RunOptions {
+                seed: Some(seed),
+                ..RunOptions::default()
+            };
+            let register = StabilizerBackend.run(&circuit, &options).unwrap();
+            assert!(register.bits().iter().all(|bit| *bit == register.bits()[0]));
+            outcomes.push(register.bits()[0]);
+        }
+
+        assert!(outcomes.contains(&0) && outcomes.contains(&1));
+    }
+
+    #[test]
+    fn seeds_batches() {
+        let mut circuit = QuantumCircuit::with_clbits(8, 8);
+        circuit.h(0..8).measure(0..8, 0..8);
+
+        let options = RunOptions {
+            seed: Some(7),
+            ..RunOptions::default()
+        };
+        let circuits = vec![circuit; 8];
+        let results = StabilizerBackend.run_batch(&circuits, &options);
+
+        assert_eq!(results, StabilizerBackend.run_batch(&circuits, &options));
+        for (seed, result) in (7..).zip(results) {
+            let options = RunOptions {
+                seed: Some(seed),
+                ..RunOptions::default()
+            };
+            assert_eq!(result, StabilizerBackend.run(&circuits[0], &options));
+        }
+    }
+
+    #[test]
+    fn starts_from_basis_states() {
+        let mut circuit = QuantumCircuit::with_clbits(3, 3);
+        circuit.cx(0, 1).measure(0..3, 0..3);
+
+        let run = |initial_state| {
+            let options = RunOptions {
+                initial_state: Some(initial_state),
+                ..RunOptions::default()
+            };
+            StabilizerBackend.run(&circuit, &options)
+        };
+
+        let register = run(InitialState::Bitstring("101".to_owned())).unwrap();
+        assert_eq!(register.bits(), &[1, 1, 1]);
+        let register = run(InitialState::Register(ClassicalRegister::new(vec![
+            0, 0, 1,
+        ])))
+        .unwrap();
+        assert_eq!(register.bits(), &[0, 0, 1]);
+
+        let state = StateVector::new([1, 0, 0, 0, 0, 0, 0, 0].map(Complex::from).to_vec());
+        assert_eq!(
+            run(InitialState::StateVector(state)),
+            Err(BackendError::UnsupportedInitialState)
+        );
+    }
+
+    #[test]
+    fn executes_conditioned_instructions() {
+        // Bit flip code, correcting the error on qubit 1 from the syndrome.
+        let mut circuit = QuantumCircuit::with_clbits(5, 5);
+        circuit
+            .h(0)
+            .cx(0, 1)
+            .cx(0, 2)
+            .x(1)
+            .cx(0, 3)
+            .cx(1, 3)
+            .cx(1, 4)
+            .cx(2, 4)
+            .measure(3..5, 3..5)
+            .c_if(3..5, 1, Instruction::PauliX(0))
+            .c_if(3..5, 3, Instruction::PauliX(1))
+            .c_if(3..5, 2, Instruction::PauliX(2))
+            .reset(3..5)
+            .measure(0..5, 0..5);
+
+        for seed in 0..16 {
+            let options = RunOptions {
+                seed: Some(seed),
+                ..RunOptions::default()
+            };
+            let bits = StabilizerBackend.run(&circuit, &options).unwrap();
+
+            assert_eq!(bits.bits()[0], bits.bits()[1]);
+            assert_eq!(bits.bits()[1], bits.bits()[2]);
+            assert_eq!(&bits.bits()[3..], &[0, 0]);
+        }
+
+        circuit.c_if(0, 1, Instruction::T(0));
+        assert!(matches!(
+            StabilizerBackend.run(&circuit, &RunOptions::default()),
+            Err(BackendError::UnsupportedInstructions(..))
+        ));
+    }
+
+    #[test]
+    fn executes_deterministic_circuits() {
+        let mut circuit = QuantumCircuit::with_clbits(6, 6);
+        circuit
+            .x(0)
+            .h(1)
+            .s(1)
+            .s(1)
+            .h(1)
+            .h(2)
+            .sdg(2)
+            .sdg(2)
+            .h(2)
+            .rx(3, PI)
+            .ry(4, -PI)
+            .h(5)
+            .p(5, PI / 2f64)
+            .pdg(5, PI / 2f64)
+            .h(5)
+            .measure(0..6, 0..6);
+
+        let register = StabilizerBackend::execute(circuit);
+        assert_eq!(register.bits(), &[1, 1, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn tracks_stabilizers() {
+        let mut state = StabilizerState::new(3);
+        for instruction in [
+            Instruction::Hadamard(0),
+            Instruction::ControlledU {
+                gate: Box::new(Instruction::PauliY(1)),
+                control: 0,
+                target: 1,
+            },
+            Instruction::PauliZ(0),
+            Instruction::RotationY {
+                qubit: 2,
+                phase: PI / 2f64,
+            },
+        ] {
+            state.apply(&instruction).unwrap();
+        }
+
+        assert_eq!(state.stabilizers(), vec!["-XYI", "+ZZI", "+IIX"]);
+    }
+
+    #[test]
+    fn rejects_non_clifford_instructions() {
+        let mut inner = QuantumCircuit::new(2);
+        inner.h(0).t(1);
+
+        let mut circuit = QuantumCircuit::new(3);
+        circuit
+            .rx(0, 0.3)
+            .rz(1, PI)
+            .custom("inner", inner, [1, 2])
+            .ccx(0, 1, 2);
+
+        let error = StabilizerBackend
+            .run(&circuit, &RunOptions::default())
+            .unwrap_err();
+        assert_eq!(
+            error,
+            BackendError::UnsupportedInstructions(vec![
+                Instruction::RotationX {
+                    qubit: 0,
+                    phase: 0.3
+                },
+                Instruction::T(2),
+                Instruction::Toffoli {
+                    control1: 0,
+                    control2: 1,
+                    target: 2
+                },
+            ])
+        );
+        assert!(error.to_string().contains("T(2)"));
+    }
+}
@@ -0,0 +1,176 @@
+//! Implements synthesis of circuits, that prepare arbitrary states from `|0...0>`,
+//! following [Möttönen et al.](https://arxiv.org/abs/quant-ph/0407010).
+//!
+//! Magnitudes of amplitudes are prepared by a cascade of uniformly controlled Y
+//! rotations, from the most significant qubit to qubit 0, and then phases are fixed
+//! by uniformly controlled Z rotations in the reverse order. Every uniformly
+//! controlled rotation with `k` controls is decomposed into `2^k` rotations and
+//! `2^k` controlled not gates.
+//!
+//! ```
+//! use quantum_crab::{
+//!     backend::Backend,
+//!     complex::Complex,
+//!     state_preparation::state_preparation,
+//!     statevector_backend::StateVectorBackend,
+//! };
+//!
+//! let amplitudes = [0.6, 0.0, 0.0, -0.8].map(Complex::from);
+//! let circuit = state_preparation(&amplitudes);
+//!
+//! let state = StateVectorBackend::execute(circuit);
+//! for (actual, expected) in state.amplitudes().iter().zip(amplitudes) {
+//!     assert!((*actual - expected).norm() < 1e-12);
+//! }
+//! ```
+use crate::{
+    complex::Complex,
+    quantum_circuit::{Instruction, QuantumCircuit},
+};
+
+/// Builds circuit, that turns `|0...0>` into the state with the normalized
+/// amplitudes, the amount of which must be a power of two. Amplitudes are indexed
+/// by basis states, where qubit 0 is the least significant bit.
+pub fn state_preparation(amplitudes: &[Complex]) -> QuantumCircuit {
+    assert!(
+        amplitudes.len().is_power_of_two(),
+        "Amount of amplitudes must be a power of two"
+    );
+
+    let qubits = amplitudes.len().trailing_zeros() as usize;
+    let mut circuit = QuantumCircuit::new(qubits);
+
+    // Magnitudes: rotation of the qubit `target` splits the probability of every
+    // pattern of more significant qubits between its `|0>` and `|1>` states.
+    let probabilities: Vec<f64> = amplitudes
+        .iter()
+        .map(|amplitude| amplitude.norm().powi(2))
+        .collect();
+    for target in (0..qubits).rev() {
+        let angles: Vec<f64> = (0..1 << (qubits - target - 1))
+            .map(|pattern| {
+                let probability = |bit: usize| -> f64 {
+                    let start = (pattern << 1 | bit) << target;
+                    probabilities[start..start + (1 << target)].iter().sum()
+                };
+
+                2f64 * probability(1).sqrt().atan2(probability(0).sqrt())
+            })
+            .collect();
+
+        uniformly_controlled_rotation(&mut circuit, Axis::Y, target, &angles);
+    }
+
+    // Phases: rotation of the qubit `target` sets relative phases of its states, and
+    // their mean phases are left for more significant qubits.
+    let mut phases: Vec<f64> = amplitudes
+        .iter()
+        .map(|amplitude| amplitude.imag.atan2(amplitude.real))
+        .collect();
+    for target in 0..qubits {
+        let angles: Vec<f64> = phases.chunks(2).map(|pair| pair[1] - pair[0]).collect();
+        phases = phases
+            .chunks(2)
+            .map(|pair| (pair[0] + pair[1]) / 2f64)
+            .collect();
+
+        uniformly_controlled_rotation(&mut circuit, Axis::Z, target, &angles);
+    }
+
+    // Global phase `e^(i * phase) = X P(phase) X P(phase)`.
+    let phase = phases[0];
+    if qubits > 0 && phase.abs() > 1e-12 {
+        circuit.p(0, phase).x(0).p(0, phase).x(0);
+    }
+
+    circuit
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Axis {
+    Y,
+    Z,
+}
+
+/// Adds rotation of the `target` qubit around the axis by `angles[pattern]`, where
+/// `pattern` is the state of all more significant qubits (bit `i` is the state of
+/// qubit `target + 1 + i`).
+fn uniformly_controlled_rotation(
+    circuit: &mut QuantumCircuit,
+    axis: Axis,
+    target: usize,
+    angles: &[f64],
+) {
+    if angles.iter().all(|angle| angle.abs() < 1e-12) {
+        return;
+    }
+
+    let rotation = |qubit, phase| match axis {
+        Axis::Y => Instruction::RotationY { qubit, phase },
+        Axis::Z => Instruction::RotationZ { qubit, phase },
+    };
+
+    if angles.len() == 1 {
+        circuit.add(rotation(target, angles[0]));
+        return;
+    }
+
+    // Controlled not gates flip signs of the following rotations for control patterns
+    // with odd amount of ones in common with Gray code of the step, so rotation
+    // angles are the transformed `angles`.
+    let gray = |step: usize| step ^ step >> 1;
+    for step in 0..angles.len() {
+        let phase = angles
+            .iter()
+            .enumerate()
+            .map(|(pattern, angle)| {
+                if (pattern & gray(step)).count_ones() % 2 == 0 {
+                    *angle
+                } else {
+                    -angle
+                }
+            })
+            .sum::<f64>()
+            / angles.len() as f64;
+        circuit.add(rotation(target, phase));
+
+        let next = (step + 1) % angles.len();
+        let control = target + 1 + (gray(step) ^ gray(next)).trailing_zeros() as usize;
+        circuit.cx(control, target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::Backend, complex::Complex, state_preparation::state_preparation,
+        statevector_backend::StateVectorBackend,
+    };
+
+    #[test]
+    fn prepares_states() {
+        let raw: Vec<Complex> = (0..8)
+            .map(|i| Complex::new((i as f64 * 0.7).sin(), (i as f64 * 1.3).cos()))
+            .collect();
+        let norm = raw
+            .iter()
+            .map(|amplitude| amplitude.norm().powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let normalized: Vec<_> = raw
+            .iter()
+            .map(|amplitude| *amplitude * Complex::from(1f64 / norm))
+            .collect();
+
+        let mut sparse = vec![Complex::from(0); 16];
+        sparse[5] = Complex::new(0f64, -1f64);
+
+        for amplitudes in [normalized, sparse] {
+            let state = StateVectorBackend::execute(state_preparation(&amplitudes));
+
+            for (actual, expected) in state.amplitudes().iter().zip(&amplitudes) {
+                assert!((*actual - *expected).norm() < 1e-12);
+            }
+        }
+    }
+}
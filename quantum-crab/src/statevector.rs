@@ -0,0 +1,342 @@
+//! Implements state vector, that is returned by the state vector backend.
+//!
+//! Amplitudes are indexed by basis states, where qubit 0 is the least significant
+//! bit. In bitstrings and ket notation qubits are written in the reverse order, so
+//! that `|01>` is the state, where qubit 0 is `|1>`.
+//!
+//! ```
+//! use quantum_crab::{
+//!     backend::Backend,
+//!     quantum_circuit::QuantumCircuit,
+//!     statevector_backend::StateVectorBackend,
+//! };
+//!
+//! let mut circuit = QuantumCircuit::new(2);
+//! circuit.h(0).cx(0, 1);
+//!
+//! let state = StateVectorBackend::execute(circuit);
+//! assert_eq!(state.to_string(), "0.707|00> + 0.707|11>");
+//! assert!((state.expectation("ZZ") - 1f64).abs() < 1e-12);
+//! assert!((state.marginal(&[1])[0] - 0.5).abs() < 1e-12);
+//! ```
+use crate::complex::Complex;
+use num::Zero;
+use rand::Rng;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+/// State of qubits, represented by amplitudes of all basis states.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateVector {
+    amplitudes: Vec<Complex>,
+}
+
+impl StateVector {
+    /// Constructs state from amplitudes, the amount of which must be a power of two.
+    pub fn new(amplitudes: Vec<Complex>) -> StateVector {
+        assert!(
+            amplitudes.len().is_power_of_two(),
+            "Amount of amplitudes must be a power of two"
+        );
+
+        StateVector { amplitudes }
+    }
+
+    /// Amount of qubits.
+    #[inline]
+    pub fn qubits(&self) -> usize {
+        self.amplitudes.len().trailing_zeros() as usize
+    }
+
+    /// Amplitudes of all basis states.
+    #[inline]
+    pub fn amplitudes(&self) -> &[Complex] {
+        &self.amplitudes
+    }
+
+    /// Returns amplitudes of all basis states.
+    #[inline]
+    pub fn into_amplitudes(self) -> Vec<Complex> {
+        self.amplitudes
+    }
+
+    /// Returns amplitude of the basis state, written as a bitstring like `"01"`.
+    pub fn amplitude(&self, bitstring: &str) -> Complex {
+        assert_eq!(
+            bitstring.len(),
+            self.qubits(),
+            "Bitstring must have a bit for every qubit"
+        );
+
+        let index = bitstring.chars().fold(0, |index, bit| match bit {
+            '0' => index << 1,
+            '1' => index << 1 | 1,
+            _ => panic!("Invalid bit {:?} in bitstring", bit),
+        });
+
+        self.amplitudes[index]
+    }
+
+    /// Returns probabilities of all basis states.
+    pub fn probabilities(&self) -> Vec<f64> {
+        self.amplitudes
+            .iter()
+            .map(|amplitude| amplitude.norm().powi(2))
+            .collect()
+    }
+
+    /// Returns probabilities of all basis states of the `qubits`, where bit `i` of the
+    /// index is the state of `qubits[i]`.
+    pub fn marginal(&self, qubits: &[usize]) -> Vec<f64> {
+        for qubit in qubits {
+            assert!(*qubit < self.qubits(), "Invalid qubit {}", qubit);
+        }
+
+        let mut probabilities = vec![0f64; 1 << qubits.len()];
+        for (index, probability) in self.probabilities().into_iter().enumerate() {
+            let marginal_index = qubits
+                .iter()
+                .enumerate()
+                .map(|(i, qubit)| (index >> qubit & 1) << i)
+                .sum::<usize>();
+            probabilities[marginal_index] += probability;
+        }
+
+        probabilities
+    }
+
+    /// Measures all qubits `shots` times, returning how many times every bitstring
+    /// was measured.
+    pub fn sample<R: Rng>(&self, shots: usize, rng: &mut R) -> BTreeMap<String, usize> {
+        let mut cumulative = Vec::with_capacity(self.amplitudes.len());
+        let mut total = 0f64;
+        for probability in self.probabilities() {
+            total += probability;
+            cumulative.push(total);
+        }
+
+        let mut counts = BTreeMap::new();
+        for _ in 0..shots {
+            let value = rng.gen::<f64>() * total;
+            let index = cumulative
+                .partition_point(|probability| *probability <= value)
+                .min(cumulative.len() - 1);
+
+            *counts.entry(self.bitstring(index)).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    /// Computes expectation value of the Pauli string observable like `"XZ"`, written
+    /// in the same order as bitstrings (so that `Z` is applied to qubit 0 here).
+    pub fn expectation(&self, observable: &str) -> f64 {
+        assert_eq!(
+            observable.len(),
+            self.qubits(),
+            "Observable must have a Pauli operator for every qubit"
+        );
+
+        // Pauli string maps `|i>` to `i^y * (-1)^(popcount(i & (y | z))) |i ^ (x | y)>`.
+        let (mut x, mut y, mut z) = (0usize, 0usize, 0usize);
+        for (qubit, pauli) in observable.chars().rev().enumerate() {
+            match pauli {
+                'I' => {}
+                'X' => x |= 1 << qubit,
+                'Y' => y |= 1 << qubit,
+                'Z' => z |= 1 << qubit,
+                _ => panic!("Invalid Pauli operator {:?} in observable", pauli),
+            }
+        }
+
+        let phase = match y.count_ones() % 4 {
+            0 => Complex::new(1f64, 0f64),
+            1 => Complex::new(0f64, 1f64),
+            2 => Complex::new(-1f64, 0f64),
+            _ => Complex::new(0f64, -1f64),
+        };
+
+        let expectation =
+            self.amplitudes
+                .iter()
+                .enumerate()
+                .fold(Complex::zero(), |sum, (index, amplitude)| {
+                    let term = self.amplitudes[index ^ (x | y)].conjugate() * *amplitude;
+
+                    if (index & (y | z)).count_ones() % 2 == 0 {
+                        sum + term
+                    } else {
+                        sum - term
+                    }
+                });
+
+        (phase * expectation).real
+    }
+
+    /// Computes inner product `<self|other>`.
+    pub fn inner_product(&self, other: &StateVector) -> Complex {
+        assert_eq!(
+            self.amplitudes.len(),
+            other.amplitudes.len(),
+            "States must have the same amount of qubits"
+        );
+
+        self.amplitudes
+            .iter()
+            .zip(&other.amplitudes)
+            .fold(Complex::zero(), |sum, (a, b)| sum + a.conjugate() * *b)
+    }
+
+    /// Computes fidelity `|<self|other>|^2` of pure states.
+    pub fn fidelity(&self, other: &StateVector) -> f64 {
+        self.inner_product(other).norm().powi(2)
+    }
+
+    /// Checks if probabilities of basis states sum up to 1.
+    pub fn is_normalized(&self) -> bool {
+        (self.probabilities().iter().sum::<f64>() - 1f64).abs() < 1e-9
+    }
+
+    fn bitstring(&self, index: usize) -> String {
+        (0..self.qubits())
+            .rev()
+            .map(|qubit| if index >> qubit & 1 == 1 { '1' } else { '0' })
+            .collect()
+    }
+}
+
+/// Formats the state in ket notation, like `0.707|00> + 0.707|11>`. Amplitudes, that
+/// round to zero with the precision of the formatter (3 digits by default), are
+/// omitted.
+impl Display for StateVector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(3);
+        let negligible = 0.5 * 10f64.powi(-(precision as i32));
+        let mut empty = true;
+
+        for (index, amplitude) in self.amplitudes.iter().enumerate() {
+            let (real, imag) = (amplitude.real, amplitude.imag);
+            let (has_real, has_imag) = (real.abs() >= negligible, imag.abs() >= negligible);
+            if !has_real && !has_imag {
+                continue;
+            }
+
+            // Sign of the coefficient is written between terms.
+            let negative = if has_real { real < 0f64 } else { imag < 0f64 };
+            match (empty, negative) {
+                (true, true) => write!(f, "-")?,
+                (true, false) => {}
+                (false, true) => write!(f, " - ")?,
+                (false, false) => write!(f, " + ")?,
+            }
+            empty = false;
+
+            let sign = if negative { -1f64 } else { 1f64 };
+            match (has_real, has_imag) {
+                (true, true) => {
+                    let operator = if imag * sign < 0f64 { '-' } else { '+' };
+                    write!(
+                        f,
+                        "({:.*} {} {:.*}i)",
+                        precision,
+                        real.abs(),
+                        operator,
+                        precision,
+                        imag.abs()
+                    )?
+                }
+                (true, false) => write!(f, "{:.*}", precision, real.abs())?,
+                _ => write!(f, "{:.*}i", precision, imag.abs())?,
+            }
+
+            write!(f, "|{}>", self.bitstring(index))?;
+        }
+
+        if empty {
+            write!(f, "0")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{complex::Complex, statevector::StateVector};
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    fn plus_minus() -> StateVector {
+        // |+> on qubit 0 and |-> on qubit 1.
+        StateVector::new(vec![
+            Complex::from(0.5),
+            Complex::from(0.5),
+            Complex::from(-0.5),
+            Complex::from(-0.5),
+        ])
+    }
+
+    #[test]
+    fn computes_observables() {
+        let state = plus_minus();
+        assert_eq!(state.qubits(), 2);
+        assert!(state.is_normalized());
+        assert_eq!(state.amplitude("10"), Complex::from(-0.5));
+
+        for (observable, expected) in [("IX", 1f64), ("XI", -1f64), ("XX", -1f64), ("ZI", 0f64)] {
+            assert!((state.expectation(observable) - expected).abs() < 1e-12);
+        }
+
+        let y = StateVector::new(vec![
+            Complex::from(FRAC_1_SQRT_2),
+            Complex::new(0f64, FRAC_1_SQRT_2),
+        ]);
+        assert!((y.expectation("Y") - 1f64).abs() < 1e-12);
+        assert!(y.expectation("X").abs() < 1e-12);
+
+        let zero = StateVector::new(vec![Complex::from(1), Complex::from(0)]);
+        assert!((y.fidelity(&zero) - 0.5).abs() < 1e-12);
+        assert_eq!(zero.inner_product(&y), Complex::from(FRAC_1_SQRT_2));
+        assert_eq!(plus_minus().marginal(&[1, 0]), vec![0.25, 0.25, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn samples_bitstrings() {
+        let state = StateVector::new(vec![
+            Complex::from(0.6),
+            Complex::from(0),
+            Complex::from(0),
+            Complex::from(0.8),
+        ]);
+
+        let counts = state.sample(1000, &mut StdRng::seed_from_u64(0));
+        assert_eq!(counts.keys().collect::<Vec<_>>(), vec!["00", "11"]);
+        assert_eq!(counts.values().sum::<usize>(), 1000);
+        assert!(counts["11"] > 550 && counts["11"] < 730);
+    }
+
+    #[test]
+    fn formats_kets() {
+        assert_eq!(
+            plus_minus().to_string(),
+            "0.500|00> + 0.500|01> - 0.500|10> - 0.500|11>"
+        );
+
+        let state = StateVector::new(vec![
+            Complex::new(0f64, -0.6),
+            Complex::new(1e-9, 0f64),
+            Complex::new(-0.48, 0.36),
+            Complex::new(0f64, 0.52),
+        ]);
+        assert_eq!(
+            state.to_string(),
+            "-0.600i|00> - (0.480 - 0.360i)|10> + 0.520i|11>"
+        );
+        assert_eq!(
+            format!("{:.1}", state),
+            "-0.6i|00> - (0.5 - 0.4i)|10> + 0.5i|11>"
+        );
+    }
+}
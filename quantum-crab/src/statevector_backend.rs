@@ -1,26 +1,105 @@
 use crate::{
-    backend::Backend,
+    amplitude_buffer::{AmplitudeBuffer, Real},
+    backend::{
+        batch_options, check_instructions, Backend, BackendError, CheckedInitialState, RunOptions,
+    },
     complex::Complex,
+    gate_fusion::{fuse, FusedGate},
     matrix::Matrix,
-    quantum_circuit::{Instruction, QuantumCircuit},
+    quantum_circuit::{condition_holds, Instruction, QuantumCircuit},
+    state_preparation::state_preparation,
+    statevector::StateVector,
 };
 use num::{One, Zero};
-use std::f64::consts::PI;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "parallel")]
+use std::sync::Arc;
+use std::{collections::BTreeMap, f64::consts::PI};
 
-#[derive(Debug)]
-pub struct StateVectorBackend;
+/// State vector backend. With the `parallel` feature, gates are applied to chunks
+/// of the state vector on multiple threads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateVectorBackend {
+    /// Thread pool to use, by default the global thread pool is used (see
+    /// [`StateVectorBackend::with_threads`]).
+    #[cfg(feature = "parallel")]
+    pub thread_pool: Option<ThreadPool>,
+    /// Circuits with fewer qubits are executed on a single thread. Ignored without
+    /// the `parallel` feature.
+    pub parallel_threshold: usize,
+    /// Precision of amplitudes during the simulation.
+    pub precision: Precision,
+    /// Gates acting on at most this many qubits (0, 1 or 2) are fused into dense
+    /// unitaries before the execution, 0 disables the fusion.
+    pub max_fused_qubits: usize,
+}
 
-/// Executes single qubit gate `instruction` and applies it to the `statevector`.
-fn execute_single_qubit_gate(
-    instruction: &Instruction,
-    circuit: &QuantumCircuit,
-    qubit: usize,
-    statevector: &mut Matrix<Complex>,
-) {
-    let mut gate_matrix = match instruction {
-        // If it is identity gate, then we don't do anything with
-        // the statevector
-        Instruction::Identity(..) => return,
+impl Default for StateVectorBackend {
+    fn default() -> StateVectorBackend {
+        StateVectorBackend {
+            #[cfg(feature = "parallel")]
+            thread_pool: None,
+            parallel_threshold: 14,
+            precision: Precision::Double,
+            max_fused_qubits: 2,
+        }
+    }
+}
+
+/// Thread pool, that executes simulations of [`StateVectorBackend`]. The pool is
+/// built once, clones of it refer to the same threads.
+#[cfg(feature = "parallel")]
+#[derive(Debug, Clone)]
+pub struct ThreadPool(Arc<rayon::ThreadPool>);
+
+#[cfg(feature = "parallel")]
+impl ThreadPool {
+    /// Builds pool of `threads` threads.
+    pub fn new(threads: usize) -> Result<ThreadPool, BackendError> {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map(|pool| ThreadPool(Arc::new(pool)))
+            .map_err(|error| {
+                BackendError::InvalidSettings(format!("Failed to build thread pool: {}", error))
+            })
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl PartialEq for ThreadPool {
+    fn eq(&self, other: &ThreadPool) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl Eq for ThreadPool {}
+
+/// Floating point precision of amplitudes during the simulation. Single precision
+/// halves the memory used by the amplitudes (8 bytes per amplitude instead of 16), at
+/// the cost of errors around `1e-7` in amplitudes.
+///
+/// Results are returned in double precision, so [`Backend::run`] and
+/// [`StateVectorBackend::run_shots`] of circuits without mid-circuit measurements widen
+/// the amplitudes into a double precision [`StateVector`] at the end. Their peak memory
+/// is then 24 bytes per amplitude instead of 32, which is not enough to simulate one
+/// more qubit. Only [`StateVectorBackend::run_shots`] of circuits with mid-circuit
+/// measurements keeps the memory at 8 bytes per amplitude.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    /// Amplitudes are stored as [`f32`].
+    Single,
+    /// Amplitudes are stored as [`f64`].
+    Double,
+}
+
+/// Returns matrix of the single qubit gate `instruction`.
+pub(crate) fn single_qubit_gate_matrix(instruction: &Instruction) -> Matrix<Complex> {
+    match instruction {
+        Instruction::Identity(..) => Matrix::identity(2),
         Instruction::PauliX(..) => matrix_real![[0, 1], [1, 0]],
         Instruction::PauliY(..) => matrix![
             [Complex::zero(), -Complex::i()],
@@ -42,15 +121,9 @@ fn execute_single_qubit_gate(
         }
         Instruction::RotationY { phase, .. } => {
             let phase_half = phase / 2f64;
-            matrix![
-                [
-                    Complex::new(phase_half.cos(), 0),
-                    Complex::new(0, -phase_half.sin())
-                ],
-                [
-                    Complex::new(0, phase_half.sin()),
-                    Complex::new(phase_half.cos(), 0)
-                ]
+            matrix_real![
+                [phase_half.cos(), -phase_half.sin()],
+                [phase_half.sin(), phase_half.cos()]
             ]
         }
         Instruction::RotationZ { phase, .. } => {
@@ -81,56 +154,992 @@ fn execute_single_qubit_gate(
         ],
         Instruction::S(..) => matrix![
             [Complex::one(), Complex::zero()],
-            [Complex::zero(), Complex::new_from_polar(1, PI / 4f64)]
+            [Complex::zero(), Complex::new_from_polar(1, PI / 2f64)]
         ],
         Instruction::SDagger(..) => matrix![
             [Complex::one(), Complex::zero()],
-            [Complex::zero(), Complex::new_from_polar(1, PI / 4f64)]
+            [Complex::zero(), Complex::new_from_polar(1, -PI / 2f64)]
         ],
         _ => unreachable!(),
-    };
-
-    if qubit != 0 {
-        gate_matrix = gate_matrix.tensor_product(&Matrix::identity(2_usize.pow(qubit as u32)));
     }
+}
 
-    for _ in (qubit + 1)..circuit.qubits() {
-        gate_matrix = Matrix::identity(2).tensor_product(&gate_matrix);
+/// Applies the instruction to the `statevector`, for basis states where all qubits
+/// in the `controls` bit mask are `|1>`. If `parallel` is set, amplitudes are updated
+/// in the current thread pool.
+pub(crate) fn apply_instruction<F: Real>(
+    instruction: &Instruction,
+    controls: usize,
+    statevector: &mut AmplitudeBuffer<F>,
+    parallel: bool,
+) {
+    let not = single_qubit_gate_matrix(&Instruction::PauliX(0));
+
+    match instruction {
+        // If it is identity gate, then we don't do anything with
+        // the statevector
+        Instruction::Identity(..) | Instruction::Barrier(..) => {}
+        Instruction::ControlledNot { control, target } => {
+            statevector.apply_single_qubit_gate(&not, controls | 1 << control, *target, parallel)
+        }
+        Instruction::ControlledU {
+            gate,
+            control,
+            target,
+        } => apply_instruction(
+            &gate.map_qubits(|_| *target),
+            controls | 1 << control,
+            statevector,
+            parallel,
+        ),
+        Instruction::Toffoli {
+            control1,
+            control2,
+            target,
+        } => statevector.apply_single_qubit_gate(
+            &not,
+            controls | 1 << control1 | 1 << control2,
+            *target,
+            parallel,
+        ),
+        Instruction::Swap(qubit1, qubit2) if controls == 0 => {
+            let swap = matrix_real![[1, 0, 0, 0], [0, 0, 1, 0], [0, 1, 0, 0], [0, 0, 0, 1]];
+            statevector.apply_two_qubit_gate(&swap, *qubit1, *qubit2, parallel)
+        }
+        Instruction::Swap(qubit1, qubit2) => {
+            for (control, target) in [(qubit1, qubit2), (qubit2, qubit1), (qubit1, qubit2)] {
+                statevector.apply_single_qubit_gate(
+                    &not,
+                    controls | 1 << control,
+                    *target,
+                    parallel,
+                );
+            }
+        }
+        Instruction::Custom {
+            circuit,
+            input_qubits,
+            ..
+        } => {
+            for instruction in circuit.instructions() {
+                apply_instruction(
+                    &instruction.map_qubits(|qubit| input_qubits[qubit]),
+                    controls,
+                    statevector,
+                    parallel,
+                );
+            }
+        }
+        Instruction::Initialize { qubits, amplitudes } => {
+            for instruction in state_preparation(amplitudes).instructions() {
+                apply_instruction(
+                    &instruction.map_qubits(|qubit| qubits[qubit]),
+                    controls,
+                    statevector,
+                    parallel,
+                );
+            }
+        }
+        Instruction::Measure { .. } | Instruction::Reset(..) | Instruction::If { .. } => {
+            panic!("Measurements are only supported by StateVectorBackend::run_shots")
+        }
+        _ => statevector.apply_single_qubit_gate(
+            &single_qubit_gate_matrix(instruction),
+            controls,
+            instruction.qubits()[0],
+            parallel,
+        ),
     }
+}
 
-    *statevector = gate_matrix.dot_product(statevector);
+/// Executes the instruction in a single shot, reading and writing classical bits.
+fn execute<F: Real, R: Rng>(
+    instruction: &Instruction,
+    clbits: &mut [u8],
+    statevector: &mut AmplitudeBuffer<F>,
+    rng: &mut R,
+    parallel: bool,
+) {
+    match instruction {
+        Instruction::Measure { qubit, clbit } => {
+            clbits[*clbit] = statevector.measure(*qubit, rng.gen());
+        }
+        Instruction::Reset(qubit) => {
+            if statevector.measure(*qubit, rng.gen()) == 1 {
+                apply_instruction(&Instruction::PauliX(*qubit), 0, statevector, parallel);
+            }
+        }
+        Instruction::If {
+            register,
+            value,
+            instruction,
+        } => {
+            if condition_holds(register, *value, clbits) {
+                execute(instruction, clbits, statevector, rng, parallel);
+            }
+        }
+        _ => apply_instruction(instruction, 0, statevector, parallel),
+    }
 }
 
-impl Backend for StateVectorBackend {
-    type Output = Matrix<Complex>;
+/// Computes unitary matrix of the circuit, column `j` of which is the state the
+/// circuit turns basis state `|j>` into.
+pub(crate) fn unitary(circuit: &QuantumCircuit) -> Matrix<Complex> {
+    let size = 1 << circuit.qubits();
+    let mut unitary = Matrix::new_with_default_elems(size, size);
 
-    fn execute(circuit: QuantumCircuit) -> Matrix<Complex> {
-        let mut statevector =
-            Matrix::new_with_default_elems(2usize.pow(circuit.qubits() as u32), 1);
-        statevector.set(0, 0, Complex::one());
+    for col in 0..size {
+        let mut basis_state = vec![Complex::zero(); size];
+        basis_state[col] = Complex::one();
+        let mut statevector = AmplitudeBuffer::<f64>::from_amplitudes(&basis_state);
 
         for instruction in circuit.instructions() {
-            match instruction {
-                &Instruction::Hadamard(qubit)
-                | &Instruction::PauliX(qubit)
-                | &Instruction::PauliY(qubit)
-                | &Instruction::PauliZ(qubit)
-                | &Instruction::Phase { qubit, .. }
-                | &Instruction::PhaseDagger { qubit, .. }
-                | &Instruction::T(qubit)
-                | &Instruction::TDagger(qubit)
-                | &Instruction::S(qubit)
-                | &Instruction::SDagger(qubit)
-                | &Instruction::Identity(qubit)
-                | &Instruction::RotationX { qubit, .. }
-                | &Instruction::RotationY { qubit, .. }
-                | &Instruction::RotationZ { qubit, .. } => {
-                    execute_single_qubit_gate(instruction, &circuit, qubit, &mut statevector)
+            apply_instruction(instruction, 0, &mut statevector, false);
+        }
+
+        for row in 0..size {
+            unitary.set(row, col, statevector.get(row));
+        }
+    }
+
+    unitary
+}
+
+impl StateVectorBackend {
+    /// Makes the backend execute simulations on its own pool of `threads` threads,
+    /// instead of the global thread pool.
+    ///
+    /// ```
+    /// use quantum_crab::statevector_backend::StateVectorBackend;
+    ///
+    /// let backend = StateVectorBackend::default().with_threads(2).unwrap();
+    /// assert_eq!(backend.thread_pool, backend.clone().thread_pool);
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn with_threads(self, threads: usize) -> Result<StateVectorBackend, BackendError> {
+        Ok(StateVectorBackend {
+            thread_pool: Some(ThreadPool::new(threads)?),
+            ..self
+        })
+    }
+
+    /// Executes the circuit, returning probabilities of all basis states. Like
+    /// [`Backend::run`], measurements, resets and conditioned instructions are not
+    /// supported.
+    pub fn probabilities(&self, circuit: &QuantumCircuit) -> Result<Vec<f64>, BackendError> {
+        self.check_settings()?;
+        check_instructions(circuit, |instruction| !instruction.is_dynamic())?;
+
+        match self.precision {
+            Precision::Single => {
+                let mut statevector = AmplitudeBuffer::<f32>::new(0);
+                self.simulate(circuit, &RunOptions::default(), &mut statevector)?;
+                Ok(self.install(circuit.qubits(), |parallel| {
+                    statevector.probabilities(parallel)
+                }))
+            }
+            Precision::Double => {
+                let mut statevector = AmplitudeBuffer::<f64>::new(0);
+                self.simulate(circuit, &RunOptions::default(), &mut statevector)?;
+                Ok(self.install(circuit.qubits(), |parallel| {
+                    statevector.probabilities(parallel)
+                }))
+            }
+        }
+    }
+
+    /// Executes the circuit, returning expectation value of the product of Pauli Z
+    /// operators on the `qubits`. Like [`Backend::run`], measurements, resets and
+    /// conditioned instructions are not supported.
+    ///
+    /// ```
+    /// use quantum_crab::{
+    ///     quantum_circuit::QuantumCircuit,
+    ///     statevector_backend::StateVectorBackend,
+    /// };
+    ///
+    /// let mut circuit = QuantumCircuit::new(2);
+    /// circuit.h(0).cx(0, 1);
+    ///
+    /// let backend = StateVectorBackend::default();
+    /// assert!(backend.expectation_z(&circuit, &[0]).unwrap().abs() < 1e-12);
+    /// assert!((backend.expectation_z(&circuit, &[0, 1]).unwrap() - 1f64).abs() < 1e-12);
+    /// ```
+    #[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+    pub fn expectation_z(
+        &self,
+        circuit: &QuantumCircuit,
+        qubits: &[usize],
+    ) -> Result<f64, BackendError> {
+        let mask = qubits.iter().fold(0, |mask, qubit| {
+            assert!(
+                *qubit < circuit.qubits(),
+                "Invalid qubit {} in observable",
+                qubit
+            );
+            mask | 1 << qubit
+        });
+        let probabilities = self.probabilities(circuit)?;
+        let term = |(index, probability): (usize, &f64)| {
+            if (index & mask).count_ones() % 2 == 0 {
+                *probability
+            } else {
+                -probability
+            }
+        };
+
+        Ok(self.install(circuit.qubits(), |parallel| {
+            #[cfg(feature = "parallel")]
+            if parallel {
+                return probabilities.par_iter().enumerate().map(term).sum();
+            }
+
+            probabilities.iter().enumerate().map(term).sum()
+        }))
+    }
+
+    /// Executes the circuit `shots` times, returning how many times every value of
+    /// classical bits was obtained, written as a bitstring with the highest classical
+    /// bit first. Bits of different classical registers (see [`crate::register`]) are
+    /// separated by spaces, like `"01 1"` for the register `a` of one bit and the
+    /// register `b` of two bits, declared after it.
+    ///
+    /// Unlike [`Backend::run`], measurements, [`Instruction::Reset`] and
+    /// [`Instruction::If`] are supported. If the circuit only measures qubits at the
+    /// end, the state is simulated once and sampled, otherwise every shot is simulated
+    /// separately. Outcomes are reproducible if the options have a seed.
+    ///
+    /// ```
+    /// use quantum_crab::{
+    ///     backend::RunOptions,
+    ///     quantum_circuit::{Instruction, QuantumCircuit},
+    ///     statevector_backend::StateVectorBackend,
+    /// };
+    ///
+    /// // Teleportation of `RY(2pi/3)|0>` from qubit 0 to qubit 2.
+    /// let mut circuit = QuantumCircuit::with_clbits(3, 3);
+    /// circuit
+    ///     .ry(0, 2f64 * std::f64::consts::PI / 3f64)
+    ///     .h(1)
+    ///     .cx(1, 2)
+    ///     .cx(0, 1)
+    ///     .h(0)
+    ///     .measure(0..2, 0..2)
+    ///     .c_if(1, 1, Instruction::PauliX(2))
+    ///     .c_if(0, 1, Instruction::PauliZ(2))
+    ///     .measure(2, 2);
+    ///
+    /// let options = RunOptions {
+    ///     seed: Some(1),
+    ///     ..RunOptions::default()
+    /// };
+    /// let counts = StateVectorBackend::default()
+    ///     .run_shots(&circuit, 1000, &options)
+    ///     .unwrap();
+    ///
+    /// let ones: usize = counts
+    ///     .iter()
+    ///     .filter(|(bits, _)| bits.starts_with('1'))
+    ///     .map(|(_, count)| count)
+    ///     .sum();
+    /// assert!(ones > 700 && ones < 800);
+    /// ```
+    pub fn run_shots(
+        &self,
+        circuit: &QuantumCircuit,
+        shots: usize,
+        options: &RunOptions,
+    ) -> Result<BTreeMap<String, usize>, BackendError> {
+        self.check_settings()?;
+
+        match self.precision {
+            Precision::Single => {
+                self.run_shots_in(circuit, shots, options, &mut AmplitudeBuffer::<f32>::new(0))
+            }
+            Precision::Double => {
+                self.run_shots_in(circuit, shots, options, &mut AmplitudeBuffer::<f64>::new(0))
+            }
+        }
+    }
+
+    fn run_shots_in<F: Real>(
+        &self,
+        circuit: &QuantumCircuit,
+        shots: usize,
+        options: &RunOptions,
+        statevector: &mut AmplitudeBuffer<F>,
+    ) -> Result<BTreeMap<String, usize>, BackendError> {
+        let circuit = circuit.flatten();
+        let mut rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let bitstring = |clbits: &[u8]| circuit.format_clbits(clbits);
+
+        // Measurements at the end of the circuit don't affect other instructions.
+        let end = circuit
+            .instructions()
+            .iter()
+            .rposition(|instruction| {
+                !matches!(
+                    instruction,
+                    Instruction::Measure { .. } | Instruction::Barrier(..)
+                )
+            })
+            .map_or(0, |index| index + 1);
+        let (body, measurements) = circuit.instructions().split_at(end);
+
+        let mut counts = BTreeMap::new();
+        if !body.iter().any(Instruction::is_dynamic) {
+            let mut gates = QuantumCircuit::new(circuit.qubits());
+            for instruction in body {
+                gates.add(instruction.clone());
+            }
+            self.simulate(&gates, options, statevector)?;
+
+            let state = StateVector::new(statevector.to_amplitudes());
+            for (qubits, count) in state.sample(shots, &mut rng) {
+                let mut clbits = vec![0; circuit.clbits()];
+                for instruction in measurements {
+                    if let Instruction::Measure { qubit, clbit } = instruction {
+                        clbits[*clbit] = qubits.as_bytes()[circuit.qubits() - 1 - qubit] - b'0';
+                    }
+                }
+
+                *counts.entry(bitstring(&clbits)).or_insert(0) += count;
+            }
+
+            return Ok(counts);
+        }
+
+        for _ in 0..shots {
+            self.prepare(&circuit, options, statevector)?;
+            let mut clbits = vec![0; circuit.clbits()];
+
+            self.install(circuit.qubits(), |parallel| {
+                for instruction in circuit.instructions() {
+                    options.check_cancelled()?;
+                    execute(instruction, &mut clbits, statevector, &mut rng, parallel);
+                }
+
+                Ok(())
+            })?;
+
+            *counts.entry(bitstring(&clbits)).or_insert(0) += 1;
+        }
+
+        Ok(counts)
+    }
+
+    /// Sets the buffer to the initial state of the options.
+    fn prepare<F: Real>(
+        &self,
+        circuit: &QuantumCircuit,
+        options: &RunOptions,
+        statevector: &mut AmplitudeBuffer<F>,
+    ) -> Result<(), BackendError> {
+        match options.initial_state(circuit.qubits())? {
+            CheckedInitialState::Basis(bits) => {
+                let index = bits
+                    .iter()
+                    .rev()
+                    .fold(0, |index, bit| index << 1 | *bit as usize);
+                statevector.reset(circuit.qubits(), index);
+            }
+            CheckedInitialState::StateVector(state) => {
+                *statevector = AmplitudeBuffer::from_amplitudes(state.amplitudes());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Executes the circuit in the buffer, which is set to the initial state first,
+    /// checking for cancellation before every gate.
+    fn simulate<F: Real>(
+        &self,
+        circuit: &QuantumCircuit,
+        options: &RunOptions,
+        statevector: &mut AmplitudeBuffer<F>,
+    ) -> Result<(), BackendError> {
+        self.prepare(circuit, options, statevector)?;
+
+        if self.max_fused_qubits == 0 {
+            return self.install(circuit.qubits(), |parallel| {
+                for instruction in circuit.instructions() {
+                    options.check_cancelled()?;
+                    apply_instruction(instruction, 0, statevector, parallel);
                 }
-                _ => todo!(),
+
+                Ok(())
+            });
+        }
+
+        let gates = fuse(circuit, self.max_fused_qubits);
+        self.install(circuit.qubits(), |parallel| {
+            for gate in &gates {
+                options.check_cancelled()?;
+
+                match gate {
+                    FusedGate::Single { qubit, matrix } => {
+                        statevector.apply_single_qubit_gate(matrix, 0, *qubit, parallel)
+                    }
+                    FusedGate::Two {
+                        qubit0,
+                        qubit1,
+                        matrix,
+                    } => statevector.apply_two_qubit_gate(matrix, *qubit0, *qubit1, parallel),
+                    FusedGate::Instruction(instruction) => {
+                        apply_instruction(instruction, 0, statevector, parallel)
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Runs the closure, which is told whether it should use multiple threads, in the
+    /// configured thread pool.
+    #[cfg(feature = "parallel")]
+    fn install<T: Send>(&self, qubits: usize, f: impl FnOnce(bool) -> T + Send) -> T {
+        if qubits < self.parallel_threshold {
+            return f(false);
+        }
+
+        match &self.thread_pool {
+            Some(pool) => pool.0.install(|| f(true)),
+            None => f(true),
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn install<T>(&self, _qubits: usize, f: impl FnOnce(bool) -> T) -> T {
+        f(false)
+    }
+}
+
+impl Backend for StateVectorBackend {
+    type Output = StateVector;
+
+    /// Executes the circuit, returning the state vector. Measurements, resets and
+    /// conditioned instructions are not supported (see
+    /// [`StateVectorBackend::run_shots`]).
+    fn run(
+        &self,
+        circuit: &QuantumCircuit,
+        options: &RunOptions,
+    ) -> Result<StateVector, BackendError> {
+        match self.precision {
+            Precision::Single => self.run_in(circuit, options, &mut AmplitudeBuffer::<f32>::new(0)),
+            Precision::Double => self.run_in(circuit, options, &mut AmplitudeBuffer::<f64>::new(0)),
+        }
+    }
+
+    /// Executes the circuits, reusing a buffer of amplitudes in every thread.
+    fn run_batch(
+        &self,
+        circuits: &[QuantumCircuit],
+        options: &RunOptions,
+    ) -> Vec<Result<StateVector, BackendError>> {
+        match self.precision {
+            Precision::Single => self.run_batch_in::<f32>(circuits, options),
+            Precision::Double => self.run_batch_in::<f64>(circuits, options),
+        }
+    }
+}
+
+impl StateVectorBackend {
+    /// Checks the settings, as they can be changed after the backend is constructed.
+    fn check_settings(&self) -> Result<(), BackendError> {
+        if self.max_fused_qubits > 2 {
+            return Err(BackendError::InvalidSettings(format!(
+                "Gates can be fused on at most 2 qubits, not {}",
+                self.max_fused_qubits
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Executes the circuit in the buffer, returning the state vector.
+    fn run_in<F: Real>(
+        &self,
+        circuit: &QuantumCircuit,
+        options: &RunOptions,
+        statevector: &mut AmplitudeBuffer<F>,
+    ) -> Result<StateVector, BackendError> {
+        self.check_settings()?;
+        check_instructions(circuit, |instruction| !instruction.is_dynamic())?;
+
+        self.simulate(circuit, options, statevector)?;
+        Ok(StateVector::new(statevector.to_amplitudes()))
+    }
+
+    fn run_batch_in<F: Real>(
+        &self,
+        circuits: &[QuantumCircuit],
+        options: &RunOptions,
+    ) -> Vec<Result<StateVector, BackendError>> {
+        let run = |statevector: &mut AmplitudeBuffer<F>, (index, circuit)| {
+            self.run_in(circuit, &batch_options(options, index), statevector)
+        };
+
+        #[cfg(feature = "parallel")]
+        return circuits
+            .par_iter()
+            .enumerate()
+            .map_init(|| AmplitudeBuffer::new(0), run)
+            .collect();
+
+        #[cfg(not(feature = "parallel"))]
+        {
+            let mut statevector = AmplitudeBuffer::new(0);
+            circuits
+                .iter()
+                .enumerate()
+                .map(|item| run(&mut statevector, item))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend::{Backend, BackendError, InitialState, RunOptions},
+        classical_register::ClassicalRegister,
+        complex::Complex,
+        quantum_circuit::{Instruction, QuantumCircuit},
+        statevector::StateVector,
+        statevector_backend::{unitary, Precision, StateVectorBackend},
+    };
+    use float_cmp::approx_eq;
+    use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+    fn assert_amplitudes(circuit: QuantumCircuit, expected: &[Complex]) {
+        let statevector = StateVectorBackend::execute(circuit);
+
+        for (i, amplitude) in expected.iter().enumerate() {
+            let actual = statevector.amplitudes()[i];
+            assert!(
+                approx_eq!(f64, actual.real, amplitude.real, epsilon = 1e-12)
+                    && approx_eq!(f64, actual.imag, amplitude.imag, epsilon = 1e-12),
+                "amplitude {}: {} != {}",
+                i,
+                actual,
+                amplitude
+            );
+        }
+    }
+
+    #[test]
+    fn applies_phase_and_rotation_gates() {
+        // S is the square root of Z, so H S S H turns |0> into |1>.
+        let mut circuit = QuantumCircuit::new(1);
+        circuit.h(0).s(0).s(0).h(0);
+        assert_amplitudes(circuit, &[0.into(), 1.into()]);
+
+        let mut circuit = QuantumCircuit::new(1);
+        circuit.h(0).s(0).sdg(0);
+        assert_amplitudes(circuit, &[FRAC_1_SQRT_2.into(), FRAC_1_SQRT_2.into()]);
+
+        // RY rotates around the Y axis, keeping amplitudes real.
+        let mut circuit = QuantumCircuit::new(1);
+        circuit.ry(0, PI / 2f64);
+        assert_amplitudes(circuit, &[FRAC_1_SQRT_2.into(), FRAC_1_SQRT_2.into()]);
+    }
+
+    #[test]
+    fn executes_single_qubit_gates() {
+        let mut circuit = QuantumCircuit::new(2);
+        circuit.x(0).s(0).h(1).ry(1, PI / 2f64);
+        // Hadamard and RY(pi/2) turn |0> into |1>.
+        assert_amplitudes(circuit, &[0.into(), 0.into(), 0.into(), Complex::new(0, 1)]);
+
+        let mut circuit = QuantumCircuit::new(1);
+        circuit.h(0).t(0).t(0).sdg(0);
+        assert_amplitudes(circuit, &[FRAC_1_SQRT_2.into(), FRAC_1_SQRT_2.into()]);
+    }
+
+    #[test]
+    fn executes_multi_qubit_gates() {
+        let mut circuit = QuantumCircuit::new(3);
+        circuit.h(0).cx(0, 1).swap(1, 2).x(1).ccx(1, 2, 0);
+        // (|000> + |011>) / sqrt(2) --> (|000> + |101>) / sqrt(2) --> (|010> + |111>) / sqrt(2)
+        // --> (|010> + |110>) / sqrt(2), where qubit 0 is the rightmost one.
+        assert_amplitudes(
+            circuit,
+            &[
+                0.into(),
+                0.into(),
+                FRAC_1_SQRT_2.into(),
+                0.into(),
+                0.into(),
+                0.into(),
+                FRAC_1_SQRT_2.into(),
+                0.into(),
+            ],
+        );
+
+        let mut inner = QuantumCircuit::new(2);
+        inner.h(0).cx(0, 1);
+        let mut circuit = QuantumCircuit::new(2);
+        circuit
+            .x(1)
+            .cu(Instruction::Hadamard(0), 1, 0)
+            .custom("bell", inner, [1, 0]);
+        // |10> --> (|10> + |11>) / sqrt(2) --> (|0> - |1>) (|0> + |1>) / 2
+        assert_amplitudes(
+            circuit,
+            &[0.5.into(), 0.5.into(), (-0.5).into(), (-0.5).into()],
+        );
+    }
+
+    #[test]
+    fn computes_probabilities() {
+        let mut circuit = QuantumCircuit::with_clbits(2, 1);
+        circuit.h(0).ry(1, PI / 3f64);
+
+        let backend = StateVectorBackend::default();
+        let probabilities = backend.probabilities(&circuit).unwrap();
+        for (probability, expected) in probabilities.iter().zip([0.375, 0.375, 0.125, 0.125]) {
+            assert!(approx_eq!(f64, *probability, expected, epsilon = 1e-12));
+        }
+
+        assert!(approx_eq!(
+            f64,
+            backend.expectation_z(&circuit, &[1]).unwrap(),
+            0.5,
+            epsilon = 1e-12
+        ));
+
+        circuit.measure(0, 0);
+        assert_eq!(
+            backend.probabilities(&circuit),
+            Err(BackendError::UnsupportedInstructions(vec![
+                Instruction::Measure { qubit: 0, clbit: 0 }
+            ]))
+        );
+        assert!(backend.expectation_z(&circuit, &[1]).is_err());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn matches_single_threaded_execution() {
+        let mut circuit = QuantumCircuit::new(6);
+        circuit
+            .h(0)
+            .cx(0, 5)
+            .rx(3, 0.4)
+            .ccx(5, 3, 1)
+            .cu(
+                Instruction::RotationY {
+                    qubit: 0,
+                    phase: 1.3,
+                },
+                1,
+                4,
+            )
+            .swap(2, 5)
+            .t(2);
+
+        let sequential = StateVectorBackend {
+            parallel_threshold: usize::MAX,
+            ..StateVectorBackend::default()
+        };
+        let parallel = StateVectorBackend {
+            parallel_threshold: 0,
+            ..StateVectorBackend::default().with_threads(3).unwrap()
+        };
+        assert_eq!(parallel.clone(), parallel);
+        assert_ne!(parallel, parallel.clone().with_threads(3).unwrap());
+
+        assert_eq!(
+            sequential.run(&circuit, &RunOptions::default()).unwrap(),
+            parallel.run(&circuit, &RunOptions::default()).unwrap()
+        );
+        assert_eq!(
+            sequential.expectation_z(&circuit, &[2, 4]).unwrap(),
+            parallel.expectation_z(&circuit, &[2, 4]).unwrap()
+        );
+    }
+
+    /// Quantum Fourier transform of a state prepared by rotations.
+    fn fourier_circuit(qubits: usize) -> QuantumCircuit {
+        let mut circuit = QuantumCircuit::new(qubits);
+        for qubit in 0..qubits {
+            circuit.ry(qubit, 0.3 * (qubit + 1) as f64).rz(qubit, 0.7);
+        }
+
+        for target in (0..qubits).rev() {
+            circuit.h(target);
+            for control in (0..target).rev() {
+                let phase = PI / (1 << (target - control)) as f64;
+                circuit.cu(Instruction::Phase { qubit: 0, phase }, control, target);
             }
         }
+        for qubit in 0..qubits / 2 {
+            circuit.swap(qubit, qubits - qubit - 1);
+        }
+
+        circuit
+    }
+
+    #[test]
+    fn quantifies_single_precision_error() {
+        // Fusion is disabled, so that gates are rounded the same way in both cases.
+        let double = StateVectorBackend {
+            max_fused_qubits: 0,
+            ..StateVectorBackend::default()
+        };
+        let single = StateVectorBackend {
+            precision: Precision::Single,
+            ..double.clone()
+        };
+
+        let mut ghz = QuantumCircuit::new(16);
+        ghz.h(0);
+        for qubit in 1..16 {
+            ghz.cx(qubit - 1, qubit);
+        }
+
+        for circuit in [fourier_circuit(10), ghz] {
+            let (expected, actual) = (
+                double.run(&circuit, &RunOptions::default()).unwrap(),
+                single.run(&circuit, &RunOptions::default()).unwrap(),
+            );
+
+            let (mut max_error, mut overlap) = (0f64, Complex::from(0));
+            for (expected, actual) in expected.amplitudes().iter().zip(actual.amplitudes()) {
+                max_error = max_error.max((*expected - *actual).norm());
+                overlap += expected.conjugate() * *actual;
+            }
+
+            // Rounding errors of single precision stay below 1e-7 per amplitude and
+            // reduce fidelity by less than 1e-6 on these circuits.
+            assert!(max_error > 0f64 && max_error < 1e-7);
+            assert!(1f64 - overlap.norm().powi(2) < 1e-6);
+        }
+    }
+
+    #[test]
+    fn matches_unfused_execution() {
+        let unfused = StateVectorBackend {
+            max_fused_qubits: 0,
+            ..StateVectorBackend::default()
+        };
+
+        let mut circuit = fourier_circuit(6);
+        circuit.ccx(0, 3, 5).t(5).cx(5, 2).y(4);
+
+        for max_fused_qubits in [1, 2] {
+            let fused = StateVectorBackend {
+                max_fused_qubits,
+                ..StateVectorBackend::default()
+            };
+            let (expected, actual) = (
+                unfused.run(&circuit, &RunOptions::default()).unwrap(),
+                fused.run(&circuit, &RunOptions::default()).unwrap(),
+            );
+
+            for (expected, actual) in expected.amplitudes().iter().zip(actual.amplitudes()) {
+                assert!((*expected - *actual).norm() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_fusion_settings() {
+        let backend = StateVectorBackend {
+            max_fused_qubits: 3,
+            ..StateVectorBackend::default()
+        };
+        let circuit = circuit!(3; h 0; ccx 0 1 2);
+        let invalid = |result| matches!(result, Err(BackendError::InvalidSettings(..)));
+
+        assert!(invalid(
+            backend.run(&circuit, &RunOptions::default()).map(|_| ())
+        ));
+        assert!(invalid(backend.probabilities(&circuit).map(|_| ())));
+        assert!(invalid(
+            backend
+                .run_shots(&circuit, 10, &RunOptions::default())
+                .map(|_| ())
+        ));
+    }
+
+    #[test]
+    fn rejects_measurements() {
+        let mut circuit = QuantumCircuit::with_clbits(2, 1);
+        circuit.h(0).measure(1, 0);
+
+        let result = StateVectorBackend::default().run(&circuit, &RunOptions::default());
+        assert_eq!(
+            result,
+            Err(BackendError::UnsupportedInstructions(vec![
+                Instruction::Measure { qubit: 1, clbit: 0 }
+            ]))
+        );
+    }
 
-        statevector
+    #[test]
+    fn starts_from_initial_states() {
+        let mut circuit = QuantumCircuit::new(3);
+        circuit.cx(0, 2);
+
+        let backend = StateVectorBackend::default();
+        let run = |initial_state| {
+            let options = RunOptions {
+                initial_state: Some(initial_state),
+                ..RunOptions::default()
+            };
+            backend.run(&circuit, &options)
+        };
+
+        let state = run(InitialState::Bitstring("011".to_owned())).unwrap();
+        assert_eq!(state.to_string(), "1.000|111>");
+        let state = run(InitialState::Register(ClassicalRegister::new(vec![
+            1, 0, 0,
+        ])))
+        .unwrap();
+        assert_eq!(state.to_string(), "1.000|101>");
+
+        let mut amplitudes = vec![Complex::from(0); 8];
+        amplitudes[0b000] = Complex::from(FRAC_1_SQRT_2);
+        amplitudes[0b011] = Complex::new(0f64, FRAC_1_SQRT_2);
+        let state = run(InitialState::StateVector(StateVector::new(amplitudes))).unwrap();
+        assert_eq!(state.to_string(), "0.707|000> + 0.707i|111>");
+
+        for invalid in [
+            InitialState::Bitstring("01".to_owned()),
+            InitialState::Bitstring("012".to_owned()),
+            InitialState::Register(ClassicalRegister::zeroed(4)),
+            InitialState::StateVector(StateVector::new(vec![Complex::from(1); 8])),
+        ] {
+            assert!(matches!(
+                run(invalid),
+                Err(BackendError::InvalidInitialState(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn initializes_qubits() {
+        let amplitudes = vec![
+            Complex::from(0.6),
+            Complex::from(0),
+            Complex::from(0),
+            Complex::new(0f64, -0.8),
+        ];
+
+        let mut circuit = QuantumCircuit::new(3);
+        circuit.x(1).add(Instruction::Initialize {
+            qubits: vec![0, 2],
+            amplitudes,
+        });
+
+        assert_eq!(
+            StateVectorBackend::execute(circuit).to_string(),
+            "0.600|010> - 0.800i|111>"
+        );
+    }
+
+    #[test]
+    fn executes_shots() {
+        let options = RunOptions {
+            seed: Some(3),
+            ..RunOptions::default()
+        };
+
+        let mut bell = QuantumCircuit::with_clbits(2, 3);
+        bell.h(0).cx(0, 1).measure(0..2, [2, 0]);
+        let counts = StateVectorBackend::default()
+            .run_shots(&bell, 500, &options)
+            .unwrap();
+        assert_eq!(counts.keys().collect::<Vec<_>>(), vec!["000", "101"]);
+        assert_eq!(counts.values().sum::<usize>(), 500);
+
+        // Qubit 1 copies the outcome of qubit 0, which is reset and reused.
+        let mut circuit = QuantumCircuit::with_clbits(2, 3);
+        circuit
+            .h(0)
+            .measure(0, 0)
+            .c_if(0, 1, Instruction::PauliX(1))
+            .reset(0)
+            .measure(0, 2)
+            .x(0)
+            .measure(1, 1)
+            .c_if(0..2, 3, Instruction::Reset(0));
+
+        for precision in [Precision::Single, Precision::Double] {
+            let backend = StateVectorBackend {
+                precision,
+                ..StateVectorBackend::default()
+            };
+            let counts = backend.run_shots(&circuit, 500, &options).unwrap();
+
+            assert_eq!(counts.keys().collect::<Vec<_>>(), vec!["000", "011"]);
+            assert!(counts["011"] > 200 && counts["011"] < 300);
+            assert_eq!(counts, backend.run_shots(&circuit, 500, &options).unwrap());
+        }
+
+        // Outcomes of classical registers are separated.
+        let mut circuit = QuantumCircuit::new(0);
+        let data = circuit.add_qreg("data", 2);
+        let low = circuit.add_creg("low", 1);
+        let high = circuit.add_creg("high", 2);
+        circuit
+            .x(&data)
+            .measure(data.at(0), &low)
+            .measure(&data, &high);
+        let counts = StateVectorBackend::default()
+            .run_shots(&circuit, 10, &options)
+            .unwrap();
+        assert_eq!(counts["11 1"], 10);
+
+        assert!(matches!(
+            StateVectorBackend::default().run(&circuit, &options),
+            Err(BackendError::UnsupportedInstructions(..))
+        ));
+    }
+
+    #[test]
+    fn executes_batches() {
+        let mut measured = QuantumCircuit::with_clbits(1, 1);
+        measured.measure(0, 0);
+
+        let circuits = [
+            fourier_circuit(5),
+            measured,
+            fourier_circuit(3),
+            fourier_circuit(4),
+        ];
+        for backend in [
+            StateVectorBackend::default(),
+            StateVectorBackend {
+                precision: Precision::Single,
+                ..StateVectorBackend::default()
+            },
+        ] {
+            let options = RunOptions::default();
+            let results = backend.run_batch(&circuits, &options);
+
+            assert_eq!(results.len(), circuits.len());
+            for (result, circuit) in results.into_iter().zip(&circuits) {
+                assert_eq!(result, backend.run(circuit, &options));
+            }
+        }
+    }
+
+    #[test]
+    fn computes_unitaries() {
+        let mut circuit = QuantumCircuit::new(2);
+        circuit.cx(1, 0);
+
+        let unitary = unitary(&circuit);
+        for (row, col) in [(0, 0), (1, 1), (3, 2), (2, 3)] {
+            assert_eq!(unitary.get(row, col), Complex::from(1));
+        }
     }
 }